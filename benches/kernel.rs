@@ -0,0 +1,140 @@
+use std::hint::black_box;
+use std::ops::{BitOr, Shl};
+use std::time::Duration;
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion, Throughput};
+
+#[macro_use]
+extern crate static_assertions;
+
+// The kernel is compiled into the benchmark straight from the source, as the program has no library to link against.
+// The modules' tests are left out of the benchmark, along with the imports only they use.
+#[allow(dead_code)]
+#[path = "../src/conway.rs"]
+mod conway;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/random.rs"]
+mod random;
+#[allow(dead_code)]
+#[path = "../src/row_major.rs"]
+mod row_major;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/simd.rs"]
+mod simd;
+#[cfg(feature = "large-cell-blocks")]
+#[allow(dead_code, unused_imports)]
+#[path = "../src/u256.rs"]
+mod u256;
+
+use conway::{BlockNeighborhood, BlockRule, CellBlock, Conway, CELLS_PER_BLOCK, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK};
+use random::Random;
+use simd::Kernel;
+
+// The fractions of cells alive on the boards stepped.
+const DENSITIES: [f64; 3] = [0.05, 0.25, 0.5];
+const SEED: u64 = 0x5EED;
+
+fn num_threads() -> usize {
+  std::thread::available_parallelism().unwrap().get()
+}
+
+fn record_busy_time(_thread: usize, _duration: Duration) {}
+
+// A board of random cells, each alive with the given probability, the same for the same seed. Boards are either blocks
+// or words of cells laid out in rows, which are always 64 bits whatever the size of a block.
+fn soup<T: Copy + From<bool> + BitOr<Output = T> + Shl<usize, Output = T>>(words: usize, density: f64, seed: u64) -> Vec<T> {
+  let mut random = Random::seeded(seed);
+  (0..words).map(|_| (0..size_of::<T>() * 8).fold(T::from(false), |word, cell| word | (T::from(random.fraction() < density) << cell))).collect()
+}
+
+// Benchmarks are named for the size of the blocks they were built with, so that running them again with
+// --features wide-cell-blocks or large-cell-blocks compares the sizes side by side rather than against each other's
+// earlier runs.
+fn group_name(name: &str) -> String {
+  format!("{} {}x{}", name, CELL_BLOCK_WIDTH, CELL_BLOCK_HEIGHT)
+}
+
+fn new_value_for_block(c: &mut Criterion) {
+  const WIDTH: usize = 16;
+  let mut group = c.benchmark_group(group_name("new_value_for_block"));
+  for density in DENSITIES {
+    let board = soup(WIDTH * WIDTH, density, SEED);
+    // A block in the middle of the board, with all eight of its neighbors.
+    let block_index = WIDTH * WIDTH / 2 + WIDTH / 2;
+    group.bench_with_input(BenchmarkId::from_parameter(density), &board, |b, board| {
+      b.iter(|| conway::new_value_for_block::<WIDTH, _>(black_box(board), black_box(block_index), &Conway))
+    });
+  }
+  group.finish();
+}
+
+fn compute_next_board_state(c: &mut Criterion) {
+  let mut group = c.benchmark_group(group_name("compute_next_board_state"));
+  group.sample_size(20);
+  step_board::<256>(&mut group);
+  step_board::<1024>(&mut group);
+  step_board::<2048>(&mut group);
+  group.finish();
+}
+
+fn step_board<const WIDTH: usize>(group: &mut BenchmarkGroup<WallTime>) {
+  group.throughput(Throughput::Elements((WIDTH * WIDTH) as u64 * CELLS_PER_BLOCK));
+  for density in DENSITIES {
+    let source = soup(WIDTH * WIDTH, density, SEED);
+    let mut destination = vec![EMPTY_BLOCK; WIDTH * WIDTH];
+    group.bench_with_input(BenchmarkId::new(format!("{0}x{0}", WIDTH), density), &source, |b, source| {
+      b.iter(|| conway::compute_next_board_state::<WIDTH, _>(source, &mut destination, &Conway))
+    });
+  }
+  // The same number of cells laid out in rows rather than blocks, for comparing the two layouts.
+  let words = WIDTH * WIDTH * CELLS_PER_BLOCK as usize / row_major::WORD_CELLS;
+  for density in DENSITIES {
+    let source = soup::<u64>(words, density, SEED);
+    let mut destination = vec![0; words];
+    group.bench_with_input(BenchmarkId::new(format!("{0}x{0} rows", WIDTH), density), &source, |b, source| {
+      b.iter(|| row_major::compute_next_board_state(source, &mut destination, WIDTH * conway::CELL_BLOCK_WIDTH as usize, &Conway))
+    });
+  }
+}
+
+// Conway's rule looked up in the tables one block at a time, the way every other rule is stepped.
+struct Tables;
+
+impl BlockRule for Tables {
+  fn new_value_for_neighborhood(&self, neighborhood: &BlockNeighborhood) -> CellBlock {
+    conway::new_value_for_neighborhood(neighborhood, &Conway)
+  }
+}
+
+// Conway's rule stepped a row at a time with each of the kernels the processor supports, against the tables.
+fn step_conway_row(c: &mut Criterion) {
+  const WIDTH: usize = 4096;
+  let mut group = c.benchmark_group(group_name("step_conway_row"));
+  group.throughput(Throughput::Elements(WIDTH as u64 * CELLS_PER_BLOCK));
+  let board = soup(3 * WIDTH, 0.25, SEED);
+  let rows = [&board[..WIDTH], &board[WIDTH..2 * WIDTH], &board[2 * WIDTH..]];
+  let mut destination = vec![EMPTY_BLOCK; WIDTH];
+  group.bench_function("tables", |b| {
+    b.iter(|| Tables.new_values_for_row(black_box(rows), &mut destination))
+  });
+  // Blocks wider than the vector kernels' words are all stepped with the portable kernel, so it is only benchmarked once.
+  for kernel in Kernel::ALL.into_iter().filter(|kernel| kernel.is_supported() && kernel.for_blocks() == *kernel) {
+    group.bench_function(kernel.name(), |b| b.iter(|| simd::step_conway_row(kernel, black_box(rows), &mut destination)));
+  }
+  group.finish();
+}
+
+fn clear_board(c: &mut Criterion) {
+  let mut group = c.benchmark_group(group_name("clear_board"));
+  group.sample_size(20);
+  for width in [256, 1024, 2048, 4096] {
+    // Clearing takes as long whatever the board holds.
+    let mut board = vec![CellBlock::MAX; width * width];
+    group.throughput(Throughput::Bytes((width * width * size_of::<CellBlock>()) as u64));
+    group.bench_function(BenchmarkId::from_parameter(format!("{0}x{0}", width)), |b| b.iter(|| conway::clear_board(&mut board)));
+  }
+  group.finish();
+}
+
+criterion_group!(benches, new_value_for_block, compute_next_board_state, step_conway_row, clear_board);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use std::time::Instant;
+use crate::conway;
+use crate::random::Random;
+use crate::selection::Rectangle;
+use crate::sparse::{TILE_HEIGHT_CELLS, TILE_WIDTH_CELLS};
+use crate::universe::{Buffers, Universe};
+
+// The soup is a square of random cells at the origin, which is also the top-left corner of the dense board.
+pub const SOUP_REGION: Rectangle = Rectangle { x: 0, y: 0, width: 1024, height: 1024 };
+pub const SOUP_DENSITY: f64 = 0.5;
+pub const SOUP_SEED: u64 = 0x5EED;
+pub const GENERATIONS: u64 = 100;
+
+// Sets cells in the given rectangle to state 1 at random, each with the given probability. The same seed always gives
+// the same soup, so runs can be compared with each other.
+pub fn seed_soup(universe: &mut Universe, region: &Rectangle, density: f64, seed: u64) {
+  for (x, y) in soup_cells(region, density, seed) {
+    universe.set_cell(x, y, 1);
+  }
+}
+
+// The cells of the soup, in the order they are picked.
+pub fn soup_cells(region: &Rectangle, density: f64, seed: u64) -> impl Iterator<Item = (i64, i64)> {
+  let mut random = Random::seeded(seed);
+  let region = *region;
+  (region.y..region.y + region.height)
+      .flat_map(move |y| (region.x..region.x + region.width).map(move |x| (x, y)))
+      .filter(move |_| random.fraction() < density)
+}
+
+// Steps the universe the given number of generations as fast as it goes, and reports how fast that was along with how
+// much of the time each thread spent working rather than waiting for the others.
+pub fn run(universe: &mut Universe, generations: u64) {
+  crate::take_busy_times();
+  let mut cells_stepped = 0u64;
+  let start = Instant::now();
+  for _ in 0..generations {
+    universe.step();
+    // Every cell of the dense board is stepped, while sparse boards only step the tiles near live cells.
+    cells_stepped += universe.tile_count().map_or(conway::BOARD_TOTAL_CELLS, |tiles| tiles as u64 * (TILE_WIDTH_CELLS * TILE_HEIGHT_CELLS) as u64);
+  }
+  let seconds = start.elapsed().as_secs_f64();
+  let busy_times = crate::take_busy_times();
+
+  println!("Stepped {} generations in {:.3} seconds.", generations, seconds);
+  if let Buffers::Dense(board) = &universe.buffers {
+    println!("Dense board laid out in {}, and Conway's Game of Life steps with the {} kernel.", board.layout(), board.kernel().name());
+  }
+  println!("{:.2} generations per second, {:.3e} cells per second.", generations as f64 / seconds, cells_stepped as f64 / seconds);
+  if let Some(population) = universe.population() {
+    println!("Population {} after generation {}.", population, universe.generation);
+  }
+  for (thread, busy_time) in busy_times.iter().enumerate() {
+    println!("Thread {} busy {:.1}% of the time.", thread, busy_time.as_secs_f64() / seconds * 100.0);
+  }
+}
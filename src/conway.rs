@@ -1,322 +1,443 @@
-use std::ops::{Shl, Shr};
+use std::time::Instant;
+use crate::simd;
 
-// Cells are stored as individual bits inside a block of cells.
+// Cells are stored as individual bits inside a block of cells. Wider blocks have fewer cells on their edges for the
+// number of cells they hold, so less of the work of stepping a block goes into gathering its neighbors.
+#[cfg(not(any(feature = "wide-cell-blocks", feature = "large-cell-blocks")))]
 pub type CellBlock = u64;
+#[cfg(feature = "wide-cell-blocks")]
+pub type CellBlock = u128;
+#[cfg(feature = "large-cell-blocks")]
+pub type CellBlock = crate::u256::U256;
+#[cfg(all(feature = "wide-cell-blocks", feature = "large-cell-blocks"))]
+compile_error!("Blocks can be wide or large, but not both");
 pub const CELLS_PER_BLOCK: u64 = CellBlock::BITS as u64;
-pub const CELL_BLOCK_WIDTH: u64 = CELLS_PER_BLOCK.isqrt();
+// Blocks are as close to square as a power of two cells across allows, 8 x 8 for 64 cells, 16 x 8 for 128 and 16 x 16
+// for 256.
+pub const CELL_BLOCK_WIDTH: u64 = 1 << CELLS_PER_BLOCK.ilog2().div_ceil(2);
 pub const CELL_BLOCK_HEIGHT: u64 = CELLS_PER_BLOCK / CELL_BLOCK_WIDTH;
 
+// The cells of a block in its first and last columns and rows.
+pub const FIRST_COLUMN: CellBlock = cells_in(1, ALL_ROWS);
+pub const LAST_COLUMN: CellBlock = cells_in(1 << (CELL_BLOCK_WIDTH - 1), ALL_ROWS);
+pub const FIRST_ROW: CellBlock = cells_in(ALL_COLUMNS, 1);
+pub const LAST_ROW: CellBlock = cells_in(ALL_COLUMNS, 1 << (CELL_BLOCK_HEIGHT - 1));
+// A block with no cells alive and one with only its first cell alive, which integer literals cannot stand in for when
+// blocks are wider than the built-in integers.
+pub const EMPTY_BLOCK: CellBlock = cells_in(0, 0);
+pub const FIRST_CELL: CellBlock = cells_in(1, 1);
+
+// Every column and every row of a block, as the bits cells_in takes.
+pub const ALL_COLUMNS: u64 = (1 << CELL_BLOCK_WIDTH) - 1;
+pub const ALL_ROWS: u64 = (1 << CELL_BLOCK_HEIGHT) - 1;
+
+// The block of cells in the given columns of the given rows, each given as bits with the first in the lowest bit. The
+// block is put together from its bytes, which works in constants whatever the size of a block, as every row of a block
+// is a whole number of bytes.
+pub const fn cells_in(columns: u64, rows: u64) -> CellBlock {
+  const ROW_BYTES: usize = CELL_BLOCK_WIDTH as usize / 8;
+  let mut bytes = [0; size_of::<CellBlock>()];
+  let mut row = 0;
+  while row < CELL_BLOCK_HEIGHT as usize {
+    let mut byte = 0;
+    while byte < ROW_BYTES && (rows >> row) & 1 == 1 {
+      bytes[row * ROW_BYTES + byte] = (columns >> (byte * 8)) as u8;
+      byte += 1;
+    }
+    row += 1;
+  }
+  CellBlock::from_le_bytes(bytes)
+}
+
+// The first 64 cells of a block, with the first cell in the lowest bit, which is all of them for 8 x 8 blocks.
+pub fn first_cells(block: CellBlock) -> u64 {
+  u64::from_le_bytes(block.to_le_bytes()[..size_of::<u64>()].try_into().unwrap())
+}
+
+const_assert!(CELL_BLOCK_WIDTH.is_multiple_of(8));
 const_assert!(size_of::<usize>() >= size_of::<u64>());
 
 
-pub const BOARD_WIDTH_BLOCKS: usize = 23170;
-pub const BOARD_HEIGHT_BLOCKS: usize = 23170;
+// The board is the same number of cells across and down whatever the size of its blocks.
+const BOARD_SIZE_CELLS: usize = 185360;
+pub const BOARD_WIDTH_BLOCKS: usize = BOARD_SIZE_CELLS / CELL_BLOCK_WIDTH as usize;
+pub const BOARD_HEIGHT_BLOCKS: usize = BOARD_SIZE_CELLS / CELL_BLOCK_HEIGHT as usize;
 pub const BOARD_TOTAL_BLOCKS: usize = BOARD_HEIGHT_BLOCKS * BOARD_WIDTH_BLOCKS;
 
 pub const BOARD_WIDTH_CELLS: u64 = BOARD_WIDTH_BLOCKS as u64 * CELL_BLOCK_WIDTH;
 pub const BOARD_HEIGHT_CELLS: u64 = BOARD_HEIGHT_BLOCKS as u64 * CELL_BLOCK_HEIGHT;
 pub const BOARD_TOTAL_CELLS: u64 = BOARD_TOTAL_BLOCKS as u64 * CELLS_PER_BLOCK;
-pub const BOARD_TOTAL_BYTES: u64 = BOARD_TOTAL_CELLS * size_of::<CellBlock>() as u64; // roughly 4 GB per buffer
 
 pub type Board = [CellBlock; BOARD_TOTAL_BLOCKS];
 
-pub fn new_value_for_block(board: &Board, block_index: usize) -> CellBlock {
-  let first_row = block_index < BOARD_WIDTH_BLOCKS;
-  let last_column = block_index % BOARD_WIDTH_BLOCKS == BOARD_WIDTH_BLOCKS - 1;
-  let last_row = block_index >= BOARD_TOTAL_BLOCKS - BOARD_WIDTH_BLOCKS;
-  let first_column = block_index % BOARD_WIDTH_BLOCKS == 0;
+// Boards are stepped a row at a time, but single blocks are still handy for benchmarking the tables.
+#[allow(dead_code)]
+pub fn new_value_for_block<const WIDTH: usize, R: BlockRule>(board: &[CellBlock], block_index: usize, rule: &R) -> CellBlock {
+  rule.new_value_for_neighborhood(&neighborhood_for_block::<WIDTH>(board, block_index))
+}
 
-  let mut neighbors: u32 = 0;
+// Boards are given as a slice of rows of blocks, with the width as a constant so that finding a block's row and column
+// stays as cheap as it is for the full-size board.
+#[allow(dead_code)]
+pub fn neighborhood_for_block<const WIDTH: usize>(board: &[CellBlock], block_index: usize) -> BlockNeighborhood {
+  let first_row = block_index < WIDTH;
+  let last_column = block_index % WIDTH == WIDTH - 1;
+  let last_row = block_index >= board.len() - WIDTH;
+  let first_column = block_index.is_multiple_of(WIDTH);
+
+  let mut neighborhood: BlockNeighborhood = [EMPTY_BLOCK; 9];
+  neighborhood[CENTER] = board[block_index];
   if !first_row {
-    neighbors |= ((board[block_index - BOARD_WIDTH_BLOCKS] & (0b11111111 << 56)) >> 40) as u32;
+    neighborhood[TOP] = board[block_index - WIDTH];
   }
   if !last_column {
-    let right_block = board[block_index + 1];
-    let right_neighbors: u32 =
-        ((right_block & 1) << 8) as u32
-            | ((right_block & (1 << 8)) << 1) as u32
-            | ((right_block & (1 << 16)) >> 6) as u32
-            | ((right_block & (1 << 24)) >> 13) as u32
-            | ((right_block & (1 << 32)) >> 20) as u32
-            | ((right_block & (1 << 40)) >> 27) as u32
-            | ((right_block & (1 << 48)) >> 34) as u32
-            | ((right_block & (1 << 56)) >> 41) as u32;
-    neighbors |= right_neighbors;
+    neighborhood[RIGHT] = board[block_index + 1];
   }
   if !last_row {
-    neighbors |= ((board[block_index + BOARD_WIDTH_BLOCKS] & 0b11111111) << 16) as u32;
+    neighborhood[BOTTOM] = board[block_index + WIDTH];
   }
   if !first_column {
-    let left_block = board[block_index - 1];
-    let left_neighbors: u32 =
-        ((left_block & (1 << 7)) << 17) as u32
-            | ((left_block & (1 << 15)) << 10) as u32
-            | ((left_block & (1 << 23)) << 3) as u32
-            | ((left_block & (1 << 31)) >> 4) as u32
-            | ((left_block & (1 << 39)) >> 11) as u32
-            | ((left_block & (1 << 47)) >> 18) as u32
-            | ((left_block & (1 << 55)) >> 25) as u32
-            | ((left_block & (1 << 63)) >> 32) as u32;
-    neighbors |= left_neighbors;
+    neighborhood[LEFT] = board[block_index - 1];
   }
-
-  let mut neighbor_corners: u8 = 0;
   if !first_row && !first_column {
-    neighbor_corners |= ((board[block_index - 1 - BOARD_WIDTH_BLOCKS] >> 63) as u8) & 0b00000001;
+    neighborhood[TOP_LEFT] = board[block_index - 1 - WIDTH];
   }
   if !first_row && !last_column {
-    neighbor_corners |= ((board[block_index + 1 - BOARD_WIDTH_BLOCKS] >> 54) as u8) & 0b00000100;
+    neighborhood[TOP_RIGHT] = board[block_index + 1 - WIDTH];
   }
   if !last_row && !last_column {
-    neighbor_corners |= ((board[block_index + 1 + BOARD_WIDTH_BLOCKS] << 7) as u8) & 0b10000000;
+    neighborhood[BOTTOM_RIGHT] = board[block_index + 1 + WIDTH];
   }
   if !last_row && !first_column {
-    neighbor_corners |= ((board[block_index - 1 + BOARD_WIDTH_BLOCKS] << 5) as u8) & 0b00100000;
+    neighborhood[BOTTOM_LEFT] = board[block_index - 1 + WIDTH];
   }
 
-  let block = board[block_index];
-  new_value_for_outer_cell_block(block, neighbors, neighbor_corners) | new_value_for_inner_cell_block(block)
+  neighborhood
 }
 
-fn new_value_for_outer_cell_block(block: u64, neighbors: u32, neighbor_corners: u8) -> u64 {
-  const TOP_LEFT: u8 = 0;
-  const TOP_RIGHT: u8 = 7;
-  const BOTTOM_RIGHT: u8 = 63;
-  const BOTTOM_LEFT: u8 = 56;
-
-  const TOP_CELLS: [u8; 6] = [01, 02, 03, 04, 05, 06];
-  const RIGHT_CELLS: [u8; 6] = [15, 23, 31, 39, 47, 55];
-  const LEFT_CELLS: [u8; 6] = [08, 16, 24, 32, 40, 48];
-  const BOTTOM_CELLS: [u8; 6] = [57, 58, 59, 60, 61, 62];
-
-  // neighbors are the cells surrounding the block's perimeter.
-  // bits 0-7 are top border left-to-right
-  // bits 8-15 are right border top-to-bottom
-  // bits 16-23 are bottom border left-to-right
-  // bits 24-31 are left border top-to-bottom
-
-  // neighbor_corners are formatted same as neighbor mask
-  // bit 0 is top-left corner
-  // bit 2 is top-right corner
-  // bit 5 is bottom-left corner
-  // bit 7 is bottom-right corner
-
-  let mut new_block: u64 = 0;
-
-  for cell in TOP_CELLS {
-    let neighbor_mask: u8 =
-        (shift_right(neighbors, cell as i8 - 1) & 0b00000111) as u8
-            | neighbor_left_of_cell(block, cell)
-            | neighbor_right_of_cell(block, cell)
-            | neighbors_below_cell(block, cell);
-    new_block |= new_value_for_cell(block, cell, neighbor_mask);
+// Steps every block of a board that is WIDTH blocks across a row at a time, splitting the rows evenly between threads.
+pub fn compute_next_board_state<const WIDTH: usize, R: BlockRule + Sync + ?Sized>(source: &[CellBlock], destination: &mut [CellBlock], rule: &R) {
+  // Stands in for the rows past the top and bottom edges.
+  let empty_row = &vec![EMPTY_BLOCK; WIDTH];
+  compute_next_strip_state::<WIDTH, _>(source, destination, [empty_row, empty_row], rule)
+}
+
+// Steps a strip of rows out of a taller board in the same way, given the rows of blocks just above and below the strip.
+pub fn compute_next_strip_state<const WIDTH: usize, R: BlockRule + Sync + ?Sized>(source: &[CellBlock], destination: &mut [CellBlock], halos: [&[CellBlock]; 2], rule: &R) {
+  assert_eq!(source.len(), destination.len());
+  let height = source.len() / WIDTH;
+  let rows_per_thread = height.div_ceil(crate::num_threads());
+
+  std::thread::scope(|scope| {
+    let mut threads = Vec::<std::thread::ScopedJoinHandle<()>>::new();
+    for (chunk_index, chunk) in destination.chunks_mut(rows_per_thread * WIDTH).enumerate() {
+      threads.push(scope.spawn(move || {
+        let start = Instant::now();
+        for (row_index, destination_row) in chunk.chunks_mut(WIDTH).enumerate() {
+          let row = chunk_index * rows_per_thread + row_index;
+          let above = if row == 0 { halos[0] } else { &source[(row - 1) * WIDTH..row * WIDTH] };
+          let below = if row + 1 == height { halos[1] } else { &source[(row + 1) * WIDTH..(row + 2) * WIDTH] };
+          rule.new_values_for_row([above, &source[row * WIDTH..(row + 1) * WIDTH], below], destination_row);
+        }
+        crate::record_busy_time(chunk_index, start.elapsed());
+      }));
+    }
+  });
+}
+
+// The neighborhood of a block in a row, given along with the rows above and below it. Works for rows of any kind of
+// word, so the kernels can use it for their edges too.
+pub fn row_neighborhood<T: Copy + Default>(rows: [&[T]; 3], column: usize) -> [T; 9] {
+  let mut neighborhood = [T::default(); 9];
+  for (row, blocks) in rows.iter().enumerate() {
+    if column > 0 {
+      neighborhood[row * 3] = blocks[column - 1];
+    }
+    neighborhood[row * 3 + 1] = blocks[column];
+    if column + 1 < blocks.len() {
+      neighborhood[row * 3 + 2] = blocks[column + 1];
+    }
   }
-  for cell in RIGHT_CELLS {
-    let row = cell / 8;
-    let neighbor_mask: u8 =
-        (neighbors_above_cell(block, cell) & 0b00000011)
-            | (shift_right(neighbors, row as i8 + 5) & 0b00000100) as u8
-            | neighbor_left_of_cell(block, cell)
-            | (shift_right(neighbors, row as i8 + 4) & 0b00010000) as u8
-            | (neighbors_below_cell(block, cell) & 0b01100000)
-            | (shift_right(neighbors, row as i8 + 2) & 0b10000000) as u8;
-    new_block |= new_value_for_cell(block, cell, neighbor_mask);
+  neighborhood
+}
+
+pub fn clear_board<T: Copy + Default + Send>(board: &mut [T]) {
+  let chunk_size = board.len().div_ceil(crate::num_threads());
+  std::thread::scope(|scope| {
+    for chunk in board.chunks_mut(chunk_size) {
+      scope.spawn(|| chunk.fill(T::default()));
+    }
+  });
+}
+
+// A block and the eight blocks surrounding it, ordered the same way as a cell's neighbor mask with the block itself
+// inserted in the middle. Blocks that fall outside of a board are expected to be zero.
+pub type BlockNeighborhood = [CellBlock; 9];
+
+pub const TOP_LEFT: usize = 0;
+pub const TOP: usize = 1;
+pub const TOP_RIGHT: usize = 2;
+pub const LEFT: usize = 3;
+pub const CENTER: usize = 4;
+pub const RIGHT: usize = 5;
+pub const BOTTOM_LEFT: usize = 6;
+pub const BOTTOM: usize = 7;
+pub const BOTTOM_RIGHT: usize = 8;
+
+// Decides the next state of a single cell from its current state and its neighbor mask. The neighbor mask has one bit
+// per surrounding cell:
+// bits 0-2 are the row above left-to-right
+// bit 3 is the cell to the left
+// bit 4 is the cell to the right
+// bits 5-7 are the row below left-to-right
+pub trait CellRule {
+  fn new_value_for_cell(&self, alive: bool, neighbor_mask: u8) -> u64;
+
+  // Rules that can step several blocks at once do so here, as described for block rules.
+  fn new_values_for_row(&self, rows: [&[CellBlock]; 3], destination: &mut [CellBlock]) where Self: Sized {
+    step_row_by_block(self, rows, destination)
   }
-  for cell in BOTTOM_CELLS {
-    let neighbor_mask: u8 =
-        neighbors_above_cell(block, cell)
-            | neighbor_left_of_cell(block, cell)
-            | neighbor_right_of_cell(block, cell)
-            | (shift_right(neighbors, cell as i8 - 46) & 0b11100000) as u8;
-    new_block |= new_value_for_cell(block, cell, neighbor_mask);
+
+  // Rules that can step a row of cells laid out 64 to a word do so here, as described for block rules.
+  fn new_values_for_cell_row(&self, _rows: [&[u64]; 3], _destination: &mut [u64]) -> bool {
+    false
+  }
+}
+
+// Decides the next value of a whole block from the block and the blocks surrounding it, for rules that do not decide
+// each cell from its neighbor mask alone. Every cell rule is also a block rule.
+pub trait BlockRule {
+  fn new_value_for_neighborhood(&self, neighborhood: &BlockNeighborhood) -> CellBlock;
+
+  // Steps a row of blocks, given along with the rows above and below it, which are all zero past the edges of the
+  // board. Rules are free to step as many of the blocks at once as they can.
+  fn new_values_for_row(&self, rows: [&[CellBlock]; 3], destination: &mut [CellBlock]) {
+    step_row_by_block(self, rows, destination)
+  }
+
+  // Steps a row of cells on a board laid out in rows rather than blocks, with each word holding 64 cells side by side
+  // and the leftmost cell in the lowest bit. Only rules that can work on the rows directly do so, returning true, and
+  // the rest return false to be stepped a row of blocks at a time instead. A rule has to do one or the other every time.
+  fn new_values_for_cell_row(&self, _rows: [&[u64]; 3], _destination: &mut [u64]) -> bool {
+    false
   }
-  for cell in LEFT_CELLS {
-    let row = cell / 8;
-    let neighbor_mask: u8 =
-        (shift_right(neighbors, row as i8 + 23) & 0b00000001) as u8
-            | (neighbors_above_cell(block, cell) & 0b00000110)
-            | (shift_right(neighbors, row as i8 + 21) & 0b00001000) as u8
-            | neighbor_right_of_cell(block, cell)
-            | (shift_right(neighbors, row as i8 + 20) & 0b00100000) as u8
-            | (neighbors_below_cell(block, cell) & 0b11000000);
-    new_block |= new_value_for_cell(block, cell, neighbor_mask);
+}
+
+impl<R: CellRule> BlockRule for R {
+  fn new_value_for_neighborhood(&self, neighborhood: &BlockNeighborhood) -> CellBlock {
+    new_value_for_neighborhood(neighborhood, self)
+  }
+
+  fn new_values_for_row(&self, rows: [&[CellBlock]; 3], destination: &mut [CellBlock]) {
+    CellRule::new_values_for_row(self, rows, destination)
   }
 
-  let cell: u8 = TOP_LEFT;
-  let neighbor_mask: u8 =
-      (neighbor_corners & 0b00000001)
-          | ((neighbors << 1) & 0b00000110) as u8
-          | ((neighbors >> 21) & 0b00001000) as u8
-          | neighbor_right_of_cell(block, cell)
-          | ((neighbors >> 20) & 0b00100000) as u8
-          | (neighbors_below_cell(block, cell) & 0b11000000);
-  new_block |= new_value_for_cell(block, cell, neighbor_mask);
-
-  let cell: u8 = TOP_RIGHT;
-  let neighbor_mask: u8 =
-      ((neighbors >> 6) & 0b00000011) as u8
-          | (neighbor_corners & 0b00000100)
-          | neighbor_left_of_cell(block, cell)
-          | ((neighbors >> 4) & 0b00010000) as u8
-          | (neighbors_below_cell(block, cell) & 0b01100000)
-          | ((neighbors >> 2) & 0b10000000) as u8;
-  new_block |= new_value_for_cell(block, cell, neighbor_mask);
-
-  let cell: u8 = BOTTOM_RIGHT;
-  let neighbor_mask: u8 =
-      (neighbors_above_cell(block, cell) & 0b00000011)
-      | ((neighbors >> 12) & 0b00000100) as u8
-      | neighbor_left_of_cell(block, cell)
-      | ((neighbors >> 9) & 0b00010000) as u8
-      | ((neighbors >> 17) & 0b01100000) as u8
-      | (neighbor_corners & 0b10000000);
-  new_block |= new_value_for_cell(block, cell, neighbor_mask);
-
-  let cell: u8 = BOTTOM_LEFT;
-  let neighbor_mask: u8 =
-      ((neighbors >> 30) & 0b00000001) as u8
-          | (neighbors_above_cell(block, cell) & 0b00000110)
-          | ((neighbors >> 28) & 0b00001000) as u8
-          | neighbor_right_of_cell(block, cell)
-          | (neighbor_corners & 0b00100000)
-          | ((neighbors >> 10) & 0b11000000) as u8;
-  new_block |= new_value_for_cell(block, cell, neighbor_mask);
-
-  new_block
+  fn new_values_for_cell_row(&self, rows: [&[u64]; 3], destination: &mut [u64]) -> bool {
+    CellRule::new_values_for_cell_row(self, rows, destination)
+  }
 }
 
-fn new_value_for_inner_cell_block(block: u64) -> u64 {
-  const INNER_CELLS: [u8; 36] = [
-    09, 10, 11, 12, 13, 14,
-    17, 18, 19, 20, 21, 22,
-    25, 26, 27, 28, 29, 30,
-    33, 34, 35, 36, 37, 38,
-    41, 42, 43, 44, 45, 46,
-    49, 50, 51, 52, 53, 54,
-  ];
-
-  let mut new_block: u64 = 0;
-  for cell in INNER_CELLS {
-    new_block |= new_value_for_inner_cell(block, cell);
+fn step_row_by_block<R: BlockRule + ?Sized>(rule: &R, rows: [&[CellBlock]; 3], destination: &mut [CellBlock]) {
+  for (column, block) in destination.iter_mut().enumerate() {
+    *block = rule.new_value_for_neighborhood(&row_neighborhood(rows, column));
   }
-  new_block
 }
 
-fn new_value_for_inner_cell(block: u64, cell: u8) -> u64 {
-  // const NEIGHBOR_MASK_AFTER: u64 =  0b111000001_;
-  // const NEIGHBOR_MASK_BEFORE: u64 = 0b_100000111;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Neighborhood {
+  // All eight surrounding cells.
+  Moore,
+  // The four orthogonally adjacent cells.
+  VonNeumann,
+  // A hexagonal grid emulated on the square one, the same way Golly does. Every row is shifted half a cell to the left
+  // of the row above, which leaves the top-right and bottom-left cells out of the neighborhood.
+  Hexagonal,
+}
 
-  let neighbor_mask: u8 =
-      neighbors_above_cell(block, cell)
-          | neighbor_left_of_cell(block, cell)
-          | neighbor_right_of_cell(block, cell)
-          | neighbors_below_cell(block, cell);
+impl Neighborhood {
+  // The bits of a neighbor mask that belong to the neighborhood. Rules on smaller neighborhoods ignore the other bits.
+  pub const fn neighbor_mask(self) -> u8 {
+    match self {
+      Neighborhood::Moore => 0b11111111,
+      Neighborhood::VonNeumann => 0b01011010,
+      Neighborhood::Hexagonal => 0b11011011,
+    }
+  }
 
-  new_value_for_cell(block, cell, neighbor_mask)
+  pub const fn neighbor_count(self) -> u8 {
+    self.neighbor_mask().count_ones() as u8
+  }
+}
+
+// Conway's Game of Life, B3/S23.
+pub struct Conway;
+
+// For dead cells and then live ones.
+const CONWAY_TABLE: [[u8; 256]; 2] = [neighbor_count_table(&[3]), neighbor_count_table(&[2, 3])];
+
+impl CellRule for Conway {
+  #[inline(always)]
+  fn new_value_for_cell(&self, alive: bool, neighbor_mask: u8) -> u64 {
+    CONWAY_TABLE[alive as usize][neighbor_mask as usize] as u64
+  }
+
+  // Conway's rule only needs the number of live neighbors, which can be counted for every cell of several blocks at
+  // once rather than looking each cell up in the tables.
+  fn new_values_for_row(&self, rows: [&[CellBlock]; 3], destination: &mut [CellBlock]) {
+    simd::step_conway_row(simd::Kernel::detect(), rows, destination)
+  }
+
+  fn new_values_for_cell_row(&self, rows: [&[u64]; 3], destination: &mut [u64]) -> bool {
+    simd::step_conway_cell_row(simd::Kernel::detect(), rows, destination);
+    true
+  }
 }
 
-fn neighbors_above_cell(block: u64, cell: u8) -> u8 {
-  (shift_right(block, cell as i8 - 9) & 0b00000111) as u8
+// Every cell's neighbor mask is gathered from the eight blocks of neighbors around the block, each lined up with the
+// cells they neighbor, so the same code works whatever the dimensions of a block.
+pub fn new_value_for_neighborhood<R: CellRule>(neighborhood: &BlockNeighborhood, rule: &R) -> CellBlock {
+  let block = neighborhood[CENTER];
+  let neighbors = block_neighbors(neighborhood);
+  (0..CELLS_PER_BLOCK as u32).fold(EMPTY_BLOCK, |new_block, cell| {
+    let neighbor_mask = neighbors.iter().enumerate().fold(0, |mask, (bit, &neighbor)| mask | u8::from((neighbor >> cell) & 1 == 1) << bit);
+    new_block | new_value_for_cell(block, cell, neighbor_mask, rule)
+  })
 }
 
-fn neighbor_left_of_cell(block: u64, cell: u8) -> u8 {
-  (shift_right(block, cell as i8 - 4) & 0b00001000) as u8
+// Each cell's eight neighbors as a block each, in the same order as a neighbor mask, taking the cells on the edges from
+// the surrounding blocks.
+pub fn block_neighbors(neighborhood: &BlockNeighborhood) -> [CellBlock; 8] {
+  let block = neighborhood[CENTER];
+  let left = from_left(block, neighborhood[LEFT]);
+  let right = from_right(block, neighborhood[RIGHT]);
+  [
+    from_above(left, from_left(neighborhood[TOP], neighborhood[TOP_LEFT])),
+    from_above(block, neighborhood[TOP]),
+    from_above(right, from_right(neighborhood[TOP], neighborhood[TOP_RIGHT])),
+    left,
+    right,
+    from_below(left, from_left(neighborhood[BOTTOM], neighborhood[BOTTOM_LEFT])),
+    from_below(block, neighborhood[BOTTOM]),
+    from_below(right, from_right(neighborhood[BOTTOM], neighborhood[BOTTOM_RIGHT])),
+  ]
 }
 
-fn neighbor_right_of_cell(block: u64, cell: u8) -> u8 {
-  (shift_right(block, cell as i8 - 3) & 0b00010000) as u8
+// Each cell's neighbor to the left, taking the last column of the block to the left for the first column.
+pub fn from_left(block: CellBlock, left: CellBlock) -> CellBlock {
+  ((block << 1) & !FIRST_COLUMN) | ((left >> (CELL_BLOCK_WIDTH - 1)) & FIRST_COLUMN)
 }
 
-fn neighbors_below_cell(block: u64, cell: u8) -> u8 {
-  (shift_right(block, cell as i8 + 2) & 0b11100000) as u8
+pub fn from_right(block: CellBlock, right: CellBlock) -> CellBlock {
+  ((block >> 1) & !LAST_COLUMN) | ((right << (CELL_BLOCK_WIDTH - 1)) & LAST_COLUMN)
 }
 
-fn new_value_for_cell(block: u64, cell: u8, neighbor_mask: u8) -> u64 {
-  (if (block >> cell) & 1 == 1 {
-    new_value_for_live_cell(neighbor_mask)
-  } else {
-    new_value_for_dead_cell(neighbor_mask)
-  }) << cell
+pub fn from_above(block: CellBlock, above: CellBlock) -> CellBlock {
+  (block << CELL_BLOCK_WIDTH) | (above >> (CELLS_PER_BLOCK - CELL_BLOCK_WIDTH))
 }
 
-fn new_value_for_dead_cell(neighbor_mask: u8) -> u64 {
-  match neighbor_mask {
-    0b00000001 => 0, 0b00000010 => 0, 0b00000011 => 0, 0b00000100 => 0, 0b00000101 => 0, 0b00000110 => 0, 0b00000111 => 1, 0b00001000 => 0,
-    0b00001001 => 0, 0b00001010 => 0, 0b00001011 => 1, 0b00001100 => 0, 0b00001101 => 1, 0b00001110 => 1, 0b00001111 => 0, 0b00010000 => 0,
-    0b00010001 => 0, 0b00010010 => 0, 0b00010011 => 1, 0b00010100 => 0, 0b00010101 => 1, 0b00010110 => 1, 0b00010111 => 0, 0b00011000 => 0,
-    0b00011001 => 1, 0b00011010 => 1, 0b00011011 => 0, 0b00011100 => 1, 0b00011101 => 0, 0b00011110 => 0, 0b00011111 => 0, 0b00100000 => 0,
-    0b00100001 => 0, 0b00100010 => 0, 0b00100011 => 1, 0b00100100 => 0, 0b00100101 => 1, 0b00100110 => 1, 0b00100111 => 0, 0b00101000 => 0,
-    0b00101001 => 1, 0b00101010 => 1, 0b00101011 => 0, 0b00101100 => 1, 0b00101101 => 0, 0b00101110 => 0, 0b00101111 => 0, 0b00110000 => 0,
-    0b00110001 => 1, 0b00110010 => 1, 0b00110011 => 0, 0b00110100 => 1, 0b00110101 => 0, 0b00110110 => 0, 0b00110111 => 0, 0b00111000 => 1,
-    0b00111001 => 0, 0b00111010 => 0, 0b00111011 => 0, 0b00111100 => 0, 0b00111101 => 0, 0b00111110 => 0, 0b00111111 => 0, 0b01000000 => 0,
-    0b01000001 => 0, 0b01000010 => 0, 0b01000011 => 1, 0b01000100 => 0, 0b01000101 => 1, 0b01000110 => 1, 0b01000111 => 0, 0b01001000 => 0,
-    0b01001001 => 1, 0b01001010 => 1, 0b01001011 => 0, 0b01001100 => 1, 0b01001101 => 0, 0b01001110 => 0, 0b01001111 => 0, 0b01010000 => 0,
-    0b01010001 => 1, 0b01010010 => 1, 0b01010011 => 0, 0b01010100 => 1, 0b01010101 => 0, 0b01010110 => 0, 0b01010111 => 0, 0b01011000 => 1,
-    0b01011001 => 0, 0b01011010 => 0, 0b01011011 => 0, 0b01011100 => 0, 0b01011101 => 0, 0b01011110 => 0, 0b01011111 => 0, 0b01100000 => 0,
-    0b01100001 => 1, 0b01100010 => 1, 0b01100011 => 0, 0b01100100 => 1, 0b01100101 => 0, 0b01100110 => 0, 0b01100111 => 0, 0b01101000 => 1,
-    0b01101001 => 0, 0b01101010 => 0, 0b01101011 => 0, 0b01101100 => 0, 0b01101101 => 0, 0b01101110 => 0, 0b01101111 => 0, 0b01110000 => 1,
-    0b01110001 => 0, 0b01110010 => 0, 0b01110011 => 0, 0b01110100 => 0, 0b01110101 => 0, 0b01110110 => 0, 0b01110111 => 0, 0b01111000 => 0,
-    0b01111001 => 0, 0b01111010 => 0, 0b01111011 => 0, 0b01111100 => 0, 0b01111101 => 0, 0b01111110 => 0, 0b01111111 => 0, 0b10000000 => 0,
-    0b10000001 => 0, 0b10000010 => 0, 0b10000011 => 1, 0b10000100 => 0, 0b10000101 => 1, 0b10000110 => 1, 0b10000111 => 0, 0b10001000 => 0,
-    0b10001001 => 1, 0b10001010 => 1, 0b10001011 => 0, 0b10001100 => 1, 0b10001101 => 0, 0b10001110 => 0, 0b10001111 => 0, 0b10010000 => 0,
-    0b10010001 => 1, 0b10010010 => 1, 0b10010011 => 0, 0b10010100 => 1, 0b10010101 => 0, 0b10010110 => 0, 0b10010111 => 0, 0b10011000 => 1,
-    0b10011001 => 0, 0b10011010 => 0, 0b10011011 => 0, 0b10011100 => 0, 0b10011101 => 0, 0b10011110 => 0, 0b10011111 => 0, 0b10100000 => 0,
-    0b10100001 => 1, 0b10100010 => 1, 0b10100011 => 0, 0b10100100 => 1, 0b10100101 => 0, 0b10100110 => 0, 0b10100111 => 0, 0b10101000 => 1,
-    0b10101001 => 0, 0b10101010 => 0, 0b10101011 => 0, 0b10101100 => 0, 0b10101101 => 0, 0b10101110 => 0, 0b10101111 => 0, 0b10110000 => 1,
-    0b10110001 => 0, 0b10110010 => 0, 0b10110011 => 0, 0b10110100 => 0, 0b10110101 => 0, 0b10110110 => 0, 0b10110111 => 0, 0b10111000 => 0,
-    0b10111001 => 0, 0b10111010 => 0, 0b10111011 => 0, 0b10111100 => 0, 0b10111101 => 0, 0b10111110 => 0, 0b10111111 => 0, 0b11000000 => 0,
-    0b11000001 => 1, 0b11000010 => 1, 0b11000011 => 0, 0b11000100 => 1, 0b11000101 => 0, 0b11000110 => 0, 0b11000111 => 0, 0b11001000 => 1,
-    0b11001001 => 0, 0b11001010 => 0, 0b11001011 => 0, 0b11001100 => 0, 0b11001101 => 0, 0b11001110 => 0, 0b11001111 => 0, 0b11010000 => 1,
-    0b11010001 => 0, 0b11010010 => 0, 0b11010011 => 0, 0b11010100 => 0, 0b11010101 => 0, 0b11010110 => 0, 0b11010111 => 0, 0b11011000 => 0,
-    0b11011001 => 0, 0b11011010 => 0, 0b11011011 => 0, 0b11011100 => 0, 0b11011101 => 0, 0b11011110 => 0, 0b11011111 => 0, 0b11100000 => 1,
-    0b11100001 => 0, 0b11100010 => 0, 0b11100011 => 0, 0b11100100 => 0, 0b11100101 => 0, 0b11100110 => 0, 0b11100111 => 0, 0b11101000 => 0,
-    0b11101001 => 0, 0b11101010 => 0, 0b11101011 => 0, 0b11101100 => 0, 0b11101101 => 0, 0b11101110 => 0, 0b11101111 => 0, 0b11110000 => 0,
-    0b11110001 => 0, 0b11110010 => 0, 0b11110011 => 0, 0b11110100 => 0, 0b11110101 => 0, 0b11110110 => 0, 0b11110111 => 0, 0b11111000 => 0,
-    0b11111001 => 0, 0b11111010 => 0, 0b11111011 => 0, 0b11111100 => 0, 0b11111101 => 0, 0b11111110 => 0, 0b11111111 => 0, 0b00000000 => 0,
-  }
+pub fn from_below(block: CellBlock, below: CellBlock) -> CellBlock {
+  (block >> CELL_BLOCK_WIDTH) | (below << (CELLS_PER_BLOCK - CELL_BLOCK_WIDTH))
 }
 
-fn new_value_for_live_cell(neighbor_mask: u8) -> u64 {
-  match neighbor_mask {
-    0b00000001 => 0, 0b00000010 => 0, 0b00000011 => 1, 0b00000100 => 0, 0b00000101 => 1, 0b00000110 => 1, 0b00000111 => 1, 0b00001000 => 0,
-    0b00001001 => 1, 0b00001010 => 1, 0b00001011 => 1, 0b00001100 => 1, 0b00001101 => 1, 0b00001110 => 1, 0b00001111 => 0, 0b00010000 => 0,
-    0b00010001 => 1, 0b00010010 => 1, 0b00010011 => 1, 0b00010100 => 1, 0b00010101 => 1, 0b00010110 => 1, 0b00010111 => 0, 0b00011000 => 1,
-    0b00011001 => 1, 0b00011010 => 1, 0b00011011 => 0, 0b00011100 => 1, 0b00011101 => 0, 0b00011110 => 0, 0b00011111 => 0, 0b00100000 => 0,
-    0b00100001 => 1, 0b00100010 => 1, 0b00100011 => 1, 0b00100100 => 1, 0b00100101 => 1, 0b00100110 => 1, 0b00100111 => 0, 0b00101000 => 1,
-    0b00101001 => 1, 0b00101010 => 1, 0b00101011 => 0, 0b00101100 => 1, 0b00101101 => 0, 0b00101110 => 0, 0b00101111 => 0, 0b00110000 => 1,
-    0b00110001 => 1, 0b00110010 => 1, 0b00110011 => 0, 0b00110100 => 1, 0b00110101 => 0, 0b00110110 => 0, 0b00110111 => 0, 0b00111000 => 1,
-    0b00111001 => 0, 0b00111010 => 0, 0b00111011 => 0, 0b00111100 => 0, 0b00111101 => 0, 0b00111110 => 0, 0b00111111 => 0, 0b01000000 => 0,
-    0b01000001 => 1, 0b01000010 => 1, 0b01000011 => 1, 0b01000100 => 1, 0b01000101 => 1, 0b01000110 => 1, 0b01000111 => 0, 0b01001000 => 1,
-    0b01001001 => 1, 0b01001010 => 1, 0b01001011 => 0, 0b01001100 => 1, 0b01001101 => 0, 0b01001110 => 0, 0b01001111 => 0, 0b01010000 => 1,
-    0b01010001 => 1, 0b01010010 => 1, 0b01010011 => 0, 0b01010100 => 1, 0b01010101 => 0, 0b01010110 => 0, 0b01010111 => 0, 0b01011000 => 1,
-    0b01011001 => 0, 0b01011010 => 0, 0b01011011 => 0, 0b01011100 => 0, 0b01011101 => 0, 0b01011110 => 0, 0b01011111 => 0, 0b01100000 => 1,
-    0b01100001 => 1, 0b01100010 => 1, 0b01100011 => 0, 0b01100100 => 1, 0b01100101 => 0, 0b01100110 => 0, 0b01100111 => 0, 0b01101000 => 1,
-    0b01101001 => 0, 0b01101010 => 0, 0b01101011 => 0, 0b01101100 => 0, 0b01101101 => 0, 0b01101110 => 0, 0b01101111 => 0, 0b01110000 => 1,
-    0b01110001 => 0, 0b01110010 => 0, 0b01110011 => 0, 0b01110100 => 0, 0b01110101 => 0, 0b01110110 => 0, 0b01110111 => 0, 0b01111000 => 0,
-    0b01111001 => 0, 0b01111010 => 0, 0b01111011 => 0, 0b01111100 => 0, 0b01111101 => 0, 0b01111110 => 0, 0b01111111 => 0, 0b10000000 => 0,
-    0b10000001 => 1, 0b10000010 => 1, 0b10000011 => 1, 0b10000100 => 1, 0b10000101 => 1, 0b10000110 => 1, 0b10000111 => 0, 0b10001000 => 1,
-    0b10001001 => 1, 0b10001010 => 1, 0b10001011 => 0, 0b10001100 => 1, 0b10001101 => 0, 0b10001110 => 0, 0b10001111 => 0, 0b10010000 => 1,
-    0b10010001 => 1, 0b10010010 => 1, 0b10010011 => 0, 0b10010100 => 1, 0b10010101 => 0, 0b10010110 => 0, 0b10010111 => 0, 0b10011000 => 1,
-    0b10011001 => 0, 0b10011010 => 0, 0b10011011 => 0, 0b10011100 => 0, 0b10011101 => 0, 0b10011110 => 0, 0b10011111 => 0, 0b10100000 => 1,
-    0b10100001 => 1, 0b10100010 => 1, 0b10100011 => 0, 0b10100100 => 1, 0b10100101 => 0, 0b10100110 => 0, 0b10100111 => 0, 0b10101000 => 1,
-    0b10101001 => 0, 0b10101010 => 0, 0b10101011 => 0, 0b10101100 => 0, 0b10101101 => 0, 0b10101110 => 0, 0b10101111 => 0, 0b10110000 => 1,
-    0b10110001 => 0, 0b10110010 => 0, 0b10110011 => 0, 0b10110100 => 0, 0b10110101 => 0, 0b10110110 => 0, 0b10110111 => 0, 0b10111000 => 0,
-    0b10111001 => 0, 0b10111010 => 0, 0b10111011 => 0, 0b10111100 => 0, 0b10111101 => 0, 0b10111110 => 0, 0b10111111 => 0, 0b11000000 => 1,
-    0b11000001 => 1, 0b11000010 => 1, 0b11000011 => 0, 0b11000100 => 1, 0b11000101 => 0, 0b11000110 => 0, 0b11000111 => 0, 0b11001000 => 1,
-    0b11001001 => 0, 0b11001010 => 0, 0b11001011 => 0, 0b11001100 => 0, 0b11001101 => 0, 0b11001110 => 0, 0b11001111 => 0, 0b11010000 => 1,
-    0b11010001 => 0, 0b11010010 => 0, 0b11010011 => 0, 0b11010100 => 0, 0b11010101 => 0, 0b11010110 => 0, 0b11010111 => 0, 0b11011000 => 0,
-    0b11011001 => 0, 0b11011010 => 0, 0b11011011 => 0, 0b11011100 => 0, 0b11011101 => 0, 0b11011110 => 0, 0b11011111 => 0, 0b11100000 => 1,
-    0b11100001 => 0, 0b11100010 => 0, 0b11100011 => 0, 0b11100100 => 0, 0b11100101 => 0, 0b11100110 => 0, 0b11100111 => 0, 0b11101000 => 0,
-    0b11101001 => 0, 0b11101010 => 0, 0b11101011 => 0, 0b11101100 => 0, 0b11101101 => 0, 0b11101110 => 0, 0b11101111 => 0, 0b11110000 => 0,
-    0b11110001 => 0, 0b11110010 => 0, 0b11110011 => 0, 0b11110100 => 0, 0b11110101 => 0, 0b11110110 => 0, 0b11110111 => 0, 0b11111000 => 0,
-    0b11111001 => 0, 0b11111010 => 0, 0b11111011 => 0, 0b11111100 => 0, 0b11111101 => 0, 0b11111110 => 0, 0b11111111 => 0, 0b00000000 => 0,
+fn new_value_for_cell<R: CellRule>(block: CellBlock, cell: u32, neighbor_mask: u8, rule: &R) -> CellBlock {
+  CellBlock::from(rule.new_value_for_cell((block >> cell) & 1 == 1, neighbor_mask)) << cell
+}
+
+// Which neighbor masks have one of the given numbers of live neighbors, as the birth or survival half of the table of
+// an outer-totalistic rule. Both Conway's table and the rules parsed from B/S notation are built from it.
+pub const fn neighbor_count_table(counts: &[u8]) -> [u8; 256] {
+  let mut table = [0; 256];
+  let mut neighbor_mask = 0;
+  while neighbor_mask < 256 {
+    let live_neighbors = (neighbor_mask as u8).count_ones();
+    let mut index = 0;
+    while index < counts.len() {
+      if counts[index] as u32 == live_neighbors {
+        table[neighbor_mask] = 1;
+      }
+      index += 1;
+    }
+    neighbor_mask += 1;
   }
+  table
 }
 
-fn shift_right<R, T: Clone + Shr<i8, Output = R> + Shl<i8, Output = R> + std::fmt::Display>(v: T, s: i8) -> R {
-  if s < 0 {
-    v.shl(s.abs())
-  } else {
-    v.shr(s)
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const WIDTH_BLOCKS: usize = 4;
+  const HEIGHT_BLOCKS: usize = 4;
+  const WIDTH: usize = WIDTH_BLOCKS * CELL_BLOCK_WIDTH as usize;
+  const HEIGHT: usize = HEIGHT_BLOCKS * CELL_BLOCK_HEIGHT as usize;
+
+  // Steps Conway's rule through the tables a block at a time rather than with the kernels.
+  struct Tables;
+
+  impl BlockRule for Tables {
+    fn new_value_for_neighborhood(&self, neighborhood: &BlockNeighborhood) -> CellBlock {
+      new_value_for_neighborhood(neighborhood, &Conway)
+    }
+  }
+
+  fn to_blocks(cells: &[bool]) -> Vec<CellBlock> {
+    let (block_width, block_height) = (CELL_BLOCK_WIDTH as usize, CELL_BLOCK_HEIGHT as usize);
+    let mut blocks = vec![EMPTY_BLOCK; WIDTH_BLOCKS * HEIGHT_BLOCKS];
+    for (x, y) in (0..cells.len()).filter(|&index| cells[index]).map(|index| (index % WIDTH, index / WIDTH)) {
+      blocks[y / block_height * WIDTH_BLOCKS + x / block_width] |= FIRST_CELL << (y % block_height * block_width + x % block_width);
+    }
+    blocks
+  }
+
+  // Steps every cell by counting its live neighbors, the way the game is usually described.
+  fn step_cells(cells: &[bool]) -> Vec<bool> {
+    (0..cells.len()).map(|index| {
+      let (x, y) = ((index % WIDTH) as i64, (index / WIDTH) as i64);
+      let live_neighbors = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+        .filter(|&neighbor| neighbor != (x, y))
+        .filter(|&(x, y)| (0..WIDTH as i64).contains(&x) && (0..HEIGHT as i64).contains(&y) && cells[y as usize * WIDTH + x as usize])
+        .count();
+      live_neighbors == 3 || (live_neighbors == 2 && cells[index])
+    }).collect()
+  }
+
+  fn cells_at(positions: impl IntoIterator<Item = (usize, usize)>) -> Vec<bool> {
+    let mut cells = vec![false; WIDTH * HEIGHT];
+    for (x, y) in positions {
+      cells[y * WIDTH + x] = true;
+    }
+    cells
+  }
+
+  fn assert_steps_like_cells<R: BlockRule + Sync>(rule: &R, mut cells: Vec<bool>, generations: usize) {
+    let mut blocks = to_blocks(&cells);
+    let mut next_blocks = vec![EMPTY_BLOCK; blocks.len()];
+    for generation in 1..=generations {
+      compute_next_board_state::<WIDTH_BLOCKS, _>(&blocks, &mut next_blocks, rule);
+      std::mem::swap(&mut blocks, &mut next_blocks);
+      cells = step_cells(&cells);
+      assert_eq!(blocks, to_blocks(&cells), "generation {}", generation);
+    }
+  }
+
+  // A glider heading down and to the right, which passes through the edges and corners of several blocks on its way
+  // and so needs the neighbors of every side of a block lined up right.
+  #[test]
+  fn glider_crosses_block_edges() {
+    let glider = cells_at([(2, 1), (3, 2), (1, 3), (2, 3), (3, 3)]);
+    let generations = 4 * (HEIGHT - 5);
+    assert_steps_like_cells(&Tables, glider.clone(), generations);
+    assert_steps_like_cells(&Conway, glider, generations);
+  }
+
+  // A blinker centered on every corner where four blocks meet, lying across the edge between the blocks to the left
+  // and right and then across the one between the blocks above and below.
+  #[test]
+  fn blinkers_straddle_block_corners() {
+    let (block_width, block_height) = (CELL_BLOCK_WIDTH as usize, CELL_BLOCK_HEIGHT as usize);
+    let corners = (1..HEIGHT_BLOCKS).flat_map(|y| (1..WIDTH_BLOCKS).map(move |x| (x * block_width, y * block_height)));
+    let blinkers = cells_at(corners.flat_map(|(x, y)| [(x - 1, y), (x, y), (x + 1, y)]));
+    assert_steps_like_cells(&Tables, blinkers.clone(), 4);
+    assert_steps_like_cells(&Conway, blinkers, 4);
   }
 }
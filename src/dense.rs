@@ -0,0 +1,192 @@
+use crate::conway;
+use crate::conway::{BlockRule, CellBlock, BOARD_HEIGHT_BLOCKS, BOARD_TOTAL_BLOCKS, BOARD_WIDTH_BLOCKS};
+use crate::row_major;
+use crate::simd::Kernel;
+use crate::sparse::{TileCoordinate, TILE_HEIGHT_BLOCKS, TILE_WIDTH_BLOCKS};
+use crate::universe::PlaneBlock;
+
+// The board split into tiles of the same size as the sparse boards use, where the last row and column of tiles may be cut
+// short.
+pub const WIDTH_TILES: usize = BOARD_WIDTH_BLOCKS.div_ceil(TILE_WIDTH_BLOCKS);
+pub const HEIGHT_TILES: usize = BOARD_HEIGHT_BLOCKS.div_ceil(TILE_HEIGHT_BLOCKS);
+
+// A board of a fixed size that stores every cell, alive or not, in one of several layouts. Whatever the layout, the
+// board is BOARD_WIDTH_BLOCKS x BOARD_HEIGHT_BLOCKS blocks and is read and written a block at a time. Boards are double
+// buffered, keeping the previous generation until the next step.
+pub trait DenseBoard: Send + Sync {
+  fn layout(&self) -> String;
+  // The kernel Conway's Game of Life is stepped with, which depends on how wide the words of the layout are.
+  fn kernel(&self) -> Kernel;
+  fn step(&mut self, rule: &(dyn BlockRule + Sync));
+  fn block(&self, block_x: usize, block_y: usize) -> CellBlock;
+  fn xor_block(&mut self, block_x: usize, block_y: usize, cells: CellBlock);
+  fn occupied_blocks(&self) -> Vec<PlaneBlock>;
+  // Every block that changed in the last step, as the cells that flipped.
+  fn changed_blocks(&self) -> Vec<PlaneBlock>;
+  fn occupied_tiles(&self) -> Vec<TileCoordinate>;
+  fn clear(&mut self);
+
+  // Looks through the blocks of a tile, stopping at the first occupied one.
+  fn is_tile_occupied(&self, tile_x: usize, tile_y: usize) -> bool {
+    is_tile_occupied(|block_x, block_y| self.block(block_x, block_y), tile_x, tile_y)
+  }
+}
+
+// Each word is a block of cells, and the blocks are stored a row at a time.
+pub struct BlockBoard {
+  board: Box<conway::Board>,
+  previous_board: Box<conway::Board>,
+}
+
+impl BlockBoard {
+  pub fn new(board: Box<conway::Board>, previous_board: Box<conway::Board>) -> Self {
+    BlockBoard { board, previous_board }
+  }
+}
+
+impl DenseBoard for BlockBoard {
+  fn layout(&self) -> String {
+    format!("{} x {} blocks", conway::CELL_BLOCK_WIDTH, conway::CELL_BLOCK_HEIGHT)
+  }
+
+  fn kernel(&self) -> Kernel {
+    Kernel::detect().for_blocks()
+  }
+
+  fn step(&mut self, rule: &(dyn BlockRule + Sync)) {
+    conway::compute_next_board_state::<BOARD_WIDTH_BLOCKS, _>(&self.board[..], &mut self.previous_board[..], rule);
+    std::mem::swap(&mut self.board, &mut self.previous_board);
+  }
+
+  fn block(&self, block_x: usize, block_y: usize) -> CellBlock {
+    self.board[block_y * BOARD_WIDTH_BLOCKS + block_x]
+  }
+
+  fn xor_block(&mut self, block_x: usize, block_y: usize, cells: CellBlock) {
+    self.board[block_y * BOARD_WIDTH_BLOCKS + block_x] ^= cells;
+  }
+
+  fn occupied_blocks(&self) -> Vec<PlaneBlock> {
+    collect_blocks(|block_x, block_y| self.block(block_x, block_y))
+  }
+
+  fn changed_blocks(&self) -> Vec<PlaneBlock> {
+    collect_blocks(|block_x, block_y| {
+      let block_index = block_y * BOARD_WIDTH_BLOCKS + block_x;
+      self.board[block_index] ^ self.previous_board[block_index]
+    })
+  }
+
+  fn occupied_tiles(&self) -> Vec<TileCoordinate> {
+    occupied_tiles(|block_x, block_y| self.block(block_x, block_y))
+  }
+
+  fn clear(&mut self) {
+    conway::clear_board(&mut self.board[..]);
+  }
+}
+
+// Each word is a row of cells side by side, as laid out by the row_major module.
+pub struct RowMajorBoard {
+  board: Box<row_major::Board>,
+  previous_board: Box<row_major::Board>,
+}
+
+impl RowMajorBoard {
+  pub fn new(board: Box<row_major::Board>, previous_board: Box<row_major::Board>) -> Self {
+    RowMajorBoard { board, previous_board }
+  }
+}
+
+impl DenseBoard for RowMajorBoard {
+  fn layout(&self) -> String {
+    format!("rows of {} cells", row_major::WORD_CELLS)
+  }
+
+  fn kernel(&self) -> Kernel {
+    Kernel::detect()
+  }
+
+  fn step(&mut self, rule: &(dyn BlockRule + Sync)) {
+    row_major::compute_next_board_state(&self.board[..], &mut self.previous_board[..], conway::BOARD_WIDTH_CELLS as usize, rule);
+    std::mem::swap(&mut self.board, &mut self.previous_board);
+  }
+
+  fn block(&self, block_x: usize, block_y: usize) -> CellBlock {
+    row_major::gather_block(&self.board[..], row_major::BOARD_WIDTH_WORDS, block_x, block_y)
+  }
+
+  fn xor_block(&mut self, block_x: usize, block_y: usize, cells: CellBlock) {
+    row_major::xor_block(&mut self.board[..], row_major::BOARD_WIDTH_WORDS, block_x, block_y, cells);
+  }
+
+  fn occupied_blocks(&self) -> Vec<PlaneBlock> {
+    collect_blocks(|block_x, block_y| self.block(block_x, block_y))
+  }
+
+  fn changed_blocks(&self) -> Vec<PlaneBlock> {
+    collect_blocks(|block_x, block_y| {
+      self.block(block_x, block_y) ^ row_major::gather_block(&self.previous_board[..], row_major::BOARD_WIDTH_WORDS, block_x, block_y)
+    })
+  }
+
+  fn occupied_tiles(&self) -> Vec<TileCoordinate> {
+    occupied_tiles(|block_x, block_y| self.block(block_x, block_y))
+  }
+
+  fn clear(&mut self) {
+    conway::clear_board(&mut self.board[..]);
+  }
+}
+
+// Scans the board in parallel for the blocks where the given function returns any cells.
+fn collect_blocks<F: Fn(usize, usize) -> CellBlock + Sync>(cells_for_block: F) -> Vec<PlaneBlock> {
+  let chunk_size = BOARD_TOTAL_BLOCKS.div_ceil(crate::num_threads());
+  let cells_for_block = &cells_for_block;
+
+  std::thread::scope(|scope| {
+    let mut threads = Vec::<std::thread::ScopedJoinHandle<Vec<PlaneBlock>>>::new();
+    for start in (0..BOARD_TOTAL_BLOCKS).step_by(chunk_size) {
+      threads.push(scope.spawn(move || {
+        (start..(start + chunk_size).min(BOARD_TOTAL_BLOCKS))
+            .filter_map(|block_index| {
+              let (block_x, block_y) = (block_index % BOARD_WIDTH_BLOCKS, block_index / BOARD_WIDTH_BLOCKS);
+              let cells = cells_for_block(block_x, block_y);
+              (cells != 0).then_some((0, (block_x as i64, block_y as i64), cells))
+            })
+            .collect()
+      }));
+    }
+    threads.into_iter().flat_map(|thread| thread.join().unwrap()).collect()
+  })
+}
+
+// Scans the tiles of the board in parallel, a row of tiles at a time, stopping at the first occupied block of each.
+fn occupied_tiles<F: Fn(usize, usize) -> CellBlock + Sync>(block: F) -> Vec<TileCoordinate> {
+  let tile_rows_per_thread = HEIGHT_TILES.div_ceil(crate::num_threads());
+  let block = &block;
+
+  std::thread::scope(|scope| {
+    let mut threads = Vec::<std::thread::ScopedJoinHandle<Vec<TileCoordinate>>>::new();
+    for first_tile_row in (0..HEIGHT_TILES).step_by(tile_rows_per_thread) {
+      threads.push(scope.spawn(move || {
+        let mut tiles = Vec::new();
+        for tile_y in first_tile_row..(first_tile_row + tile_rows_per_thread).min(HEIGHT_TILES) {
+          for tile_x in 0..WIDTH_TILES {
+            if is_tile_occupied(block, tile_x, tile_y) {
+              tiles.push((tile_x as i64, tile_y as i64));
+            }
+          }
+        }
+        tiles
+      }));
+    }
+    threads.into_iter().flat_map(|thread| thread.join().unwrap()).collect()
+  })
+}
+
+fn is_tile_occupied<F: Fn(usize, usize) -> CellBlock>(block: F, tile_x: usize, tile_y: usize) -> bool {
+  let block_rows = tile_y * TILE_HEIGHT_BLOCKS..((tile_y + 1) * TILE_HEIGHT_BLOCKS).min(BOARD_HEIGHT_BLOCKS);
+  let block_columns = tile_x * TILE_WIDTH_BLOCKS..((tile_x + 1) * TILE_WIDTH_BLOCKS).min(BOARD_WIDTH_BLOCKS);
+  block_rows.into_iter().any(|block_y| block_columns.clone().any(|block_x| block(block_x, block_y) != 0))
+}
@@ -0,0 +1,305 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+use crate::conway;
+use crate::conway::{BlockRule, CellBlock, Conway, BOARD_HEIGHT_BLOCKS, BOARD_WIDTH_BLOCKS, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK, FIRST_CELL};
+use crate::rule::Rule;
+
+// Workers report to the coordinator every this many generations, as well as before the first and after the last.
+const REPORT_INTERVAL: u64 = 10;
+// How long a worker keeps trying to reach the worker above it, which may not be listening yet.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A distributed run splits the dense board into horizontal strips of whole rows of blocks, each stepped by a worker
+// process of its own, so that no process needs to hold the whole board. Every generation each worker swaps the rows
+// of blocks along the edges of its strip with the workers above and below it over Unix sockets, and every so often
+// reports to the coordinator, which adds up the reports and prints them.
+pub fn run_coordinator(workers: usize, rule: Option<&Rule>, generations: u64) -> Result<(), String> {
+  if !(1..=BOARD_HEIGHT_BLOCKS).contains(&workers) {
+    return Err(format!("Expected between 1 and {} workers", BOARD_HEIGHT_BLOCKS));
+  }
+  check_rule(rule)?;
+  let socket_dir = std::env::temp_dir().join(format!("lifer-{}", std::process::id()));
+  std::fs::create_dir_all(&socket_dir).map_err(|error| format!("Failed to create {}: {}", socket_dir.display(), error))?;
+  let result = coordinate(workers, &socket_dir, generations);
+  std::fs::remove_dir_all(&socket_dir).ok();
+  result
+}
+
+fn coordinate(workers: usize, socket_dir: &Path, generations: u64) -> Result<(), String> {
+  let listener = UnixListener::bind(socket_dir.join("coordinator")).map_err(|error| format!("Failed to listen for workers: {}", error))?;
+  let executable = std::env::current_exe().map_err(|error| format!("Failed to find the executable: {}", error))?;
+  // Workers share the processor between them, unless told otherwise.
+  let threads = (crate::num_threads() / workers).max(1).to_string();
+  let mut children = Vec::new();
+  for index in 0..workers {
+    let mut command = Command::new(&executable);
+    command.args(std::env::args().skip(1)).args(["--worker", &index.to_string()]).arg("--socket-dir").arg(socket_dir);
+    if crate::argument_value("--threads").is_none() {
+      command.args(["--threads", &threads]);
+    }
+    children.push(command.spawn().map_err(|error| format!("Failed to start worker {}: {}", index, error))?);
+  }
+  println!("Started {} workers of about {} rows of blocks each.", workers, BOARD_HEIGHT_BLOCKS / workers);
+
+  let result = collect_reports(&listener, &mut children, generations);
+  // Workers are left waiting on each other when any of them fails.
+  if result.is_err() {
+    for child in &mut children {
+      child.kill().ok();
+    }
+  }
+  for (index, mut child) in children.into_iter().enumerate() {
+    let status = child.wait().map_err(|error| format!("Failed to wait for worker {}: {}", index, error))?;
+    if !status.success() && result.is_ok() {
+      return Err(format!("Worker {} failed: {}", index, status));
+    }
+  }
+  result
+}
+
+fn collect_reports(listener: &UnixListener, children: &mut [Child], generations: u64) -> Result<(), String> {
+  let workers = children.len();
+  // Workers introduce themselves with their index, in whatever order they get going. A worker that fails to start
+  // never connects, so the workers are checked on while waiting for them.
+  listener.set_nonblocking(true).map_err(|error| error.to_string())?;
+  let mut reports: Vec<Option<BufReader<UnixStream>>> = (0..workers).map(|_| None).collect();
+  while reports.iter().any(Option::is_none) {
+    match listener.accept() {
+      Ok((stream, _)) => {
+        stream.set_nonblocking(false).map_err(|error| error.to_string())?;
+        let mut reader = BufReader::new(stream);
+        let index = read_report(&mut reader)?.parse::<usize>().ok().filter(|&index| index < workers).ok_or("Malformed worker introduction")?;
+        reports[index] = Some(reader);
+      }
+      Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+        for (index, child) in children.iter_mut().enumerate() {
+          if let Some(status) = child.try_wait().map_err(|error| error.to_string())? {
+            return Err(format!("Worker {} failed to start: {}", index, status));
+          }
+        }
+        std::thread::sleep(Duration::from_millis(10));
+      }
+      Err(error) => return Err(format!("Failed to accept a worker: {}", error)),
+    }
+  }
+  let mut reports: Vec<BufReader<UnixStream>> = reports.into_iter().map(Option::unwrap).collect();
+
+  let start = Instant::now();
+  // How long each worker spent stepping its strip rather than waiting on its neighbors, out of how long it has run.
+  let mut busy_times = vec![(Duration::ZERO, Duration::ZERO); workers];
+  for generation in (0..=generations).filter(|&generation| is_report_generation(generation, generations)) {
+    let mut population = 0;
+    for (index, reader) in reports.iter_mut().enumerate() {
+      let report = read_report(reader).map_err(|error| format!("Worker {} stopped before generation {}: {}", index, generation, error))?;
+      let values: Vec<u64> = report.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+      let [reported_generation, worker_population, busy_nanoseconds, running_nanoseconds] = values[..] else {
+        return Err(format!("Malformed report from worker {}: {}", index, report));
+      };
+      if reported_generation != generation {
+        return Err(format!("Worker {} reported generation {} instead of {}", index, reported_generation, generation));
+      }
+      population += worker_population;
+      busy_times[index] = (Duration::from_nanos(busy_nanoseconds), Duration::from_nanos(running_nanoseconds));
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    println!("Generation {}: population {}, {:.2} generations per second.", generation, population, generation as f64 / seconds.max(f64::EPSILON));
+  }
+  for (index, (busy_time, running_time)) in busy_times.iter().enumerate() {
+    println!("Worker {} busy {:.1}% of the time.", index, busy_time.as_secs_f64() / running_time.as_secs_f64().max(f64::EPSILON) * 100.0);
+  }
+
+  Ok(())
+}
+
+fn read_report(reader: &mut BufReader<UnixStream>) -> Result<String, String> {
+  let mut line = String::new();
+  match reader.read_line(&mut line) {
+    Ok(0) => Err("Connection closed".to_string()),
+    Ok(_) => Ok(line.trim().to_string()),
+    Err(error) => Err(error.to_string()),
+  }
+}
+
+fn is_report_generation(generation: u64, generations: u64) -> bool {
+  generation.is_multiple_of(REPORT_INTERVAL) || generation == generations
+}
+
+// Only the rules the dense board runs can be distributed.
+fn check_rule(rule: Option<&Rule>) -> Result<(), String> {
+  match rule {
+    None => Ok(()),
+    Some(rule @ (Rule::Table(_) | Rule::Margolus(_))) if rule.states() == 2 => Ok(()),
+    Some(_) => Err("Distributed runs only support two-state rules with a transition table".to_string()),
+  }
+}
+
+// The rows of blocks a worker steps, out of the whole board.
+fn strip_rows(index: usize, workers: usize) -> std::ops::Range<usize> {
+  index * BOARD_HEIGHT_BLOCKS / workers..(index + 1) * BOARD_HEIGHT_BLOCKS / workers
+}
+
+// Steps one strip of the board, starting from whichever of the given cells fall inside it.
+pub fn run_worker(index: usize, workers: usize, socket_dir: &Path, rule: Option<&Rule>, cells: &[(i64, i64, u8)], generations: u64) -> Result<(), String> {
+  check_rule(rule)?;
+  let rows = strip_rows(index, workers);
+  let mut board = vec![EMPTY_BLOCK; rows.len() * BOARD_WIDTH_BLOCKS];
+  let mut next_board = vec![EMPTY_BLOCK; board.len()];
+  for &(x, y, state) in cells {
+    let (block_x, block_y) = (x.div_euclid(CELL_BLOCK_WIDTH as i64), y.div_euclid(CELL_BLOCK_HEIGHT as i64));
+    let inside = (0..BOARD_WIDTH_BLOCKS as i64).contains(&block_x) && (rows.start as i64..rows.end as i64).contains(&block_y);
+    if state != 0 && inside {
+      let cell = y.rem_euclid(CELL_BLOCK_HEIGHT as i64) as u64 * CELL_BLOCK_WIDTH + x.rem_euclid(CELL_BLOCK_WIDTH as i64) as u64;
+      board[(block_y as usize - rows.start) * BOARD_WIDTH_BLOCKS + block_x as usize] |= FIRST_CELL << cell;
+    }
+  }
+
+  let mut coordinator = UnixStream::connect(socket_dir.join("coordinator")).map_err(|error| format!("Failed to reach the coordinator: {}", error))?;
+  writeln!(coordinator, "{}", index).map_err(|error| error.to_string())?;
+  // Each worker listens for the worker below it, and connects to the one above it.
+  let listener = (index + 1 < workers)
+      .then(|| UnixListener::bind(worker_socket(socket_dir, index)))
+      .transpose()
+      .map_err(|error| format!("Failed to listen for worker {}: {}", index + 1, error))?;
+  let mut above = if index > 0 { Some(connect_to_worker(socket_dir, index - 1)?) } else { None };
+  let mut below = listener.map(|listener| listener.accept().map(|(stream, _)| stream))
+      .transpose()
+      .map_err(|error| format!("Failed to accept worker {}: {}", index + 1, error))?;
+
+  // The rows of blocks just past the edges of the strip, which stay empty at the edges of the board.
+  let mut halo_above = vec![EMPTY_BLOCK; BOARD_WIDTH_BLOCKS];
+  let mut halo_below = vec![EMPTY_BLOCK; BOARD_WIDTH_BLOCKS];
+  let mut busy_time = Duration::ZERO;
+  let running_since = Instant::now();
+  for generation in 0..=generations {
+    if generation > 0 {
+      let (first_row, last_row) = (&board[..BOARD_WIDTH_BLOCKS], &board[board.len() - BOARD_WIDTH_BLOCKS..]);
+      std::thread::scope(|scope| {
+        let exchanged_above = above.as_mut().map(|stream| scope.spawn(|| exchange_rows(stream, first_row, &mut halo_above)));
+        let exchanged_below = below.as_mut().map(|stream| exchange_rows(stream, last_row, &mut halo_below));
+        exchanged_above.map_or(Ok(()), |thread| thread.join().unwrap()).and(exchanged_below.unwrap_or(Ok(())))
+      }).map_err(|error| format!("Worker {} failed to exchange rows: {}", index, error))?;
+
+      let start = Instant::now();
+      let halos = [&halo_above[..], &halo_below[..]];
+      let mut step = |rule: &(dyn BlockRule + Sync)| conway::compute_next_strip_state::<BOARD_WIDTH_BLOCKS, _>(&board, &mut next_board, halos, rule);
+      match rule {
+        Some(Rule::Table(rule)) => step(rule),
+        Some(Rule::Margolus(rule)) => step(&rule.step(generation - 1)),
+        _ => step(&Conway),
+      }
+      std::mem::swap(&mut board, &mut next_board);
+      busy_time += start.elapsed();
+    }
+    if is_report_generation(generation, generations) {
+      let population: u64 = board.iter().map(|block| block.count_ones() as u64).sum();
+      let report = format!("{} {} {} {}", generation, population, busy_time.as_nanos(), running_since.elapsed().as_nanos());
+      writeln!(coordinator, "{}", report).map_err(|error| error.to_string())?;
+    }
+  }
+  Ok(())
+}
+
+fn worker_socket(socket_dir: &Path, index: usize) -> PathBuf {
+  socket_dir.join(format!("worker-{}", index))
+}
+
+fn connect_to_worker(socket_dir: &Path, index: usize) -> Result<UnixStream, String> {
+  let start = Instant::now();
+  loop {
+    match UnixStream::connect(worker_socket(socket_dir, index)) {
+      Ok(stream) => return Ok(stream),
+      Err(_) if start.elapsed() < CONNECT_TIMEOUT => std::thread::sleep(Duration::from_millis(10)),
+      Err(error) => return Err(format!("Failed to reach worker {}: {}", index, error)),
+    }
+  }
+}
+
+// Sends a row of blocks to the neighboring worker while receiving its row in return. Sending happens on a thread of
+// its own, as neither side would otherwise get to receiving once the rows outgrow the socket's buffer.
+fn exchange_rows(stream: &mut UnixStream, row: &[CellBlock], halo: &mut [CellBlock]) -> std::io::Result<()> {
+  let bytes: Vec<u8> = row.iter().flat_map(|block| block.to_le_bytes()).collect();
+  let mut received = vec![0; bytes.len()];
+  let mut sender = stream.try_clone()?;
+  std::thread::scope(|scope| {
+    let sent = scope.spawn(move || sender.write_all(&bytes));
+    let result = stream.read_exact(&mut received);
+    sent.join().unwrap().and(result)
+  })?;
+  for (block, bytes) in halo.iter_mut().zip(received.chunks_exact(size_of::<CellBlock>())) {
+    *block = CellBlock::from_le_bytes(bytes.try_into().unwrap());
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::random::Random;
+  use crate::rule::parse_rule;
+
+  const WIDTH_BLOCKS: usize = 5;
+  const HEIGHT_BLOCKS: usize = 10;
+
+  // Steps the board a strip at a time, each with the rows of blocks just past it as its neighbors last had them, and
+  // checks every generation against stepping the whole board at once.
+  fn assert_strips_step_like_the_board<R: BlockRule + Sync>(rule: &R, strips: &[std::ops::Range<usize>]) {
+    let mut random = Random::seeded(3);
+    let mut board: Vec<CellBlock> = (0..WIDTH_BLOCKS * HEIGHT_BLOCKS).map(|_| random.block()).collect();
+    let mut next_board = vec![EMPTY_BLOCK; board.len()];
+    let mut strip_boards: Vec<Vec<CellBlock>> = strips.iter().map(|rows| board[rows.start * WIDTH_BLOCKS..rows.end * WIDTH_BLOCKS].to_vec()).collect();
+    let empty_row = vec![EMPTY_BLOCK; WIDTH_BLOCKS];
+    for generation in 1..=30 {
+      conway::compute_next_board_state::<WIDTH_BLOCKS, _>(&board, &mut next_board, rule);
+      std::mem::swap(&mut board, &mut next_board);
+
+      let halos: Vec<[Vec<CellBlock>; 2]> = (0..strips.len()).map(|index| [
+        index.checked_sub(1).map_or(empty_row.clone(), |above| strip_boards[above][strip_boards[above].len() - WIDTH_BLOCKS..].to_vec()),
+        strip_boards.get(index + 1).map_or(empty_row.clone(), |below| below[..WIDTH_BLOCKS].to_vec()),
+      ]).collect();
+      for (strip, [halo_above, halo_below]) in strip_boards.iter_mut().zip(&halos) {
+        let mut next_strip = vec![EMPTY_BLOCK; strip.len()];
+        conway::compute_next_strip_state::<WIDTH_BLOCKS, _>(strip, &mut next_strip, [halo_above, halo_below], rule);
+        *strip = next_strip;
+      }
+      assert_eq!(strip_boards.concat(), board, "generation {}", generation);
+    }
+  }
+
+  #[test]
+  fn strips_step_like_the_whole_board() {
+    // A strip of a single row of blocks has both of its halos along the same row.
+    let strips = [0..3, 3..4, 4..HEIGHT_BLOCKS];
+    assert_strips_step_like_the_board(&Conway, &strips);
+    let Ok(Rule::Table(high_life)) = parse_rule("B36/S23") else { unreachable!() };
+    assert_strips_step_like_the_board(&high_life, &strips);
+  }
+
+  #[test]
+  fn strips_cover_the_board() {
+    for workers in [1, 3, 7, 64] {
+      let strips: Vec<_> = (0..workers).map(|index| strip_rows(index, workers)).collect();
+      assert_eq!(strips[0].start, 0);
+      assert_eq!(strips[workers - 1].end, BOARD_HEIGHT_BLOCKS);
+      assert!(strips.windows(2).all(|pair| pair[0].end == pair[1].start && !pair[0].is_empty()));
+    }
+  }
+
+  // Rows far bigger than a socket's buffer still get across both ways at once.
+  #[test]
+  fn rows_exchange_both_ways() {
+    let mut random = Random::seeded(4);
+    let rows: [Vec<CellBlock>; 2] = std::array::from_fn(|_| (0..100_000).map(|_| random.block()).collect());
+    let (mut upper, mut lower) = UnixStream::pair().unwrap();
+    let mut halos = [vec![EMPTY_BLOCK; rows[0].len()], vec![EMPTY_BLOCK; rows[1].len()]];
+    let [upper_halo, lower_halo] = &mut halos;
+    std::thread::scope(|scope| {
+      let upper = scope.spawn(|| exchange_rows(&mut upper, &rows[0], upper_halo));
+      exchange_rows(&mut lower, &rows[1], lower_halo).unwrap();
+      upper.join().unwrap().unwrap();
+    });
+    assert_eq!(halos, [rows[1].clone(), rows[0].clone()]);
+  }
+}
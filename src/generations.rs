@@ -0,0 +1,249 @@
+use crate::conway::{CellBlock, CELLS_PER_BLOCK, EMPTY_BLOCK, FIRST_CELL};
+use crate::sparse;
+use crate::sparse::{SparseBoard, Tile, TileCoordinate, TileRule, TILE_WIDTH_BLOCKS};
+
+// The most refractory planes a board may need, enough for the 255 states a rule can describe.
+const MAX_REFRACTORY_PLANES: usize = 8;
+
+// A board for Generations rules, stored as bit-planes of sparse boards. Plane 0 holds the live cells and is stepped
+// with the same block neighborhoods as a two-state board. The remaining planes hold, in binary, how far each
+// refractory cell has decayed: 1 for state 2, 2 for state 3, and so on. Dead and live cells are zero in those planes.
+pub struct GenerationsBoard {
+  planes: Vec<SparseBoard>,
+  states: u8,
+}
+
+impl GenerationsBoard {
+  pub fn new(states: u8) -> Self {
+    assert!(states >= 2);
+    let refractory_planes = (u8::BITS - (states - 2).leading_zeros()) as usize;
+    GenerationsBoard {
+      planes: (0..1 + refractory_planes).map(|_| SparseBoard::new()).collect(),
+      states,
+    }
+  }
+
+  pub fn get_cell(&self, x: i64, y: i64) -> u8 {
+    if self.planes[0].get_cell(x, y) {
+      return 1;
+    }
+    let decay = self.planes[1..].iter()
+        .enumerate()
+        .fold(0u8, |decay, (bit, plane)| decay | ((plane.get_cell(x, y) as u8) << bit));
+    if decay == 0 { 0 } else { decay + 1 }
+  }
+
+  pub fn set_cell(&mut self, x: i64, y: i64, state: u8) {
+    assert!(state < self.states);
+    self.planes[0].set_cell(x, y, state == 1);
+    let decay = state.saturating_sub(1);
+    for (bit, plane) in self.planes[1..].iter_mut().enumerate() {
+      plane.set_cell(x, y, (decay >> bit) & 1 == 1);
+    }
+  }
+
+  // Returns the state of every cell of the block at the given block coordinates, in the same order as a block's bits.
+  pub fn block_states(&self, block_x: i64, block_y: i64) -> [u8; CELLS_PER_BLOCK as usize] {
+    let blocks: Vec<CellBlock> = self.planes.iter().map(|plane| plane.block(block_x, block_y)).collect();
+    states_from_planes(&blocks)
+  }
+
+  pub fn population(&self) -> u64 {
+    self.planes[0].population()
+  }
+
+  pub fn tile_count(&self) -> usize {
+    self.planes[0].tile_count()
+  }
+
+  // The bit-planes the board stores each cell's state in.
+  pub fn planes(&self) -> &[SparseBoard] {
+    &self.planes
+  }
+
+  pub fn planes_mut(&mut self) -> &mut [SparseBoard] {
+    &mut self.planes
+  }
+
+  pub fn clear(&mut self) {
+    for plane in &mut self.planes {
+      plane.clear();
+    }
+  }
+}
+
+// Converts a block of each of a board's planes into the states of the block's cells.
+pub fn states_from_planes(blocks: &[CellBlock]) -> [u8; CELLS_PER_BLOCK as usize] {
+  let live = blocks[0];
+  let mut states = [0u8; CELLS_PER_BLOCK as usize];
+  for (bit, &block) in blocks[1..].iter().enumerate() {
+    if block == 0 {
+      continue;
+    }
+    for (cell, state) in states.iter_mut().enumerate() {
+      *state |= u8::from((block >> cell) & 1 == 1) << bit;
+    }
+  }
+  for (cell, state) in states.iter_mut().enumerate() {
+    *state = if (live >> cell) & 1 == 1 { 1 } else if *state == 0 { 0 } else { *state + 1 };
+  }
+  states
+}
+
+// Converts the states of a block's cells into a block of each of the given number of planes.
+pub fn planes_from_states(states: &[u8; CELLS_PER_BLOCK as usize], plane_count: usize) -> Vec<CellBlock> {
+  let mut blocks = vec![EMPTY_BLOCK; plane_count];
+  for (cell, &state) in states.iter().enumerate() {
+    if state == 1 {
+      blocks[0] |= FIRST_CELL << cell;
+    }
+    let decay = state.saturating_sub(1);
+    for (bit, block) in blocks[1..].iter_mut().enumerate() {
+      *block |= CellBlock::from((decay >> bit) & 1) << cell;
+    }
+  }
+  blocks
+}
+
+// Steps the live cells with the given rule, and decays every cell that fails to survive through the board's
+// refractory states.
+pub fn compute_next_board_state<R: TileRule>(source: &GenerationsBoard, destination: &mut GenerationsBoard, rule: &R) {
+  assert_eq!(source.states, destination.states);
+
+  // Refractory cells keep decaying even without live neighbors, so their tiles are always candidates.
+  let mut candidates = source.planes[0].candidate_tiles(rule.range());
+  for plane in &source.planes[1..] {
+    candidates.extend(plane.tiles().map(|(&coordinate, _)| coordinate));
+  }
+  candidates.sort_unstable();
+  candidates.dedup();
+
+  destination.clear();
+  for (coordinate, tiles) in sparse::compute_tiles_in_parallel(&candidates, |coordinate| new_value_for_tile(source, coordinate, rule)) {
+    for (plane, tile) in destination.planes.iter_mut().zip(tiles) {
+      plane.insert_tile(coordinate, tile);
+    }
+  }
+}
+
+fn new_value_for_tile<R: TileRule>(board: &GenerationsBoard, tile_coordinate: TileCoordinate, rule: &R) -> Vec<Box<Tile>> {
+  let live_tiles = board.planes[0].tile_neighborhood(tile_coordinate);
+  let refractory_tiles: Vec<Option<&Tile>> = board.planes[1..].iter().map(|plane| plane.tile(tile_coordinate)).collect();
+  let last_decay = board.states.saturating_sub(2);
+
+  let mut new_tiles: Vec<Box<Tile>> = board.planes.iter().map(|_| sparse::new_tile()).collect();
+  let next_tile = rule.new_value_for_tile(&live_tiles);
+  let mut refractory = [EMPTY_BLOCK; MAX_REFRACTORY_PLANES];
+  for block_index in 0..new_tiles[0].len() {
+    let block_x = (block_index % TILE_WIDTH_BLOCKS) as i64;
+    let block_y = (block_index / TILE_WIDTH_BLOCKS) as i64;
+
+    let alive = live_tiles.block(block_x, block_y);
+    let next = next_tile[block_index];
+    let mut decaying = EMPTY_BLOCK;
+    for (plane, tile) in refractory.iter_mut().zip(&refractory_tiles) {
+      *plane = tile.map_or(EMPTY_BLOCK, |tile| tile[block_index]);
+      decaying |= *plane;
+    }
+    if decaying == 0 && alive == 0 && next == 0 {
+      continue;
+    }
+
+    let survived = next & alive;
+    let born = next & !alive & !decaying;
+    new_tiles[0][block_index] = survived | born;
+
+    // Advance every refractory cell by one state, dropping the ones that were in the last state back to dead.
+    let refractory_planes = &mut refractory[..refractory_tiles.len()];
+    let mut expiring = decaying;
+    for (bit, plane) in refractory_planes.iter().enumerate() {
+      expiring &= if (last_decay >> bit) & 1 == 1 { *plane } else { !*plane };
+    }
+    let mut carry = decaying & !expiring;
+    for plane in refractory_planes.iter_mut() {
+      let sum = *plane ^ carry;
+      carry &= *plane;
+      *plane = sum & !expiring;
+    }
+
+    // Live cells that fail to survive enter the first refractory state.
+    if let Some(first_plane) = refractory_planes.first_mut() {
+      *first_plane |= alive & !survived;
+    }
+
+    for (tile, plane) in new_tiles[1..].iter_mut().zip(refractory_planes.iter()) {
+      tile[block_index] = *plane;
+    }
+  }
+  new_tiles
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::rule::{parse_table_rule, TableRule};
+
+  fn step(board: &GenerationsBoard, rule: &TableRule) -> GenerationsBoard {
+    let mut next = GenerationsBoard::new(rule.states);
+    compute_next_board_state(board, &mut next, rule);
+    next
+  }
+
+  // Steps a square of cells the way Generations rules are usually described, with only state 1 counting as a neighbor.
+  fn step_cells(cells: &[Vec<u8>], rule: &TableRule) -> Vec<Vec<u8>> {
+    let size = cells.len() as i64;
+    let state = |x: i64, y: i64| if (0..size).contains(&x) && (0..size).contains(&y) { cells[y as usize][x as usize] } else { 0 };
+    (0..size).map(|y| (0..size).map(|x| {
+      let neighbor_mask = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)].iter().enumerate()
+          .fold(0u8, |mask, (bit, &(dx, dy))| mask | ((state(x + dx, y + dy) == 1) as u8) << bit);
+      match state(x, y) {
+        0 => rule.table[neighbor_mask as usize],
+        1 if rule.table[256 + neighbor_mask as usize] == 1 => 1,
+        decaying if decaying + 1 < rule.states => decaying + 1,
+        _ => 0,
+      }
+    }).collect()).collect()
+  }
+
+  #[test]
+  fn lone_cells_decay_through_every_state() {
+    let rule = parse_table_rule("B2/S/C5").unwrap();
+    let mut board = GenerationsBoard::new(rule.states);
+    board.set_cell(-3, 7, 1);
+    for state in [2, 3, 4, 0] {
+      board = step(&board, &rule);
+      assert_eq!(board.get_cell(-3, 7), state);
+    }
+    assert_eq!(board.tile_count(), 0);
+  }
+
+  #[test]
+  fn steps_like_cells() {
+    for rule in ["B2/S/C3", "345/2/4", "B3/S23/C11"] {
+      let rule = parse_table_rule(rule).unwrap();
+      let size = 60;
+      let mut cells: Vec<Vec<u8>> = (0..size)
+          .map(|y| (0..size).map(|x| {
+            let inside = (22..38).contains(&x) && (22..38).contains(&y);
+            if inside && (x * 7 + y * 13 + x * y) % 5 < 2 { 1 } else { 0 }
+          }).collect())
+          .collect();
+      let mut board = GenerationsBoard::new(rule.states);
+      for (y, row) in cells.iter().enumerate() {
+        for (x, &state) in row.iter().enumerate() {
+          board.set_cell(x as i64 - 30, y as i64 - 30, state);
+        }
+      }
+
+      for generation in 1..=20 {
+        board = step(&board, &rule);
+        cells = step_cells(&cells, &rule);
+        for (y, row) in cells.iter().enumerate() {
+          for (x, &state) in row.iter().enumerate() {
+            assert_eq!(board.get_cell(x as i64 - 30, y as i64 - 30), state, "cell ({}, {}) at generation {}", x, y, generation);
+          }
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,435 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::conway::{CellBlock, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK};
+use crate::metadata::Metadata;
+use crate::sparse::{TileCoordinate, TILE_HEIGHT_BLOCKS, TILE_WIDTH_BLOCKS};
+use crate::universe::{BlockCoordinate, PlaneBlock, Universe};
+
+// A full copy of the board is kept every this many states, so that states far from the current one can be restored
+// without walking every delta in between.
+const SNAPSHOT_INTERVAL: u64 = 64;
+
+// The most memory the packed deltas and snapshots may take, past which the oldest states are forgotten.
+const MAX_HISTORY_BYTES: usize = 256 * 1024 * 1024;
+
+#[derive(Clone)]
+struct State {
+  generation: u64,
+  // Packed blocks of the whole board, for every SNAPSHOT_INTERVAL'th state.
+  snapshot: Option<Vec<u8>>,
+}
+
+// The states the universe went through, whether by stepping or by editing cells, so that it can be moved back and
+// forth between them. Consecutive states are linked by the cells that flipped between them, which take the universe
+// from either state to the other. Moving back and then stepping or editing forgets the states that came after.
+//
+// Every board keeps its steps, the dense one included. Its flipped cells are found by comparing the two buffers in
+// parallel right after each step, and only every SNAPSHOT_INTERVAL'th state packs the whole board.
+pub struct History {
+  // The state the history started in, kept even once it has been forgotten so that the universe can always be reset.
+  initial: State,
+  states: VecDeque<State>,
+  // deltas[i] holds the packed blocks of cells that flip between states[i] and states[i + 1].
+  deltas: VecDeque<Vec<u8>>,
+  // The index of the state the universe is in.
+  position: usize,
+  // How many states have been forgotten, which also tells whether states[0] is still the initial state.
+  forgotten: u64,
+  bytes: usize,
+  // The cells flipped so far by an edit in progress, by plane and block.
+  edit: HashMap<(usize, BlockCoordinate), CellBlock>,
+  // Fed the cells flipped by every step and edit, while it is being tracked.
+  metadata: Option<Metadata>,
+  // The tiles holding any cells flipped since they were last taken, so that whatever shows the occupied tiles only has
+  // to look through those again. None if the whole board may have changed, as it does when restoring a snapshot.
+  touched_tiles: Option<HashSet<TileCoordinate>>,
+}
+
+impl History {
+  pub fn new(universe: &Universe) -> Self {
+    let initial = State { generation: universe.generation, snapshot: Some(pack(universe.occupied_blocks())) };
+    let bytes = initial.snapshot.as_ref().map_or(0, |snapshot| snapshot.len());
+    History {
+      initial: initial.clone(),
+      states: VecDeque::from([initial]),
+      deltas: VecDeque::new(),
+      position: 0,
+      forgotten: 0,
+      bytes,
+      edit: HashMap::new(),
+      metadata: None,
+      touched_tiles: None,
+    }
+  }
+
+  // Starts or stops tracking the age and activity of every cell, from the state the universe is in.
+  pub fn track_metadata(&mut self, universe: &Universe, enabled: bool) {
+    if !enabled {
+      self.metadata = None;
+    } else if self.metadata.is_none() {
+      self.metadata = Some(Metadata::new(universe.generation));
+    }
+  }
+
+  pub fn metadata(&self) -> Option<&Metadata> {
+    self.metadata.as_ref()
+  }
+
+  // Takes the tiles holding any cells flipped since the last time, or None if the whole board may have changed.
+  pub fn take_touched_tiles(&mut self) -> Option<HashSet<TileCoordinate>> {
+    self.touched_tiles.replace(HashSet::new())
+  }
+
+  // Moves the universe to the next generation, replaying it from the history if the universe was moved back.
+  pub fn step(&mut self, universe: &mut Universe) {
+    if self.forward(universe) {
+      return;
+    }
+    universe.step();
+    self.push(universe, universe.changed_blocks());
+  }
+
+  // Sets a cell as part of the edit in progress, which becomes a single state once finished.
+  pub fn edit_cell(&mut self, universe: &mut Universe, x: i64, y: i64, state: u8) {
+    let block_x = x.div_euclid(CELL_BLOCK_WIDTH as i64);
+    let block_y = y.div_euclid(CELL_BLOCK_HEIGHT as i64);
+    let before: Vec<CellBlock> = (0..universe.plane_count()).map(|plane| universe.block(plane, block_x, block_y)).collect();
+    universe.set_cell(x, y, state);
+    for (plane, before) in before.into_iter().enumerate() {
+      let cells = before ^ universe.block(plane, block_x, block_y);
+      if cells != 0 {
+        *self.edit.entry((plane, (block_x, block_y))).or_insert(EMPTY_BLOCK) ^= cells;
+        self.touch(&[(plane, (block_x, block_y), cells)]);
+      }
+    }
+  }
+
+  // Sets a whole block of one of the board's planes as part of the edit in progress.
+  pub fn edit_block(&mut self, universe: &mut Universe, plane: usize, block_x: i64, block_y: i64, cells: CellBlock) {
+    let changed = universe.block(plane, block_x, block_y) ^ cells;
+    if changed != 0 {
+      universe.xor_block(plane, block_x, block_y, changed);
+      *self.edit.entry((plane, (block_x, block_y))).or_insert(EMPTY_BLOCK) ^= changed;
+      self.touch(&[(plane, (block_x, block_y), changed)]);
+    }
+  }
+
+  pub fn finish_edit(&mut self, universe: &Universe) {
+    let changed: Vec<PlaneBlock> = self.edit.drain()
+        .filter(|&(_, cells)| cells != 0)
+        .map(|((plane, coordinate), cells)| (plane, coordinate, cells))
+        .collect();
+    if !changed.is_empty() {
+      self.push(universe, changed);
+    }
+  }
+
+  // Moves the universe to the state before the current one. Returns false if there is none left.
+  pub fn back(&mut self, universe: &mut Universe) -> bool {
+    self.finish_edit(universe);
+    if self.position == 0 {
+      return false;
+    }
+    self.seek(universe, self.position - 1);
+    true
+  }
+
+  // Moves the universe to the state after the current one. Returns false if it is already in the latest state.
+  pub fn forward(&mut self, universe: &mut Universe) -> bool {
+    self.finish_edit(universe);
+    if self.position + 1 >= self.states.len() {
+      return false;
+    }
+    self.seek(universe, self.position + 1);
+    true
+  }
+
+  pub fn reset(&mut self, universe: &mut Universe) {
+    self.finish_edit(universe);
+    if self.forgotten == 0 {
+      self.seek(universe, 0);
+      return;
+    }
+
+    // The initial state has been forgotten, so it is restored from its own snapshot and the history starts over.
+    restore(universe, &self.initial);
+    self.touched_tiles = None;
+    self.states = VecDeque::from([self.initial.clone()]);
+    self.deltas.clear();
+    self.position = 0;
+    self.forgotten = 0;
+    self.bytes = self.initial.snapshot.as_ref().map_or(0, |snapshot| snapshot.len());
+    if self.metadata.is_some() {
+      self.metadata = Some(Metadata::new(universe.generation));
+    }
+  }
+
+  // Adds the state the universe is in, reached from the current state by flipping the given cells.
+  fn push(&mut self, universe: &Universe, changed: Vec<PlaneBlock>) {
+    for state in self.states.drain(self.position + 1..) {
+      self.bytes -= state.snapshot.map_or(0, |snapshot| snapshot.len());
+    }
+    for delta in self.deltas.drain(self.position..) {
+      self.bytes -= delta.len();
+    }
+
+    if let Some(metadata) = &mut self.metadata {
+      metadata.record(universe.generation, &changed);
+    }
+    self.touch(&changed);
+    let delta = pack(changed);
+    self.bytes += delta.len();
+    self.deltas.push_back(delta);
+    let is_snapshot = (self.forgotten + self.states.len() as u64).is_multiple_of(SNAPSHOT_INTERVAL);
+    let snapshot = is_snapshot.then(|| pack(universe.occupied_blocks()));
+    self.bytes += snapshot.as_ref().map_or(0, |snapshot| snapshot.len());
+    self.states.push_back(State { generation: universe.generation, snapshot });
+    self.position += 1;
+
+    while self.bytes > MAX_HISTORY_BYTES && self.states.len() > 1 {
+      let state = self.states.pop_front().unwrap();
+      self.bytes -= state.snapshot.map_or(0, |snapshot| snapshot.len());
+      self.bytes -= self.deltas.pop_front().unwrap().len();
+      self.forgotten += 1;
+      self.position -= 1;
+    }
+  }
+
+  // Starts from whichever of the current state and the snapshots is closest to the target, and walks the deltas from
+  // there, in either direction.
+  fn seek(&mut self, universe: &mut Universe, target: usize) {
+    // Stepping forward through the history flips the same cells as the step did in the first place, but moving back
+    // loses track of when cells changed, so tracking starts over from the target instead.
+    if let Some(metadata) = &mut self.metadata {
+      if target == self.position + 1 {
+        metadata.record(self.states[target].generation, &unpack(&self.deltas[self.position]));
+      } else {
+        *metadata = Metadata::new(self.states[target].generation);
+      }
+    }
+
+    let mut index = self.position;
+    for (snapshot_index, state) in self.states.iter().enumerate() {
+      if state.snapshot.is_some() && snapshot_index.abs_diff(target) < index.abs_diff(target) {
+        index = snapshot_index;
+      }
+    }
+    if index != self.position {
+      restore(universe, &self.states[index]);
+      self.touched_tiles = None;
+    }
+
+    while index < target {
+      let changed = apply(universe, &self.deltas[index]);
+      self.touch(&changed);
+      index += 1;
+    }
+    while index > target {
+      index -= 1;
+      let changed = apply(universe, &self.deltas[index]);
+      self.touch(&changed);
+    }
+    self.position = target;
+    universe.generation = self.states[target].generation;
+  }
+
+  fn touch(&mut self, changed: &[PlaneBlock]) {
+    if let Some(touched_tiles) = &mut self.touched_tiles {
+      touched_tiles.extend(changed.iter().map(|&(_, (block_x, block_y), _)| {
+        (block_x.div_euclid(TILE_WIDTH_BLOCKS as i64), block_y.div_euclid(TILE_HEIGHT_BLOCKS as i64))
+      }));
+    }
+  }
+}
+
+fn restore(universe: &mut Universe, state: &State) {
+  universe.clear();
+  apply(universe, state.snapshot.as_ref().unwrap());
+  universe.generation = state.generation;
+}
+
+// Flips the packed blocks of cells, returning them.
+fn apply(universe: &mut Universe, packed: &[u8]) -> Vec<PlaneBlock> {
+  let blocks = unpack(packed);
+  for &(plane, (block_x, block_y), cells) in &blocks {
+    universe.xor_block(plane, block_x, block_y, cells);
+  }
+  blocks
+}
+
+// Packs blocks in order of plane, row and column. Each block is written as varints of its plane and coordinates
+// relative to the block before it, then a varint with a bit set for every non-zero byte of its cells, followed by those
+// bytes. Changes between generations tend to be small and clustered, so most blocks take only a few bytes.
+fn pack(mut blocks: Vec<PlaneBlock>) -> Vec<u8> {
+  blocks.sort_unstable_by_key(|&(plane, (x, y), _)| (plane, y, x));
+  let mut bytes = Vec::new();
+  let (mut plane, mut x, mut y) = (0, 0, 0);
+  for (block_plane, (block_x, block_y), cells) in blocks {
+    write_varint(&mut bytes, (block_plane - plane) as u64);
+    write_varint(&mut bytes, zigzag(block_x - x));
+    write_varint(&mut bytes, zigzag(block_y - y));
+    (plane, x, y) = (block_plane, block_x, block_y);
+
+    let cell_bytes = cells.to_le_bytes();
+    let occupied_bytes = cell_bytes.iter()
+        .enumerate()
+        .fold(0u64, |occupied, (index, &byte)| occupied | (((byte != 0) as u64) << index));
+    write_varint(&mut bytes, occupied_bytes);
+    bytes.extend(cell_bytes.iter().filter(|&&byte| byte != 0));
+  }
+  bytes
+}
+
+fn unpack(bytes: &[u8]) -> Vec<PlaneBlock> {
+  let mut blocks = Vec::new();
+  let mut offset = 0;
+  let (mut plane, mut x, mut y) = (0, 0, 0);
+  while offset < bytes.len() {
+    plane += read_varint(bytes, &mut offset) as usize;
+    x += unzigzag(read_varint(bytes, &mut offset));
+    y += unzigzag(read_varint(bytes, &mut offset));
+
+    let occupied_bytes = read_varint(bytes, &mut offset);
+    let mut cell_bytes = [0u8; size_of::<CellBlock>()];
+    for (index, byte) in cell_bytes.iter_mut().enumerate() {
+      if (occupied_bytes >> index) & 1 == 1 {
+        *byte = bytes[offset];
+        offset += 1;
+      }
+    }
+    blocks.push((plane, (x, y), CellBlock::from_le_bytes(cell_bytes)));
+  }
+  blocks
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    bytes.push((value as u8) | 0x80);
+    value >>= 7;
+  }
+  bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> u64 {
+  let mut value = 0;
+  let mut shift = 0;
+  loop {
+    let byte = bytes[*offset];
+    *offset += 1;
+    value |= ((byte & 0x7F) as u64) << shift;
+    if byte & 0x80 == 0 {
+      return value;
+    }
+    shift += 7;
+  }
+}
+
+// Maps signed values to unsigned ones so that small values of either sign stay small: 0, -1, 1, -2 become 0, 1, 2, 3.
+fn zigzag(value: i64) -> u64 {
+  ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+  ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::conway::FIRST_CELL;
+  use crate::generations::GenerationsBoard;
+  use crate::rule::{parse_rule, Rule};
+  use crate::sparse::SparseBoard;
+  use crate::universe::Buffers;
+
+  fn cells(universe: &Universe) -> Vec<PlaneBlock> {
+    let mut blocks = universe.occupied_blocks();
+    blocks.sort_unstable_by_key(|&(plane, (x, y), _)| (plane, y, x));
+    blocks
+  }
+
+  // Steps past a few snapshots, then moves back and forth through the generations and edits, which must bring back
+  // exactly the cells each state had.
+  fn assert_rewinds(mut universe: Universe) {
+    for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+      universe.set_cell(x, y, 1);
+    }
+    let mut history = History::new(&universe);
+    let mut states = vec![cells(&universe)];
+    for _ in 0..150 {
+      history.step(&mut universe);
+      states.push(cells(&universe));
+    }
+
+    for target in [149, 70, 64, 3, 0] {
+      while universe.generation > target {
+        assert!(history.back(&mut universe));
+      }
+      assert_eq!(cells(&universe), states[target as usize], "generation {}", target);
+    }
+    assert!(!history.back(&mut universe));
+    for _ in 0..100 {
+      history.step(&mut universe);
+    }
+    assert_eq!((universe.generation, cells(&universe)), (100, states[100].clone()));
+
+    history.edit_cell(&mut universe, -40, 33, 1);
+    history.edit_cell(&mut universe, -41, 33, 1);
+    history.finish_edit(&universe);
+    assert_ne!(cells(&universe), states[100]);
+    assert!(history.back(&mut universe));
+    assert_eq!(cells(&universe), states[100]);
+    assert!(history.forward(&mut universe));
+    assert!(!history.forward(&mut universe));
+
+    history.reset(&mut universe);
+    assert_eq!((universe.generation, cells(&universe)), (0, states[0].clone()));
+  }
+
+  #[test]
+  fn rewinds_steps_and_edits() {
+    assert_rewinds(Universe::new(Buffers::Sparse(SparseBoard::new(), SparseBoard::new()), None));
+    let Ok(Rule::Table(rule)) = parse_rule("B2/S345/C4") else { unreachable!() };
+    let buffers = Buffers::Generations(GenerationsBoard::new(rule.states), GenerationsBoard::new(rule.states));
+    assert_rewinds(Universe::new(buffers, Some(Rule::Table(rule))));
+  }
+
+  #[test]
+  fn varints_round_trip() {
+    let values = [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u32::MAX as u64, u64::MAX];
+    let mut bytes = Vec::new();
+    for value in values {
+      write_varint(&mut bytes, value);
+    }
+    assert_eq!(bytes[..5], [0, 1, 0x7f, 0x80, 0x01]);
+    let mut offset = 0;
+    for value in values {
+      assert_eq!(read_varint(&bytes, &mut offset), value);
+    }
+    assert_eq!(offset, bytes.len());
+  }
+
+  #[test]
+  fn zigzag_round_trips() {
+    assert_eq!([0, -1, 1, -2, 2].map(zigzag), [0, 1, 2, 3, 4]);
+    for value in [i64::MIN, -1000, 0, 1000, i64::MAX] {
+      assert_eq!(unzigzag(zigzag(value)), value);
+    }
+  }
+
+  #[test]
+  fn packed_blocks_round_trip() {
+    // One byte each for the plane, the coordinates and which bytes are occupied, and one for the only occupied byte.
+    assert_eq!(pack(vec![(0, (0, 0), FIRST_CELL)]), [0, 0, 0, 1, 1]);
+    let blocks: Vec<PlaneBlock> = vec![
+      (1, (-3, 7), FIRST_CELL << (CellBlock::BITS - 1)),
+      (0, (5, -2), CellBlock::from(0x0102u64)),
+      (0, (i64::MIN / 2, 0), CellBlock::MAX),
+      (0, (4, -2), CellBlock::from(0xffu8) << 8),
+      (2, (0, 0), EMPTY_BLOCK),
+    ];
+    let packed = pack(blocks.clone());
+    let mut sorted = blocks;
+    sorted.sort_unstable_by_key(|&(plane, (x, y), _)| (plane, y, x));
+    assert_eq!(unpack(&packed), sorted);
+  }
+}
@@ -0,0 +1,328 @@
+use std::fmt;
+
+// Arrays and objects nested deeper than this are refused, as parsing them recurses once per level.
+const MAX_DEPTH: usize = 64;
+
+// Just enough JSON for the commands scripts send and the replies they get back. Objects keep their keys in the order
+// they were written, and numbers are kept as doubles, which hold every cell coordinate a pattern can reach.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+impl Json {
+  pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+    Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+  }
+
+  // The value of a field of an object, where missing fields and anything that is not an object give None.
+  pub fn get(&self, key: &str) -> Option<&Json> {
+    match self {
+      Json::Object(fields) => fields.iter().find(|(field, _)| field == key).map(|(_, value)| value),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Json::String(text) => Some(text),
+      _ => None,
+    }
+  }
+
+  // Numbers with a fractional part are not integers.
+  pub fn as_i64(&self) -> Option<i64> {
+    match self {
+      Json::Number(number) if number.fract() == 0.0 && number.abs() < (1u64 << 53) as f64 => Some(*number as i64),
+      _ => None,
+    }
+  }
+}
+
+impl From<bool> for Json {
+  fn from(value: bool) -> Self {
+    Json::Bool(value)
+  }
+}
+
+impl From<i64> for Json {
+  fn from(value: i64) -> Self {
+    Json::Number(value as f64)
+  }
+}
+
+impl From<u64> for Json {
+  fn from(value: u64) -> Self {
+    Json::Number(value as f64)
+  }
+}
+
+impl From<&str> for Json {
+  fn from(value: &str) -> Self {
+    Json::String(value.to_string())
+  }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+  fn from(value: Option<T>) -> Self {
+    value.map_or(Json::Null, Into::into)
+  }
+}
+
+// Writes the value on a single line, so that replies can be told apart by their newlines.
+impl fmt::Display for Json {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Json::Null => write!(formatter, "null"),
+      Json::Bool(value) => write!(formatter, "{}", value),
+      // JSON has no way to write infinities or NaN.
+      Json::Number(number) if !number.is_finite() => write!(formatter, "null"),
+      Json::Number(number) => write!(formatter, "{}", number),
+      Json::String(text) => write_string(formatter, text),
+      Json::Array(values) => {
+        write!(formatter, "[")?;
+        for (index, value) in values.iter().enumerate() {
+          write!(formatter, "{}{}", if index == 0 { "" } else { "," }, value)?;
+        }
+        write!(formatter, "]")
+      }
+      Json::Object(fields) => {
+        write!(formatter, "{{")?;
+        for (index, (key, value)) in fields.iter().enumerate() {
+          write!(formatter, "{}", if index == 0 { "" } else { "," })?;
+          write_string(formatter, key)?;
+          write!(formatter, ":{}", value)?;
+        }
+        write!(formatter, "}}")
+      }
+    }
+  }
+}
+
+fn write_string(formatter: &mut fmt::Formatter, text: &str) -> fmt::Result {
+  write!(formatter, "\"")?;
+  for character in text.chars() {
+    match character {
+      '"' => write!(formatter, "\\\"")?,
+      '\\' => write!(formatter, "\\\\")?,
+      '\n' => write!(formatter, "\\n")?,
+      '\r' => write!(formatter, "\\r")?,
+      '\t' => write!(formatter, "\\t")?,
+      character if (character as u32) < 0x20 => write!(formatter, "\\u{:04x}", character as u32)?,
+      character => write!(formatter, "{}", character)?,
+    }
+  }
+  write!(formatter, "\"")
+}
+
+pub fn parse(text: &str) -> Result<Json, String> {
+  let mut parser = Parser { text: text.as_bytes(), position: 0, depth: 0 };
+  let value = parser.value()?;
+  parser.skip_whitespace();
+  if parser.position < parser.text.len() {
+    return Err(parser.error("Unexpected text after the value"));
+  }
+  Ok(value)
+}
+
+struct Parser<'a> {
+  text: &'a [u8],
+  position: usize,
+  // How many arrays and objects the value being parsed is inside of.
+  depth: usize,
+}
+
+impl Parser<'_> {
+  fn error(&self, message: &str) -> String {
+    format!("{} at offset {}", message, self.position)
+  }
+
+  fn skip_whitespace(&mut self) {
+    while self.text.get(self.position).is_some_and(|byte| byte.is_ascii_whitespace()) {
+      self.position += 1;
+    }
+  }
+
+  fn expect(&mut self, expected: &str) -> Result<(), String> {
+    if !self.text[self.position..].starts_with(expected.as_bytes()) {
+      return Err(self.error(&format!("Expected \"{}\"", expected)));
+    }
+    self.position += expected.len();
+    Ok(())
+  }
+
+  fn value(&mut self) -> Result<Json, String> {
+    self.skip_whitespace();
+    match self.text.get(self.position) {
+      None => Err(self.error("Unexpected end of text")),
+      Some(b'n') => self.expect("null").map(|_| Json::Null),
+      Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+      Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+      Some(b'"') => self.string().map(Json::String),
+      Some(&opening @ (b'[' | b'{')) => {
+        if self.depth == MAX_DEPTH {
+          return Err(self.error(&format!("Nested more than {} deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value = if opening == b'[' { self.array() } else { self.object() };
+        self.depth -= 1;
+        value
+      }
+      Some(_) => self.number(),
+    }
+  }
+
+  fn array(&mut self) -> Result<Json, String> {
+    self.position += 1;
+    let mut values = Vec::new();
+    self.skip_whitespace();
+    if self.text.get(self.position) == Some(&b']') {
+      self.position += 1;
+      return Ok(Json::Array(values));
+    }
+    loop {
+      values.push(self.value()?);
+      self.skip_whitespace();
+      match self.text.get(self.position) {
+        Some(b',') => self.position += 1,
+        Some(b']') => break,
+        _ => return Err(self.error("Expected \",\" or \"]\"")),
+      }
+    }
+    self.position += 1;
+    Ok(Json::Array(values))
+  }
+
+  fn object(&mut self) -> Result<Json, String> {
+    self.position += 1;
+    let mut fields = Vec::new();
+    self.skip_whitespace();
+    if self.text.get(self.position) == Some(&b'}') {
+      self.position += 1;
+      return Ok(Json::Object(fields));
+    }
+    loop {
+      self.skip_whitespace();
+      if self.text.get(self.position) != Some(&b'"') {
+        return Err(self.error("Expected a key"));
+      }
+      let key = self.string()?;
+      self.skip_whitespace();
+      self.expect(":")?;
+      fields.push((key, self.value()?));
+      self.skip_whitespace();
+      match self.text.get(self.position) {
+        Some(b',') => self.position += 1,
+        Some(b'}') => break,
+        _ => return Err(self.error("Expected \",\" or \"}\"")),
+      }
+    }
+    self.position += 1;
+    Ok(Json::Object(fields))
+  }
+
+  fn number(&mut self) -> Result<Json, String> {
+    let start = self.position;
+    while self.text.get(self.position).is_some_and(|&byte| byte.is_ascii_digit() || b"+-.eE".contains(&byte)) {
+      self.position += 1;
+    }
+    // The text is only ASCII digits and signs here.
+    let number = std::str::from_utf8(&self.text[start..self.position]).unwrap();
+    number.parse().map(Json::Number).map_err(|_| {
+      self.position = start;
+      self.error("Expected a value")
+    })
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    self.position += 1;
+    let mut bytes = Vec::new();
+    loop {
+      let Some(&byte) = self.text.get(self.position) else {
+        return Err(self.error("Unterminated string"));
+      };
+      self.position += 1;
+      match byte {
+        b'"' => break,
+        b'\\' => {
+          let escaped = *self.text.get(self.position).ok_or_else(|| self.error("Unterminated string"))?;
+          self.position += 1;
+          let character = match escaped {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => self.unicode_escape()?,
+            _ => return Err(self.error("Unknown escape")),
+          };
+          bytes.extend_from_slice(character.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        byte => bytes.push(byte),
+      }
+    }
+    String::from_utf8(bytes).map_err(|_| self.error("Invalid UTF-8 in string"))
+  }
+
+  // Characters outside of the basic multilingual plane are escaped as a surrogate pair.
+  fn unicode_escape(&mut self) -> Result<char, String> {
+    let first = self.code_unit()?;
+    let code = if (0xd800..0xdc00).contains(&first) {
+      self.expect("\\u")?;
+      let second = self.code_unit()?;
+      if !(0xdc00..0xe000).contains(&second) {
+        return Err(self.error("Invalid unicode escape"));
+      }
+      0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00)
+    } else {
+      first
+    };
+    char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+  }
+
+  fn code_unit(&mut self) -> Result<u32, String> {
+    let digits = self.text.get(self.position..self.position + 4).and_then(|digits| std::str::from_utf8(digits).ok());
+    let code = digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()).ok_or_else(|| self.error("Invalid unicode escape"))?;
+    self.position += 4;
+    Ok(code)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips() {
+    let text = r#"{"command":"load","x":-12,"y":3.5,"rle":"bo$2bo$3o!\n","flags":[true,false,null],"nested":{}}"#;
+    let value = parse(text).unwrap();
+    assert_eq!(value.get("command").and_then(Json::as_str), Some("load"));
+    assert_eq!(value.get("x").and_then(Json::as_i64), Some(-12));
+    assert_eq!(value.get("y").and_then(Json::as_i64), None);
+    assert_eq!(value.to_string(), text);
+    assert_eq!(parse(" [ 1 , \"\\u00e9\\ud83d\\ude00\\t\" ] ").unwrap(), Json::Array(vec![Json::Number(1.0), "é😀\t".into()]));
+  }
+
+  #[test]
+  fn rejects_malformed_text() {
+    for text in ["", "{", "[1,]", "{\"a\" 1}", "{1: 2}", "nul", "1 2", "\"open", "\"\\x\"", "-", "\"\\ud83d\\u0041\"", "\"\\ude00\""] {
+      assert!(parse(text).is_err(), "{:?}", text);
+    }
+  }
+
+  #[test]
+  fn limits_nesting() {
+    let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(parse(&nested(MAX_DEPTH)).is_ok());
+    assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+    assert!(parse(&"[{\"a\":".repeat(100_000)).is_err());
+  }
+}
@@ -0,0 +1,242 @@
+use crate::conway::{Neighborhood, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, FIRST_CELL};
+use crate::sparse;
+use crate::sparse::{Tile, TileNeighborhood, TileRule, TILE_HEIGHT_CELLS, TILE_WIDTH_BLOCKS, TILE_WIDTH_CELLS};
+
+// Neighborhoods are gathered from the tiles adjacent to the one being computed, so they cannot reach any further.
+pub const MAX_RANGE: i64 = 500;
+
+const_assert!(MAX_RANGE <= TILE_WIDTH_CELLS && MAX_RANGE <= TILE_HEIGHT_CELLS);
+
+// Larger than Life rules count the live cells in a neighborhood of any range, and compare the count against inclusive
+// ranges for survival and birth. Moore neighborhoods cover every cell within the square of the given range, von Neumann
+// neighborhoods every cell within the given Manhattan distance, and hexagonal neighborhoods every cell within the given
+// distance on the hexagonal grid.
+#[derive(Clone)]
+pub struct LargerThanLifeRule {
+  pub range: i64,
+  pub states: u8,
+  pub include_middle: bool,
+  pub survival: (u32, u32),
+  pub birth: (u32, u32),
+  pub neighborhood: Neighborhood,
+}
+
+impl TileRule for LargerThanLifeRule {
+  fn range(&self) -> i64 {
+    self.range
+  }
+
+  fn new_value_for_tile(&self, tiles: &TileNeighborhood) -> Box<Tile> {
+    let mut tile = sparse::new_tile();
+    if tiles.is_empty() {
+      return tile;
+    }
+
+    let counts = match self.neighborhood {
+      Neighborhood::Moore => NeighborhoodCounts::moore(tiles, self.range),
+      neighborhood => NeighborhoodCounts::rows(tiles, self.range, neighborhood),
+    };
+    for y in 0..TILE_HEIGHT_CELLS {
+      for x in 0..TILE_WIDTH_CELLS {
+        let alive = tiles.cell(x, y);
+        let mut count = counts.count(x, y);
+        if !self.include_middle && alive {
+          count -= 1;
+        }
+        let (min, max) = if alive { self.survival } else { self.birth };
+        if count >= min && count <= max {
+          let block_index = (y as u64 / CELL_BLOCK_HEIGHT) as usize * TILE_WIDTH_BLOCKS + (x as u64 / CELL_BLOCK_WIDTH) as usize;
+          tile[block_index] |= FIRST_CELL << ((y as u64 % CELL_BLOCK_HEIGHT) * CELL_BLOCK_WIDTH + x as u64 % CELL_BLOCK_WIDTH);
+        }
+      }
+    }
+    tile
+  }
+}
+
+// Neighborhood counts for every cell of a tile. Moore neighborhoods are counted in constant time from a summed-area
+// table of the tile and its surrounding halo. Von Neumann and hexagonal neighborhoods are diamonds and sheared
+// hexagons, which are summed row by row from prefix sums of each row instead.
+struct NeighborhoodCounts {
+  range: i64,
+  width: i64,
+  sums: Vec<u32>,
+  neighborhood: Neighborhood,
+}
+
+impl NeighborhoodCounts {
+  fn moore(tiles: &TileNeighborhood, range: i64) -> Self {
+    let width = TILE_WIDTH_CELLS + 2 * range + 1;
+    let height = TILE_HEIGHT_CELLS + 2 * range + 1;
+    let mut sums = vec![0u32; (width * height) as usize];
+    for y in 1..height {
+      let mut row_sum = 0;
+      for x in 1..width {
+        row_sum += tiles.cell(x - 1 - range, y - 1 - range) as u32;
+        sums[(y * width + x) as usize] = sums[((y - 1) * width + x) as usize] + row_sum;
+      }
+    }
+    NeighborhoodCounts { range, width, sums, neighborhood: Neighborhood::Moore }
+  }
+
+  fn rows(tiles: &TileNeighborhood, range: i64, neighborhood: Neighborhood) -> Self {
+    let width = TILE_WIDTH_CELLS + 2 * range + 1;
+    let height = TILE_HEIGHT_CELLS + 2 * range;
+    let mut sums = vec![0u32; (width * height) as usize];
+    for y in 0..height {
+      for x in 1..width {
+        sums[(y * width + x) as usize] = sums[(y * width + x - 1) as usize] + tiles.cell(x - 1 - range, y - range) as u32;
+      }
+    }
+    NeighborhoodCounts { range, width, sums, neighborhood }
+  }
+
+  // Counts the live cells in the neighborhood of the cell at the given offset within the tile, including the cell.
+  fn count(&self, x: i64, y: i64) -> u32 {
+    let sum = |x: i64, y: i64| self.sums[(y * self.width + x) as usize];
+    let range = self.range;
+    match self.neighborhood {
+      Neighborhood::Moore => {
+        let (left, top, right, bottom) = (x, y, x + 2 * range + 1, y + 2 * range + 1);
+        sum(right, bottom) + sum(left, top) - sum(left, bottom) - sum(right, top)
+      }
+      Neighborhood::VonNeumann | Neighborhood::Hexagonal => {
+        (-range..=range)
+            .map(|dy| {
+              // The hexagonal grid drops the top-right and bottom-left corners, so rows above reach less far to the
+              // right and rows below reach less far to the left.
+              let (left, right) = match self.neighborhood {
+                Neighborhood::VonNeumann => (range - dy.abs(), range - dy.abs()),
+                _ if dy < 0 => (range, range + dy),
+                _ => (range - dy, range),
+              };
+              sum(x + range + right + 1, y + range + dy) - sum(x + range - left, y + range + dy)
+            })
+            .sum()
+      }
+    }
+  }
+}
+
+// Parses rules in Golly's Larger than Life notation, such as Bosco's Rule "R5,C0,M1,S34..58,B34..45,NM". C0 and C2
+// both mean two states; more states decay like Generations rules. NM selects the Moore neighborhood, NN the von
+// Neumann neighborhood and NH the hexagonal neighborhood.
+pub fn parse_larger_than_life_rule(rule: &str) -> Result<LargerThanLifeRule, String> {
+  let mut range: Option<i64> = None;
+  let mut states: u8 = 2;
+  let mut include_middle = false;
+  let mut survival: Option<(u32, u32)> = None;
+  let mut birth: Option<(u32, u32)> = None;
+  let mut neighborhood = Neighborhood::Moore;
+
+  for part in rule.trim().split(',') {
+    let invalid = || format!("Invalid component \"{}\" in rule \"{}\"", part, rule);
+    let value = part.get(1..).ok_or_else(invalid)?;
+    match part.chars().next().map(|c| c.to_ascii_uppercase()) {
+      Some('R') => range = Some(value.parse().map_err(|_| invalid())?),
+      Some('C') => states = value.parse::<u8>().map_err(|_| invalid())?.max(2),
+      Some('M') => include_middle = value.parse::<u8>().map_err(|_| invalid())? == 1,
+      Some('S') => survival = Some(parse_count_range(value).ok_or_else(invalid)?),
+      Some('B') => birth = Some(parse_count_range(value).ok_or_else(invalid)?),
+      Some('N') => neighborhood = match value.to_ascii_uppercase().as_str() {
+        "M" => Neighborhood::Moore,
+        "N" => Neighborhood::VonNeumann,
+        "H" => Neighborhood::Hexagonal,
+        _ => return Err(invalid()),
+      },
+      _ => return Err(invalid()),
+    }
+  }
+
+  let range = range.ok_or_else(|| format!("Rule \"{}\" has no range", rule))?;
+  if !(1..=MAX_RANGE).contains(&range) {
+    return Err(format!("Range {} of rule \"{}\" is not between 1 and {}", range, rule, MAX_RANGE));
+  }
+  let survival = survival.ok_or_else(|| format!("Rule \"{}\" has no survival range", rule))?;
+  let birth = birth.ok_or_else(|| format!("Rule \"{}\" has no birth range", rule))?;
+  if birth.0 == 0 {
+    return Err(format!("Rule \"{}\" can give birth with no live neighbors, which is not supported", rule));
+  }
+  Ok(LargerThanLifeRule { range, states, include_middle, survival, birth, neighborhood })
+}
+
+pub fn is_larger_than_life_rule(rule: &str) -> bool {
+  let rule = rule.trim();
+  rule.starts_with(['R', 'r']) && rule.contains(',')
+}
+
+fn parse_count_range(range: &str) -> Option<(u32, u32)> {
+  match range.split_once("..") {
+    Some((min, max)) => Some((min.parse().ok()?, max.parse().ok()?)),
+    None => range.parse().ok().map(|count| (count, count)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::sparse::SparseBoard;
+
+  // Counts every neighborhood cell by cell, the way the rules are described.
+  fn step_cells(cells: &SparseBoard, rule: &LargerThanLifeRule, area: i64) -> Vec<(i64, i64)> {
+    let range = rule.range;
+    let mut live = Vec::new();
+    for y in -area..area {
+      for x in -area..area {
+        let count = (-range..=range)
+            .flat_map(|dy| (-range..=range).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| rule.neighborhood == Neighborhood::Moore || dx.abs() + dy.abs() <= range)
+            .filter(|&(dx, dy)| (dx, dy) != (0, 0) || rule.include_middle)
+            .filter(|&(dx, dy)| cells.get_cell(x + dx, y + dy))
+            .count() as u32;
+        let (min, max) = if cells.get_cell(x, y) { rule.survival } else { rule.birth };
+        if (min..=max).contains(&count) {
+          live.push((x, y));
+        }
+      }
+    }
+    live
+  }
+
+  fn live_cells(board: &SparseBoard, area: i64) -> Vec<(i64, i64)> {
+    (-area..area).flat_map(|y| (-area..area).map(move |x| (x, y))).filter(|&(x, y)| board.get_cell(x, y)).collect()
+  }
+
+  #[test]
+  fn steps_like_cells_across_tile_edges() {
+    for rule in ["R2,C0,M1,S6..10,B5..7,NM", "R3,C0,M0,S4..9,B4..6,NN", "R1,C2,M0,S2..3,B3..3,NM"] {
+      let rule = parse_larger_than_life_rule(rule).unwrap();
+      // A soup around the origin, where four tiles meet.
+      let mut board = SparseBoard::new();
+      for y in -12..12i64 {
+        for x in -12..12i64 {
+          board.set_cell(x, y, (x * 7 + y * 13 + x * y).rem_euclid(5) < 2);
+        }
+      }
+      let area = 12 + 4 * rule.range;
+      for generation in 1..=4 {
+        let expected = step_cells(&board, &rule, area);
+        let mut next = SparseBoard::new();
+        sparse::compute_next_board_state_with_rule(&board, &mut next, &rule);
+        board = next;
+        assert_eq!(live_cells(&board, area), expected, "generation {}", generation);
+      }
+    }
+  }
+
+  #[test]
+  fn parses_golly_notation() {
+    let bosco = parse_larger_than_life_rule("R5,C0,M1,S34..58,B34..45,NM").unwrap();
+    assert_eq!((bosco.range, bosco.states, bosco.include_middle), (5, 2, true));
+    assert_eq!((bosco.survival, bosco.birth, bosco.neighborhood), ((34, 58), (34, 45), Neighborhood::Moore));
+    // C0 and C1 mean two states as C2 does.
+    for (states, expected) in [("C0", 2), ("C1", 2), ("C2", 2), ("C7", 7)] {
+      let rule = parse_larger_than_life_rule(&format!("R1,{},M0,S2..3,B3,NN", states)).unwrap();
+      assert_eq!((rule.states, rule.birth, rule.neighborhood), (expected, (3, 3), Neighborhood::VonNeumann));
+    }
+    for rule in ["R0,C0,M0,S1..2,B1..2,NM", "R501,C0,M0,S1..2,B1..2,NM", "R2,C0,M0,S1..2,B0..2,NM", "R2,C0,M0,S1..2,B1..2,NX", "R2,C0,M0,S1..2"] {
+      assert!(parse_larger_than_life_rule(rule).is_err(), "{}", rule);
+    }
+    assert!(is_larger_than_life_rule("R5,C0,M1,S34..58,B34..45,NM") && !is_larger_than_life_rule("B3/S23"));
+  }
+}
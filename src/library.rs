@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+use crate::conway::{Neighborhood, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH};
+use crate::pattern;
+use crate::pattern::PatternCells;
+use crate::render;
+use crate::render::{Colors, Viewport};
+use crate::rule::Rule;
+use crate::selection::Rectangle;
+use crate::universe::{Buffers, Universe};
+
+pub const THUMBNAIL_SIZE: u32 = 128;
+const MARGIN: u32 = 8;
+pub const PANEL_WIDTH: u32 = THUMBNAIL_SIZE + 2 * MARGIN;
+const ENTRY_HEIGHT: u32 = THUMBNAIL_SIZE + MARGIN;
+
+// Thumbnails show where a pattern gets to over this many generations.
+const THUMBNAIL_GENERATIONS: u64 = 16;
+
+const PANEL_COLOR: u32 = 0xFF1C1C28;
+const HOVER_COLOR: u32 = 0xFF3070F0;
+// Cells only reached in later generations of a thumbnail.
+const TRAIL_COLOR: u32 = 0xFF404058;
+// Shown in place of the thumbnail of a pattern that could not be loaded.
+const ERROR_COLOR: u32 = 0xFF602020;
+
+struct Entry {
+  path: PathBuf,
+  // The path relative to the library's directory.
+  name: String,
+  // Drawn the first time the entry scrolls into view.
+  thumbnail: Option<Result<Vec<u32>, String>>,
+}
+
+// The pattern files found in a directory, shown as a column of thumbnails along the right edge of the window.
+pub struct Library {
+  entries: Vec<Entry>,
+  // How many pixels the column is scrolled down by.
+  scroll: f64,
+  pub visible: bool,
+}
+
+impl Library {
+  // Indexes every pattern file in the directory and its subdirectories, in order of their paths.
+  pub fn index(directory: &Path) -> Result<Self, String> {
+    let mut paths = Vec::new();
+    find_pattern_files(directory, &mut paths)?;
+    paths.sort();
+    let entries = paths.into_iter()
+        .map(|path| {
+          let name = path.strip_prefix(directory).unwrap_or(&path).display().to_string();
+          Entry { path, name, thumbnail: None }
+        })
+        .collect();
+    Ok(Library { entries, scroll: 0.0, visible: true })
+  }
+
+  // Whether the panel covers the given pixel column of a window of the given width.
+  pub fn contains(&self, window_width: u32, pixel_x: f64) -> bool {
+    self.visible && pixel_x >= window_width.saturating_sub(PANEL_WIDTH) as f64
+  }
+
+  // Returns the index of the entry whose thumbnail is drawn at the given pixel.
+  pub fn entry_at(&self, window_width: u32, pixel_x: f64, pixel_y: f64) -> Option<usize> {
+    let left = window_width.saturating_sub(PANEL_WIDTH - MARGIN) as f64;
+    if !self.contains(window_width, pixel_x) || !(left..left + THUMBNAIL_SIZE as f64).contains(&pixel_x) {
+      return None;
+    }
+    let y = pixel_y + self.scroll - MARGIN as f64;
+    let index = (y / ENTRY_HEIGHT as f64).floor();
+    let inside = y - index * (ENTRY_HEIGHT as f64) < THUMBNAIL_SIZE as f64;
+    (inside && index >= 0.0 && (index as usize) < self.entries.len()).then_some(index as usize)
+  }
+
+  pub fn scroll(&mut self, pixels: f64, window_height: u32) {
+    let content_height = (self.entries.len() as u32 * ENTRY_HEIGHT + MARGIN) as f64;
+    self.scroll = (self.scroll + pixels).min(content_height - window_height as f64).max(0.0);
+  }
+
+  pub fn name(&self, index: usize) -> &str {
+    &self.entries[index].name
+  }
+
+  pub fn load(&self, index: usize) -> Result<PatternCells, String> {
+    pattern::load_pattern_file(&self.entries[index].path)
+  }
+
+  // Draws the panel over the right edge of a buffer of width x height pixels, outlining the hovered entry.
+  pub fn draw(&mut self, rule: Option<&Rule>, colors: &Colors, hovered: Option<usize>, width: u32, height: u32, pixels: &mut [u32]) {
+    let left = width.saturating_sub(PANEL_WIDTH) as usize;
+    for row in pixels.chunks_mut((width as usize).max(1)) {
+      row[left..].fill(PANEL_COLOR);
+    }
+
+    let first = (self.scroll / ENTRY_HEIGHT as f64) as usize;
+    let last = (((self.scroll + height as f64) / ENTRY_HEIGHT as f64) as usize + 1).min(self.entries.len());
+    for index in first.min(last)..last {
+      let entry = &mut self.entries[index];
+      let thumbnail = entry.thumbnail.get_or_insert_with(|| {
+        pattern::load_pattern_file(&entry.path)
+            .map(|cells| thumbnail(&cells, rule, colors))
+            .inspect_err(|error| eprintln!("{}", error))
+      });
+
+      let top = MARGIN as f64 + (index as u32 * ENTRY_HEIGHT) as f64 - self.scroll;
+      for thumbnail_y in 0..THUMBNAIL_SIZE as usize {
+        let y = top as i64 + thumbnail_y as i64;
+        if !(0..height as i64).contains(&y) {
+          continue;
+        }
+        let row = &mut pixels[y as usize * width as usize..][..width as usize];
+        for thumbnail_x in 0..THUMBNAIL_SIZE as usize {
+          let Some(pixel) = row.get_mut(left + MARGIN as usize + thumbnail_x) else { break };
+          let on_border = [thumbnail_x, thumbnail_y].iter().any(|&offset| !(2..THUMBNAIL_SIZE as usize - 2).contains(&offset));
+          *pixel = match thumbnail {
+            _ if on_border && hovered == Some(index) => HOVER_COLOR,
+            Ok(thumbnail) => thumbnail[thumbnail_y * THUMBNAIL_SIZE as usize + thumbnail_x],
+            Err(_) => ERROR_COLOR,
+          };
+        }
+      }
+    }
+  }
+}
+
+fn find_pattern_files(directory: &Path, paths: &mut Vec<PathBuf>) -> Result<(), String> {
+  let error = |error: std::io::Error| format!("Could not read pattern directory \"{}\": {}", directory.display(), error);
+  for entry in std::fs::read_dir(directory).map_err(error)? {
+    let path = entry.map_err(error)?.path();
+    if path.is_dir() {
+      find_pattern_files(&path, paths)?;
+    } else if pattern::is_pattern_file(&path) {
+      paths.push(path);
+    }
+  }
+  Ok(())
+}
+
+// Runs the pattern for a few generations and draws the first in full color over a dim trail of wherever the later ones
+// reach, so that moving patterns show which way they go. The pattern is run once to find the area it covers, and again
+// to draw it.
+fn thumbnail(cells: &PatternCells, rule: Option<&Rule>, colors: &Colors) -> Vec<u32> {
+  let run = |visit: &mut dyn FnMut(&Universe)| {
+    let mut universe = Universe::new(Buffers::for_rule(rule), rule.cloned());
+    for &(x, y, state) in &cells.cells {
+      universe.set_cell(x, y, state);
+    }
+    visit(&universe);
+    for _ in 0..THUMBNAIL_GENERATIONS {
+      universe.step();
+      visit(&universe);
+    }
+  };
+
+  let mut bounds: Option<(i64, i64, i64, i64)> = None;
+  let mut hexagonal = false;
+  run(&mut |universe| {
+    hexagonal = universe.neighborhood() == Neighborhood::Hexagonal;
+    for (_, (block_x, block_y), _) in universe.occupied_blocks() {
+      let (min_x, min_y, max_x, max_y) = bounds.unwrap_or((block_x, block_y, block_x, block_y));
+      bounds = Some((min_x.min(block_x), min_y.min(block_y), max_x.max(block_x), max_y.max(block_y)));
+    }
+  });
+  let (min_x, min_y, max_x, max_y) = bounds.unwrap_or((0, 0, 0, 0));
+  let (width, height) = (CELL_BLOCK_WIDTH as i64, CELL_BLOCK_HEIGHT as i64);
+  let rectangle = Rectangle { x: min_x * width, y: min_y * height, width: (max_x - min_x + 1) * width, height: (max_y - min_y + 1) * height };
+  let viewport = Viewport::fitting(THUMBNAIL_SIZE, THUMBNAIL_SIZE, hexagonal, &rectangle);
+
+  let mut thumbnail: Option<Vec<u32>> = None;
+  let mut generation = vec![0u32; (THUMBNAIL_SIZE * THUMBNAIL_SIZE) as usize];
+  run(&mut |universe| {
+    render::render(universe, &viewport, colors, &mut generation);
+    match &mut thumbnail {
+      None => thumbnail = Some(generation.clone()),
+      Some(thumbnail) => {
+        for (pixel, &later) in thumbnail.iter_mut().zip(&generation) {
+          if *pixel == colors[0] && later != colors[0] {
+            *pixel = TRAIL_COLOR;
+          }
+        }
+      }
+    }
+  });
+  thumbnail.unwrap()
+}
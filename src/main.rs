@@ -1,80 +1,654 @@
+mod benchmark;
 mod conway;
+mod dense;
+mod distributed;
+mod generations;
+mod history;
+mod json;
+mod larger_than_life;
+mod library;
+mod margolus;
+mod metadata;
+mod minimap;
+mod multi_state;
+mod pattern;
+mod png;
+mod random;
+mod recording;
+mod remote;
+mod render;
+mod row_major;
+mod rule;
+mod rule_table;
+mod scripting;
+mod selection;
+mod simd;
+mod sparse;
+mod terminal;
+#[cfg(feature = "large-cell-blocks")]
+mod u256;
+mod universe;
+mod viewer;
 
 use std::io;
 use std::io::Write;
 use std::mem::MaybeUninit;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use sdl3::event::Event;
+use sdl3::keyboard::{Keycode, Mod};
+use sdl3::mouse::MouseButton;
+use sdl3::pixels::{PixelFormat, PixelFormatEnum};
 
 #[macro_use]
 extern crate static_assertions;
 
 const MAX_THREADS: usize = 64;
 
+// Time spent by each thread stepping the board, for the benchmark to report how evenly the work was spread.
+static BUSY_NANOSECONDS: [AtomicU64; MAX_THREADS] = [const { AtomicU64::new(0) }; MAX_THREADS];
+
+// Screenshots and recordings bigger than this many pixels are refused rather than running out of memory.
+const MAX_IMAGE_PIXELS: u64 = 1 << 28;
+
+// Shown by screenshots and recordings of an empty board, when no region is given.
+const EMPTY_REGION: selection::Rectangle = selection::Rectangle { x: 0, y: 0, width: 1, height: 1 };
+
+// How far the library panel scrolls for each notch of the mouse wheel.
+const LIBRARY_SCROLL_PIXELS: f64 = 48.0;
+
 fn main() {
   // TODO: recursively divide board using quad tree or binary bit tree and use 1 to flag subtrees as needing update and 0 as not
 
-  println!("Boards is {} x {}", conway::BOARD_WIDTH_CELLS, conway::BOARD_HEIGHT_CELLS);
-  println!("Allocating 2 buffers of size {} ({} GB) {} x {} each", conway::BOARD_TOTAL_BYTES, conway::BOARD_TOTAL_BYTES as f64 / 1024.0 / 1024.0 / 1024.0, conway::BOARD_WIDTH_BLOCKS, conway::BOARD_HEIGHT_BLOCKS);
+  let rule_argument = argument_value("--rule");
+  let rule = rule_argument.as_ref().map(|rule| rule::load_rule(rule).unwrap_or_else(|error| exit_with_error(error)));
+  // Written into copied patterns.
+  let mut rule_name = rule.as_ref().zip(rule_argument).map_or("B3/S23".to_string(), |(rule, source)| rule::rule_name(rule, &source));
+  // Only two-state rules with a transition table can run on the dense board.
+  let use_sparse_board = std::env::args().any(|arg| arg == "--sparse")
+      || rule.as_ref().is_some_and(|rule| matches!(rule, rule::Rule::LargerThanLife(_) | rule::Rule::MultiState(_)) || rule.states() > 2);
 
-  print!("Allocating buffer 1...");
-  let buffer1 = Box::<conway::Board>::new_uninit();
-  println!("done.");
-  print!("Allocating buffer 2...");
-  let buffer2 = Box::<conway::Board>::new_uninit();
-  println!("done.");
+  // Distributed runs split the dense board between worker processes, each of which only allocates its own strip of it.
+  // The coordinator starts the workers with the same arguments it was given, so they all seed the same cells.
+  if let Some(index) = argument_value("--worker") {
+    let index: usize = index.parse().unwrap_or_else(|_| exit_with_error(format!("Invalid worker index \"{}\"", index)));
+    let socket_dir = argument_value("--socket-dir").unwrap_or_else(|| exit_with_error("Workers need a --socket-dir".to_string()));
+    distributed::run_worker(index, parsed_argument("--workers", 1), Path::new(&socket_dir), rule.as_ref(), &seed_cells(), parsed_argument("--generations", benchmark::GENERATIONS))
+        .unwrap_or_else(|error| exit_with_error(error));
+    return;
+  }
+  if let Some(workers) = argument_value("--workers") {
+    let workers: usize = workers.parse().unwrap_or_else(|_| exit_with_error(format!("Invalid value \"{}\" for --workers", workers)));
+    distributed::run_coordinator(workers, rule.as_ref(), parsed_argument("--generations", benchmark::GENERATIONS))
+        .unwrap_or_else(|error| exit_with_error(error));
+    return;
+  }
 
-  print!("Zeroing-out buffer 1...");
-  let mut buffer1: Box<conway::Board> = zero_out_buffer(buffer1);
-  println!("done.");
-  print!("Zeroing-out buffer 2...");
-  let mut buffer2: Box<conway::Board> = zero_out_buffer(buffer2);
-  println!("done.");
+  let buffers = if use_sparse_board {
+    let states = rule.as_ref().map_or(2, rule::Rule::states);
+    println!("Using {}-state sparse board of {} x {} block tiles", states, sparse::TILE_WIDTH_BLOCKS, sparse::TILE_HEIGHT_BLOCKS);
+    universe::Buffers::for_rule(rule.as_ref())
+  } else {
+    // The dense board is laid out in blocks unless asked for rows, which only Conway's Game of Life steps directly.
+    match argument_value("--layout").as_deref() {
+      None | Some("blocks") => {
+        let (buffer1, buffer2) = allocate_dense_buffers::<conway::CellBlock, { conway::BOARD_TOTAL_BLOCKS }>("blocks");
+        universe::Buffers::Dense(Box::new(dense::BlockBoard::new(buffer1, buffer2)))
+      }
+      Some("rows") => {
+        let (buffer1, buffer2) = allocate_dense_buffers::<u64, { row_major::BOARD_TOTAL_WORDS }>("rows");
+        universe::Buffers::Dense(Box::new(dense::RowMajorBoard::new(buffer1, buffer2)))
+      }
+      Some(layout) => exit_with_error(format!("Unknown layout {}, expected blocks or rows", layout)),
+    }
+  };
+  let mut universe = universe::Universe::new(buffers, rule);
+
+  // Patterns are placed with their top-left cell at the origin, which is also the top-left cell of the dense board.
+  if let Some(path) = argument_value("--pattern") {
+    let cells = pattern::load_pattern_file(Path::new(&path)).unwrap_or_else(|error| exit_with_error(error));
+    for (x, y, state) in cells.cells {
+      universe.set_cell(x, y, state);
+    }
+  }
+  // Scripts run once the pattern is in place, and stay loaded for the keys they bind.
+  let script = argument_value("--script").map(|path| scripting::Script::load(Path::new(&path)).unwrap_or_else(|error| exit_with_error(error)));
+  if let Some(script) = &script {
+    script.run(&mut universe, None).unwrap_or_else(|error| exit_with_error(error));
+  }
+  // Benchmarks step a soup of random cells, or the pattern if one was given, without drawing anything.
+  if std::env::args().any(|arg| arg == "--bench") {
+    if !std::env::args().any(|arg| arg == "--pattern") {
+      let (region, density, seed) = soup_arguments();
+      benchmark::seed_soup(&mut universe, &region, density, seed);
+    }
+    benchmark::run(&mut universe, parsed_argument("--generations", benchmark::GENERATIONS));
+    return;
+  }
+
+  let generations: u64 = parsed_argument("--generations", 0);
+  // How many cells across each pixel of a screenshot or recording covers, where fractions draw each cell over several
+  // pixels.
+  let cells_per_pixel: f64 = parsed_argument("--scale", 1.0);
+  let region = argument_value("--region").map(|region| parse_region(&region).unwrap_or_else(|error| exit_with_error(error)));
+  // Recordings move the view along with the pattern, rather than keeping it where it started.
+  let follow = std::env::args().any(|arg| arg == "--follow");
+  let frames_per_second: u32 = parsed_argument("--fps", 10);
+  let mut colors = render::colors_for_rule(universe.rule.as_ref());
+  let hexagonal = universe.neighborhood() == conway::Neighborhood::Hexagonal;
+
+  // Screenshots and recordings asked for on the command line are rendered straight from the board, without opening a
+  // window. Recordings start out showing the whole pattern, and screenshots show whatever it has grown into.
+  let screenshot_path = argument_value("--screenshot");
+  let record_path = argument_value("--record");
+  if screenshot_path.is_some() || record_path.is_some() {
+    let mut recorder = record_path.as_ref().map(|path| {
+      let region = region.or_else(|| selection::bounding_rectangle(&universe)).unwrap_or(EMPTY_REGION);
+      let viewport = render::Viewport::covering(&region, cells_per_pixel, hexagonal);
+      check_image_size(&viewport)
+          .and_then(|_| recording::Recorder::start(path, viewport, follow, frames_per_second, &colors))
+          .unwrap_or_else(|error| exit_with_error(error))
+    });
+    for generation in 0..=generations {
+      if generation > 0 {
+        universe.step();
+      }
+      if let Some(recorder) = &mut recorder {
+        recorder.record(&universe, &colors).unwrap_or_else(|error| exit_with_error(error));
+      }
+    }
+    if let (Some(recorder), Some(path)) = (recorder, &record_path) {
+      let frames = recorder.finish().unwrap_or_else(|error| exit_with_error(error));
+      println!("Recorded {} generations to {}", frames, path);
+    }
+
+    if let Some(path) = screenshot_path {
+      let region = region.or_else(|| selection::bounding_rectangle(&universe)).unwrap_or(EMPTY_REGION);
+      let viewport = render::Viewport::covering(&region, cells_per_pixel, hexagonal);
+      save_screenshot(&universe, &viewport, &colors, &path).unwrap_or_else(|error| exit_with_error(error));
+      println!("Saved generation {} to {}", universe.generation, path);
+    }
+    return;
+  }
+
+  // Runs in the terminal instead of a window, for when there is no display, such as over SSH.
+  if std::env::args().any(|arg| arg == "--tui") {
+    terminal::run(&mut universe, &colors, hexagonal).unwrap_or_else(|error| exit_with_error(error));
+    return;
+  }
+
+  // Serves the board to browsers instead, for watching long runs on machines without a display. Unless a number of
+  // generations is given, it runs until stopped.
+  if let Some(address) = argument_value("--serve") {
+    let generations = argument_value("--generations").map(|_| generations);
+    viewer::run(&mut universe, &address, generations, &colors, hexagonal, &rule_name).unwrap_or_else(|error| exit_with_error(error));
+    return;
+  }
+
+  let mut library = argument_value("--library").map(|directory| library::Library::index(Path::new(&directory)).unwrap_or_else(|error| exit_with_error(error)));
 
   let sdl = sdl3::init().unwrap();
   let video = sdl.video().unwrap();
-  let _window =
+  let window =
       video.window("Lifer", 1920, 1080)
           .high_pixel_density()
           .resizable()
           .position_centered()
           .build()
           .unwrap();
+  let mut canvas = window.into_canvas();
+  let texture_creator = canvas.texture_creator();
+  // Matches the ARGB pixels drawn by the renderer.
+  let pixel_format = PixelFormat::from(PixelFormatEnum::ARGB8888);
 
-  let mut step: u8 = 0;
+  let (width, height) = canvas.output_size().unwrap();
+  let mut viewport = render::Viewport::new(width, height, hexagonal);
+  let mut texture = texture_creator.create_texture_streaming(pixel_format, width, height).unwrap();
+  let mut pixels = vec![0u32; width as usize * height as usize];
+  let mut paused = false;
+  let mut color_mode = render::ColorMode::States;
+  let mut history = history::History::new(&universe);
+  // The state cells are drawn in while the right mouse button is held, and the last cell drawn.
+  let mut drawing: Option<(u8, (i64, i64))> = None;
+  // The selected cells, and the corner a selection being dragged out started from.
+  let mut selection: Option<selection::Rectangle> = None;
+  let mut selection_anchor: Option<(i64, i64)> = None;
+  // The last pattern copied, along with the RLE put on the system clipboard for it.
+  let mut copied: Option<(pattern::Pattern, String)> = None;
+  // A pattern dragged out of the library, drawn under the mouse until it is dropped onto the board.
+  let mut dragged: Option<pattern::Pattern> = None;
+  // The library entry named in the window title.
+  let mut titled_entry: Option<usize> = None;
+  // A recording of the view started from the keyboard, which gets a frame for every generation the board shows.
+  let mut recorder: Option<recording::Recorder> = None;
+  let mut recorded_generation = None;
+  let mut minimap = minimap::Minimap::new();
+  // Whether the left mouse button was pressed on the minimap, so that dragging moves the view along with the mouse.
+  let mut navigating = false;
+  let mut mouse_position = (0.0, 0.0);
+  let clipboard = video.clipboard();
+  let keyboard = sdl.keyboard();
+
+  let server = argument_value("--listen").map(|address| remote::Server::listen(&address).unwrap_or_else(|error| exit_with_error(error)));
 
   let mut event_pump = sdl.event_pump().unwrap();
   'main_loop: loop {
+    // Mouse coordinates are in window points, which may be smaller than pixels on high density displays.
+    let scale = viewport.width as f64 / canvas.window().size().0 as f64;
     for event in event_pump.poll_iter() {
+      if let Event::MouseMotion { x, y, .. } = event {
+        mouse_position = (x as f64 * scale, y as f64 * scale);
+      }
       match event {
         Event::Quit { .. } => break 'main_loop,
+        // Keys bound by the script take the place of whatever they would otherwise do.
+        Event::KeyDown { keycode: Some(keycode), .. } if script.as_ref().is_some_and(|script| script.binds(keycode)) => {
+          if let Err(error) = script.as_ref().unwrap().press(keycode, &mut universe, Some(&mut history)) {
+            eprintln!("{}", error);
+          }
+        }
+        Event::KeyDown { keycode: Some(Keycode::Space), .. } => paused = !paused,
+        Event::KeyDown { keycode: Some(keycode @ (Keycode::Left | Keycode::Right | Keycode::Home | Keycode::Z | Keycode::Y)), keymod, .. } => {
+          let control = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+          let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+          match keycode {
+            Keycode::Left => { history.back(&mut universe); }
+            Keycode::Right => history.step(&mut universe),
+            Keycode::Home => history.reset(&mut universe),
+            Keycode::Z if control && shift => { history.forward(&mut universe); }
+            Keycode::Z if control => { history.back(&mut universe); }
+            Keycode::Y if control => { history.forward(&mut universe); }
+            _ => continue,
+          }
+          paused = true;
+          println!("Generation {}", universe.generation);
+        }
+        Event::KeyDown { keycode: Some(keycode), keymod, .. } => {
+          let control = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+          let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+          match (keycode, selection) {
+            (Keycode::C, Some(rectangle)) if control => copied = Some(copy_selection(&universe, rectangle, &rule_name, &clipboard)),
+            (Keycode::X, Some(rectangle)) if control => {
+              copied = Some(copy_selection(&universe, rectangle, &rule_name, &clipboard));
+              selection::clear_inside(&mut history, &mut universe, rectangle);
+            }
+            (Keycode::V, _) if control => {
+              if let Some(pattern) = pattern_to_paste(&universe, &copied, &clipboard) {
+                let (x, y) = viewport.cell_at(mouse_position.0, mouse_position.1);
+                selection = Some(selection::paste(&mut history, &mut universe, &pattern, x, y));
+              }
+            }
+            (Keycode::R, Some(rectangle)) => selection = Some(selection::rotate_clockwise(&mut history, &mut universe, rectangle)),
+            (Keycode::H, Some(rectangle)) => selection = Some(selection::flip_horizontally(&mut history, &mut universe, rectangle)),
+            (Keycode::V, Some(rectangle)) => selection = Some(selection::flip_vertically(&mut history, &mut universe, rectangle)),
+            (Keycode::Delete | Keycode::Backspace, Some(rectangle)) if shift => selection::clear_outside(&mut history, &mut universe, rectangle),
+            (Keycode::Delete | Keycode::Backspace, Some(rectangle)) => selection::clear_inside(&mut history, &mut universe, rectangle),
+            (Keycode::F, Some(rectangle)) => selection::random_fill(&mut history, &mut universe, rectangle),
+            // Saves the selection at the scale given on the command line, or otherwise the view as it is on screen.
+            (Keycode::P, _) => {
+              let path = format!("lifer-{}.png", universe.generation);
+              let result = match selection {
+                Some(rectangle) => save_screenshot(&universe, &render::Viewport::covering(&rectangle, cells_per_pixel, hexagonal), &colors, &path),
+                None => save_screenshot(&universe, &viewport, &colors, &path),
+              };
+              match result {
+                Ok(()) => println!("Saved generation {} to {}", universe.generation, path),
+                Err(error) => eprintln!("{}", error),
+              }
+            }
+            (Keycode::G, _) => match recorder.take() {
+              Some(recorder) => finish_recording(recorder),
+              None => {
+                let path = format!("lifer-{}.gif", universe.generation);
+                match recording::Recorder::start(&path, viewport.clone(), follow, frames_per_second, &colors) {
+                  Ok(started) => {
+                    println!("Recording to {}", path);
+                    recorder = Some(started);
+                    recorded_generation = None;
+                  }
+                  Err(error) => eprintln!("{}", error),
+                }
+              }
+            },
+            // Cycles through coloring cells by state, age, trails and activity, tracking cell metadata for all but the
+            // first.
+            (Keycode::M, _) => {
+              color_mode = color_mode.next();
+              history.track_metadata(&universe, color_mode != render::ColorMode::States);
+              println!("Coloring cells by {}", color_mode.name());
+            }
+            (Keycode::N, _) => minimap.visible ^= true,
+            (Keycode::L, _) if library.is_some() => library.as_mut().unwrap().visible ^= true,
+            (Keycode::Escape, _) => {
+              selection = None;
+              dragged = None;
+            }
+            _ => continue,
+          }
+        }
+        Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } if minimap.contains(x as f64 * scale, y as f64 * scale) => {
+          let (cell_x, cell_y) = minimap.cell_at(x as f64 * scale, y as f64 * scale).unwrap();
+          viewport.center_on(cell_x, cell_y);
+          navigating = true;
+        }
+        Event::MouseMotion { mousestate, x, y, .. } if mousestate.left() && navigating => {
+          if let Some((cell_x, cell_y)) = minimap.cell_at(x as f64 * scale, y as f64 * scale) {
+            viewport.center_on(cell_x, cell_y);
+          }
+        }
+        Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. }
+            if library.as_ref().is_some_and(|library| library.contains(viewport.width, x as f64 * scale)) => {
+          let library = library.as_ref().unwrap();
+          if let Some(index) = library.entry_at(viewport.width, x as f64 * scale, y as f64 * scale) {
+            match library.load(index) {
+              Ok(cells) => dragged = Some(pattern::Pattern::from_cells(&cells, &universe)),
+              Err(error) => eprintln!("{}", error),
+            }
+          }
+        }
+        Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. }
+            if keyboard.mod_state().intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+          let cell = viewport.cell_at(x as f64 * scale, y as f64 * scale);
+          selection = Some(selection::Rectangle::from_corners(cell, cell));
+          selection_anchor = Some(cell);
+        }
+        Event::MouseButtonUp { mouse_btn: MouseButton::Left, x, y, .. } => {
+          selection_anchor = None;
+          navigating = false;
+          // Patterns dropped back onto the library are not placed.
+          let over_library = library.as_ref().is_some_and(|library| library.contains(viewport.width, x as f64 * scale));
+          if let Some(pattern) = dragged.take().filter(|_| !over_library) {
+            let (x, y) = pattern_origin(&viewport, &pattern, (x as f64 * scale, y as f64 * scale));
+            selection = Some(selection::paste(&mut history, &mut universe, &pattern, x, y));
+          }
+        }
+        Event::MouseButtonDown { mouse_btn: MouseButton::Right, x, y, .. } => {
+          let cell = viewport.cell_at(x as f64 * scale, y as f64 * scale);
+          let state = if universe.get_cell(cell.0, cell.1) == 0 { 1 } else { 0 };
+          history.edit_cell(&mut universe, cell.0, cell.1, state);
+          drawing = Some((state, cell));
+        }
+        Event::MouseMotion { mousestate, x, y, .. } if mousestate.right() => {
+          if let Some((state, last_cell)) = drawing {
+            let cell = viewport.cell_at(x as f64 * scale, y as f64 * scale);
+            for (x, y) in cells_on_line(last_cell, cell) {
+              history.edit_cell(&mut universe, x, y, state);
+            }
+            drawing = Some((state, cell));
+          }
+        }
+        Event::MouseButtonUp { mouse_btn: MouseButton::Right, .. } => {
+          history.finish_edit(&universe);
+          drawing = None;
+        }
+        Event::MouseWheel { y, mouse_x, .. }
+            if library.as_ref().is_some_and(|library| library.contains(viewport.width, mouse_x as f64 * scale)) => {
+          library.as_mut().unwrap().scroll(-y as f64 * LIBRARY_SCROLL_PIXELS, viewport.height);
+        }
+        Event::MouseWheel { y, mouse_x, mouse_y, .. } => {
+          viewport.zoom(1.25f64.powf(y as f64), mouse_x as f64 * scale, mouse_y as f64 * scale);
+        }
+        Event::MouseMotion { mousestate, x, y, .. } if mousestate.left() && selection_anchor.is_some() => {
+          let cell = viewport.cell_at(x as f64 * scale, y as f64 * scale);
+          selection = Some(selection::Rectangle::from_corners(selection_anchor.unwrap(), cell));
+        }
+        Event::MouseMotion { mousestate, xrel, yrel, .. } if mousestate.left() && dragged.is_none() => {
+          viewport.pan(xrel as f64 * scale, yrel as f64 * scale);
+        }
         _ => continue,
       }
     }
 
-    let source: &conway::Board;
-    let destination: &mut conway::Board;
-    if step & 1 == 0 {
-      source = &buffer1;
-      destination = &mut buffer2;
-    } else {
-      source = &buffer2;
-      destination = &mut buffer1;
+    for request in server.iter().flat_map(|server| server.requests()) {
+      let result = match &request.command {
+        remote::Command::Load { cells, x, y } => Ok(remote::load(&mut history, &mut universe, cells, *x, *y)),
+        remote::Command::Rule { rule, name } => remote::change_rule(&mut universe, rule.clone()).map(|_| {
+          // The history cannot step back into generations of another rule.
+          history = history::History::new(&universe);
+          history.track_metadata(&universe, color_mode != render::ColorMode::States);
+          colors = render::colors_for_rule(universe.rule.as_ref());
+          viewport.hexagonal = universe.neighborhood() == conway::Neighborhood::Hexagonal;
+          rule_name = name.clone();
+          json::Json::object([("rule", name.as_str().into())])
+        }),
+        remote::Command::Step(generations) => {
+          for _ in 0..*generations {
+            history.step(&mut universe);
+          }
+          Ok(json::Json::object([("generation", universe.generation.into())]))
+        }
+        remote::Command::Pause | remote::Command::Resume => {
+          paused = matches!(request.command, remote::Command::Pause);
+          Ok(json::Json::object([("paused", paused.into())]))
+        }
+        remote::Command::Population => Ok(remote::population(&universe)),
+        remote::Command::Region(region) => Ok(remote::region(&universe, region, &rule_name)),
+      };
+      request.reply(result);
     }
-    step ^= 1;
 
-    print!("Updating board...");
-    io::stdout().flush().unwrap();
-    let start = std::time::Instant::now();
-    compute_next_board_state(source, destination);
-    let duration = start.elapsed();
-    println!("Done in {} milliseconds.", duration.as_secs_f32() * 1000.0);
+    if !paused {
+      print!("Updating board...");
+      io::stdout().flush().unwrap();
+      let start = std::time::Instant::now();
+      history.step(&mut universe);
+      let duration = start.elapsed();
+      print!("Done in {} milliseconds.", duration.as_secs_f32() * 1000.0);
+      if let (Some(population), Some(tile_count)) = (universe.population(), universe.tile_count()) {
+        print!(" Population {} in {} tiles.", population, tile_count);
+      }
+      println!();
+    }
 
-    // TODO draw the board
+    if let Some(active_recorder) = recorder.as_mut().filter(|_| recorded_generation != Some(universe.generation)) {
+      if let Err(error) = active_recorder.record(&universe, &colors) {
+        eprintln!("{}", error);
+        recorder = None;
+      }
+      recorded_generation = Some(universe.generation);
+    }
+
+    let (width, height) = canvas.output_size().unwrap();
+    if (width, height) != (viewport.width, viewport.height) {
+      viewport.resize(width, height);
+      texture = texture_creator.create_texture_streaming(pixel_format, width, height).unwrap();
+      pixels = vec![0u32; width as usize * height as usize];
+    }
+    match history.metadata() {
+      Some(metadata) => render::render_metadata(&universe, &viewport, &colors, metadata, color_mode, &mut pixels),
+      None => render::render(&universe, &viewport, &colors, &mut pixels),
+    }
+    if let Some(rectangle) = &selection {
+      render::highlight(&viewport, rectangle, render::SELECTION_COLOR, &mut pixels);
+    }
+    if let Some(pattern) = &dragged {
+      let (x, y) = pattern_origin(&viewport, pattern, mouse_position);
+      render::ghost(&viewport, pattern, x, y, &mut pixels);
+    }
+    // Refreshed even while hidden, so that the tiles the history has touched do not pile up.
+    minimap.refresh(&universe, &mut history);
+    if minimap.visible {
+      minimap.draw(&universe, &viewport, &mut pixels);
+    }
+    if let Some(library) = library.as_mut().filter(|library| library.visible) {
+      let hovered = library.entry_at(width, mouse_position.0, mouse_position.1);
+      library.draw(universe.rule.as_ref(), &colors, hovered, width, height, &mut pixels);
+      if hovered != titled_entry {
+        let title = hovered.map_or("Lifer".to_string(), |index| format!("Lifer - {}", library.name(index)));
+        canvas.window_mut().set_title(&title).unwrap();
+        titled_entry = hovered;
+      }
+    }
+    texture.with_lock(None, |buffer, pitch| {
+      for (row, row_pixels) in pixels.chunks(width as usize).enumerate() {
+        for (column, pixel) in row_pixels.iter().enumerate() {
+          buffer[row * pitch + column * 4..][..4].copy_from_slice(&pixel.to_ne_bytes());
+        }
+      }
+    }).unwrap();
+    canvas.copy(&texture, None, None).unwrap();
+    canvas.present();
+  }
+
+  if let Some(recorder) = recorder {
+    finish_recording(recorder);
+  }
+}
+
+// The soup benchmarks and distributed runs start from, as its region, density and seed.
+fn soup_arguments() -> (selection::Rectangle, f64, u64) {
+  let region = argument_value("--region").map_or(benchmark::SOUP_REGION, |region| parse_region(&region).unwrap_or_else(|error| exit_with_error(error)));
+  (region, parsed_argument("--density", benchmark::SOUP_DENSITY), parsed_argument("--seed", benchmark::SOUP_SEED))
+}
+
+// The cells a distributed run starts from, which are the pattern if one was given and a soup otherwise.
+fn seed_cells() -> Vec<(i64, i64, u8)> {
+  match argument_value("--pattern") {
+    Some(path) => pattern::load_pattern_file(Path::new(&path)).unwrap_or_else(|error| exit_with_error(error)).cells,
+    None => {
+      let (region, density, seed) = soup_arguments();
+      benchmark::soup_cells(&region, density, seed).map(|(x, y)| (x, y, 1)).collect()
+    }
+  }
+}
+
+fn argument_value(name: &str) -> Option<String> {
+  let mut args = std::env::args().skip_while(|arg| arg != name);
+  args.next()?;
+  args.next()
+}
+
+// Parses a value given on the command line, exiting if it is malformed.
+fn parsed_argument<T: std::str::FromStr>(name: &str, default: T) -> T {
+  match argument_value(name) {
+    Some(value) => value.parse().unwrap_or_else(|_| exit_with_error(format!("Invalid value \"{}\" for {}", value, name))),
+    None => default,
+  }
+}
+
+// Parses a rectangle of cells given as "x,y,width,height".
+fn parse_region(region: &str) -> Result<selection::Rectangle, String> {
+  let numbers: Vec<i64> = region.split(',').map(|number| number.trim().parse()).collect::<Result<_, _>>()
+      .map_err(|_| format!("Invalid region \"{}\"", region))?;
+  match numbers[..] {
+    [x, y, width, height] if width > 0 && height > 0 => Ok(selection::Rectangle { x, y, width, height }),
+    _ => Err(format!("Region \"{}\" should be given as x,y,width,height", region)),
+  }
+}
+
+fn exit_with_error(error: String) -> ! {
+  eprintln!("{}", error);
+  std::process::exit(1);
+}
+
+fn finish_recording(recorder: recording::Recorder) {
+  match recorder.finish() {
+    Ok(frames) => println!("Recorded {} generations", frames),
+    Err(error) => eprintln!("{}", error),
+  }
+}
+
+fn check_image_size(viewport: &render::Viewport) -> Result<(), String> {
+  if viewport.width as u64 * viewport.height as u64 > MAX_IMAGE_PIXELS {
+    return Err(format!("An image of {} x {} pixels is too large, try a larger --scale", viewport.width, viewport.height));
+  }
+  Ok(())
+}
+
+// Renders the universe as seen through the viewport into a PNG file.
+fn save_screenshot(universe: &universe::Universe, viewport: &render::Viewport, colors: &render::Colors, path: &str) -> Result<(), String> {
+  check_image_size(viewport)?;
+  let mut pixels = vec![0u32; viewport.width as usize * viewport.height as usize];
+  render::render(universe, viewport, colors, &mut pixels);
+  png::write_png(path, viewport.width, viewport.height, &pixels)
+}
+
+// Copies the selected cells, keeping the pattern for pasting and putting it on the system clipboard as RLE.
+fn copy_selection(universe: &universe::Universe, selection: selection::Rectangle, rule_name: &str, clipboard: &sdl3::clipboard::ClipboardUtil) -> (pattern::Pattern, String) {
+  let pattern = selection::copy(universe, selection);
+  let rle = pattern::format_rle(&pattern.to_cells(universe), universe.states(), rule_name);
+  if let Err(error) = clipboard.set_clipboard_text(&rle) {
+    eprintln!("Could not copy to the clipboard: {}", error);
+  }
+  (pattern, rle)
+}
+
+// Pastes whatever RLE is on the system clipboard, unless it is still what was last copied here, in which case the
+// copied pattern is pasted as it was.
+fn pattern_to_paste(universe: &universe::Universe, copied: &Option<(pattern::Pattern, String)>, clipboard: &sdl3::clipboard::ClipboardUtil) -> Option<pattern::Pattern> {
+  let text = clipboard.clipboard_text().unwrap_or_default();
+  match copied {
+    Some((pattern, rle)) if text.is_empty() || text == *rle => return Some(pattern.clone()),
+    _ => {}
   }
+  match pattern::parse_rle(&text) {
+    Ok(cells) => Some(pattern::Pattern::from_cells(&cells, universe)),
+    Err(error) => {
+      eprintln!("Could not paste from the clipboard: {}", error);
+      copied.as_ref().map(|(pattern, _)| pattern.clone())
+    }
+  }
+}
+
+// The top-left cell at which a pattern is placed so that it is centered on the given pixel.
+fn pattern_origin(viewport: &render::Viewport, pattern: &pattern::Pattern, pixel: (f64, f64)) -> (i64, i64) {
+  let (x, y) = viewport.cell_at(pixel.0, pixel.1);
+  (x - pattern.width / 2, y - pattern.height / 2)
+}
+
+// The cells along the line from one cell to another, leaving out the first, so that fast mouse movements draw
+// unbroken lines.
+fn cells_on_line(from: (i64, i64), to: (i64, i64)) -> Vec<(i64, i64)> {
+  let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs());
+  (1..=steps)
+      .map(|step| {
+        let fraction = step as f64 / steps as f64;
+        let x = from.0 as f64 + (to.0 - from.0) as f64 * fraction;
+        let y = from.1 as f64 + (to.1 - from.1) as f64 * fraction;
+        (x.round() as i64, y.round() as i64)
+      })
+      .collect()
+}
+
+fn allocate_dense_buffers<T: Clone + Send + Default, const N: usize>(layout: &str) -> (Box<[T; N]>, Box<[T; N]>) {
+  let bytes = N * size_of::<T>();
+  println!("Boards is {} x {}", conway::BOARD_WIDTH_CELLS, conway::BOARD_HEIGHT_CELLS);
+  println!("Allocating 2 buffers of size {} ({} GB) laid out in {} each", bytes, bytes as f64 / 1024.0 / 1024.0 / 1024.0, layout);
+
+  print!("Allocating buffer 1...");
+  let buffer1 = Box::<[T; N]>::new_uninit();
+  println!("done.");
+  print!("Allocating buffer 2...");
+  let buffer2 = Box::<[T; N]>::new_uninit();
+  println!("done.");
+
+  print!("Zeroing-out buffer 1...");
+  let buffer1 = zero_out_buffer(buffer1);
+  println!("done.");
+  print!("Zeroing-out buffer 2...");
+  let buffer2 = zero_out_buffer(buffer2);
+  println!("done.");
+
+  (buffer1, buffer2)
 }
 
+// Threads to step the board with, which is one for each processor unless given with --threads. Every step asks, and
+// finding out how many processors there are means reading the system's settings, so it is only looked up the first time.
 fn num_threads() -> usize {
-  std::cmp::min(MAX_THREADS, std::thread::available_parallelism().unwrap().get())
+  static THREADS: OnceLock<usize> = OnceLock::new();
+  *THREADS.get_or_init(|| parsed_argument("--threads", std::thread::available_parallelism().unwrap().get()).clamp(1, MAX_THREADS))
+}
+
+// Adds to the time the given thread has spent stepping the board since the busy times were last taken.
+fn record_busy_time(thread: usize, duration: Duration) {
+  BUSY_NANOSECONDS[thread].fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+}
+
+// How long each thread has spent stepping the board, starting the count over.
+fn take_busy_times() -> Vec<Duration> {
+  BUSY_NANOSECONDS[..num_threads()].iter().map(|nanoseconds| Duration::from_nanos(nanoseconds.swap(0, Ordering::Relaxed))).collect()
 }
 
 fn zero_out_buffer<T: Clone + Send + Default, const N: usize>(buffer: Box<MaybeUninit<[T; N]>>) -> Box<[T; N]> {
@@ -83,7 +657,7 @@ fn zero_out_buffer<T: Clone + Send + Default, const N: usize>(buffer: Box<MaybeU
 
 fn zero_out_buffer_in_parallel<T: Clone + Send + Default, const N: usize>(buffer: Box<MaybeUninit<[T; N]>>) -> Box<[T; N]> {
   let num_threads = num_threads();
-  let chunk_size = (N + num_threads - 1) / num_threads;
+  let chunk_size = N.div_ceil(num_threads);
 
   let mut buffer = unsafe { buffer.assume_init() };
   std::thread::scope(|scope| {
@@ -103,19 +677,3 @@ fn zero_out_buffer_serially<T: Clone + Send + Default, const N: usize>(buffer: B
   buffer.fill(T::default());
   buffer
 }
-
-fn compute_next_board_state(source: &conway::Board, destination: &mut conway::Board) {
-  let num_threads = num_threads();
-  let chunk_size = (source.len() + num_threads - 1) / num_threads;
-
-  std::thread::scope(|scope| {
-    let mut threads = Vec::<std::thread::ScopedJoinHandle<()>>::new();
-    for (chunk_index, chunk) in destination.chunks_mut(chunk_size).enumerate() {
-      threads.push(scope.spawn(move || {
-        for (block_index, block) in chunk.iter_mut().enumerate() {
-          *block = conway::new_value_for_block(source, chunk_index * chunk_size + block_index);
-        }
-      }));
-    }
-  });
-}
@@ -0,0 +1,201 @@
+use crate::conway;
+use crate::conway::{BlockNeighborhood, BlockRule, CellBlock, ALL_COLUMNS, ALL_ROWS, BOTTOM, BOTTOM_LEFT, BOTTOM_RIGHT, CENTER, CELL_BLOCK_WIDTH, EMPTY_BLOCK, FIRST_COLUMN, FIRST_ROW, LAST_COLUMN, LAST_ROW, LEFT, RIGHT, TOP, TOP_LEFT, TOP_RIGHT};
+
+// The top-left cell of every 2x2 partition that starts on an even row and column of a block, which is every other cell
+// of every other row.
+const PARTITION_CELLS: CellBlock = conway::cells_in(ALL_COLUMNS / 3, ALL_ROWS / 3);
+
+// Margolus rules divide the universe into 2x2 partitions and replace each partition as a whole. Partitions start on
+// even rows and columns on even generations, and on odd ones on odd generations. A partition is numbered by adding 1
+// for its top-left cell, 2 for its top-right cell, 4 for its bottom-left cell and 8 for its bottom-right cell.
+#[derive(Clone)]
+pub struct MargolusRule {
+  // The partition each partition becomes on even and odd generations. Rules that fill empty partitions, such as
+  // Critters, would fill the whole universe every other generation, so they are run in alternating frames instead:
+  // even generations store the complement of every cell, and odd generations read it back. This shows the pattern
+  // the way it is usually drawn, without the background flashing.
+  tables: [[u8; 16]; 2],
+}
+
+impl MargolusRule {
+  // The rule for the step from the given generation to the next.
+  pub fn step(&self, generation: u64) -> MargolusStep<'_> {
+    MargolusStep { table: &self.tables[(generation % 2) as usize], odd: generation % 2 == 1 }
+  }
+}
+
+pub struct MargolusStep<'a> {
+  table: &'a [u8; 16],
+  odd: bool,
+}
+
+impl BlockRule for MargolusStep<'_> {
+  fn new_value_for_neighborhood(&self, neighborhood: &BlockNeighborhood) -> CellBlock {
+    if !self.odd {
+      return new_value_for_partitions(neighborhood[CENTER], self.table);
+    }
+
+    // Odd partitions straddle the edges of the block, so the block is stepped through windows shifted diagonally by
+    // one cell, in which they line up again. The window shifted down and to the right holds the partitions of all but
+    // the first row and column, the one shifted up and to the left holds the rest except for the top-right and
+    // bottom-left corners, which the remaining two windows hold.
+    let shifted = |dx: i64, dy: i64| new_value_for_partitions(shifted_block(neighborhood, dx, dy), self.table);
+    let first_row_and_column = (FIRST_ROW & !LAST_COLUMN) | (FIRST_COLUMN & !LAST_ROW);
+    (shifted(1, 1) << (CELL_BLOCK_WIDTH + 1)) & !(FIRST_ROW | FIRST_COLUMN)
+        | (shifted(-1, -1) >> (CELL_BLOCK_WIDTH + 1)) & first_row_and_column
+        | (shifted(1, -1) >> (CELL_BLOCK_WIDTH - 1)) & FIRST_ROW & LAST_COLUMN
+        | (shifted(-1, 1) << (CELL_BLOCK_WIDTH - 1)) & LAST_ROW & FIRST_COLUMN
+  }
+}
+
+// Replaces every partition that starts on an even row and column of the block.
+fn new_value_for_partitions(block: CellBlock, table: &[u8; 16]) -> CellBlock {
+  let corners = [block, block >> 1, block >> CELL_BLOCK_WIDTH, block >> (CELL_BLOCK_WIDTH + 1)].map(|cells| cells & PARTITION_CELLS);
+  let mut new_corners = [EMPTY_BLOCK; 4];
+  for (partition, &new_partition) in table.iter().enumerate() {
+    let matching = corners.iter()
+        .enumerate()
+        .fold(PARTITION_CELLS, |matching, (bit, &corner)| matching & if (partition >> bit) & 1 == 1 { corner } else { !corner });
+    for (bit, new_corner) in new_corners.iter_mut().enumerate() {
+      if (new_partition >> bit) & 1 == 1 {
+        *new_corner |= matching;
+      }
+    }
+  }
+  new_corners[0] | (new_corners[1] << 1) | (new_corners[2] << CELL_BLOCK_WIDTH) | (new_corners[3] << (CELL_BLOCK_WIDTH + 1))
+}
+
+// Returns the block of cells offset by one cell in each of the given directions from the center block.
+fn shifted_block(neighborhood: &BlockNeighborhood, dx: i64, dy: i64) -> CellBlock {
+  let shift_row = |left: CellBlock, center: CellBlock, right: CellBlock| match dx {
+    1 => conway::from_right(center, right),
+    -1 => conway::from_left(center, left),
+    _ => center,
+  };
+  let above = shift_row(neighborhood[TOP_LEFT], neighborhood[TOP], neighborhood[TOP_RIGHT]);
+  let center = shift_row(neighborhood[LEFT], neighborhood[CENTER], neighborhood[RIGHT]);
+  let below = shift_row(neighborhood[BOTTOM_LEFT], neighborhood[BOTTOM], neighborhood[BOTTOM_RIGHT]);
+  match dy {
+    1 => conway::from_below(center, below),
+    -1 => conway::from_above(center, above),
+    _ => center,
+  }
+}
+
+pub fn is_margolus_rule(rule: &str) -> bool {
+  rule.trim().to_ascii_uppercase().starts_with("MS,D")
+}
+
+// Parses Margolus rules in MCell's notation, which lists the partition each of the 16 partitions becomes, such as
+// Critters "MS,D15;14;13;3;11;5;6;1;7;9;10;2;12;4;8;0" or the Billiard Ball Machine "MS,D0;8;4;3;2;5;9;7;1;6;10;11;12;13;14;15".
+pub fn parse_margolus_rule(rule: &str) -> Result<MargolusRule, String> {
+  let partitions = &rule.trim()["MS,D".len()..];
+  let mut table = [0u8; 16];
+  let mut count = 0;
+  for (index, partition) in partitions.split(';').enumerate() {
+    let partition = match partition.trim().parse::<u8>() {
+      Ok(partition) if partition < 16 && index < 16 => partition,
+      _ => return Err(format!("Invalid partition \"{}\" in rule \"{}\"", partition, rule)),
+    };
+    table[index] = partition;
+    count += 1;
+  }
+  if count != 16 {
+    return Err(format!("Rule \"{}\" should list 16 partitions", rule));
+  }
+
+  let tables = match (table[0], table[15]) {
+    (0, _) => [table, table],
+    (15, 0) => [table.map(|partition| partition ^ 15), std::array::from_fn(|partition| table[partition ^ 15])],
+    _ => return Err(format!("Rule \"{}\" changes empty partitions, which is only supported if it swaps them with full ones", rule)),
+  };
+  Ok(MargolusRule { tables })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::sparse;
+  use crate::sparse::SparseBoard;
+
+  const CRITTERS: &str = "MS,D15;14;13;3;11;5;6;1;7;9;10;2;12;4;8;0";
+  const BILLIARD_BALL_MACHINE: &str = "MS,D0;8;4;3;2;5;9;7;1;6;10;11;12;13;14;15";
+  const AREA: i64 = 40;
+
+  // Replaces the partitions of the cells from -AREA to AREA one at a time, the way the rules are described. The cells
+  // outside of the area are all the same as the background, which Critters turns over every generation.
+  fn step_cells(cells: &mut Vec<Vec<bool>>, background: &mut bool, table: &[u8; 16], generation: u64) {
+    let offset = (generation % 2) as usize;
+    let cell = |cells: &Vec<Vec<bool>>, x: usize, y: usize| cells.get(y).and_then(|row| row.get(x)).copied().unwrap_or(*background);
+    let mut next = cells.clone();
+    for y in (0..cells.len() + 2).skip(offset).step_by(2) {
+      for x in (0..cells.len() + 2).skip(offset).step_by(2) {
+        // Partitions on odd generations start one cell before the area.
+        let (x, y) = (x.wrapping_sub(2 * offset), y.wrapping_sub(2 * offset));
+        let partition = [(0, 0), (1, 0), (0, 1), (1, 1)]
+            .iter()
+            .enumerate()
+            .fold(0, |partition, (bit, &(dx, dy))| partition | (cell(cells, x.wrapping_add(dx), y.wrapping_add(dy)) as u8) << bit);
+        for (bit, (dx, dy)) in [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().enumerate() {
+          if let Some(cell) = next.get_mut(y.wrapping_add(dy)).and_then(|row| row.get_mut(x.wrapping_add(dx))) {
+            *cell = (table[partition as usize] >> bit) & 1 == 1;
+          }
+        }
+      }
+    }
+    *cells = next;
+    *background = (table[if *background { 15 } else { 0 }] & 1) == 1;
+  }
+
+  fn soup() -> Vec<Vec<bool>> {
+    (-AREA..AREA).map(|y| (-AREA..AREA).map(|x| x.abs() < 8 && y.abs() < 8 && (x * 7 + y * 13 + x * y).rem_euclid(5) < 2).collect()).collect()
+  }
+
+  fn live_cells(board: &SparseBoard) -> Vec<Vec<bool>> {
+    (-AREA..AREA).map(|y| (-AREA..AREA).map(|x| board.get_cell(x, y)).collect()).collect()
+  }
+
+  fn assert_steps_like_cells(rule: &str, table: [u8; 16], generations: u64) {
+    let rule = parse_margolus_rule(rule).unwrap();
+    let (mut cells, mut background) = (soup(), false);
+    let mut board = SparseBoard::new();
+    for (y, row) in (-AREA..).zip(&cells) {
+      for (x, &alive) in (-AREA..).zip(row) {
+        board.set_cell(x, y, alive);
+      }
+    }
+
+    for generation in 0..generations {
+      step_cells(&mut cells, &mut background, &table, generation);
+      let mut next = SparseBoard::new();
+      sparse::compute_next_board_state_with_rule(&board, &mut next, &rule.step(generation));
+      board = next;
+
+      // Rules that flip the background are stored inverted after every even generation.
+      let inverted = table[0] == 15 && generation % 2 == 0;
+      let expected: Vec<Vec<bool>> = cells.iter().map(|row| row.iter().map(|&alive| alive != inverted).collect()).collect();
+      assert_eq!(live_cells(&board), expected, "generation {}", generation + 1);
+    }
+  }
+
+  #[test]
+  fn billiard_ball_machine_steps_like_cells() {
+    assert_steps_like_cells(BILLIARD_BALL_MACHINE, [0, 8, 4, 3, 2, 5, 9, 7, 1, 6, 10, 11, 12, 13, 14, 15], 16);
+  }
+
+  // Critters turns every empty partition full and every full one empty, so the background flashes on and off. The board
+  // shows the same cells on every other generation, and never fills up with the background.
+  #[test]
+  fn critters_alternates_between_generations() {
+    assert_steps_like_cells(CRITTERS, [15, 14, 13, 3, 11, 5, 6, 1, 7, 9, 10, 2, 12, 4, 8, 0], 16);
+  }
+
+  #[test]
+  fn rejects_invalid_rules() {
+    assert!(is_margolus_rule(" ms,d0;8;4;3;2;5;9;7;1;6;10;11;12;13;14;15"));
+    assert!(parse_margolus_rule("MS,D0;8;4;3;2;5;9;7;1;6;10;11;12;13;14").is_err());
+    assert!(parse_margolus_rule("MS,D0;8;4;3;2;5;9;7;1;6;10;11;12;13;14;15;0").is_err());
+    assert!(parse_margolus_rule("MS,D0;8;4;3;2;5;9;7;1;6;10;11;12;13;14;16").is_err());
+    assert!(parse_margolus_rule("MS,D1;8;4;3;2;5;9;7;0;6;10;11;12;13;14;15").is_err());
+  }
+}
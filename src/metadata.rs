@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use crate::conway::{CellBlock, CELLS_PER_BLOCK, EMPTY_BLOCK, FIRST_CELL};
+use crate::universe::{BlockCoordinate, PlaneBlock};
+
+// When each cell of a block last changed state, as generations since tracking started, and how many times it has.
+#[derive(Clone)]
+pub struct BlockMetadata {
+  pub last_changed: [u32; CELLS_PER_BLOCK as usize],
+  pub changes: [u32; CELLS_PER_BLOCK as usize],
+}
+
+// What happened to every cell since tracking started, built up from the blocks of cells that flip each generation so
+// that it costs nothing for the parts of the board that stay still. Cells that have not changed since are counted as
+// having last changed when tracking started. A live cell's age is the time since it last changed, and a dead cell's the
+// time since it died.
+pub struct Metadata {
+  start: u64,
+  blocks: HashMap<BlockCoordinate, BlockMetadata>,
+  max_changes: u32,
+}
+
+impl Metadata {
+  pub fn new(generation: u64) -> Self {
+    Metadata { start: generation, blocks: HashMap::new(), max_changes: 0 }
+  }
+
+  // Records the cells flipped in any of the board's planes on the way to the given generation.
+  pub fn record(&mut self, generation: u64, changed: &[PlaneBlock]) {
+    let generation = generation.saturating_sub(self.start).min(u32::MAX as u64) as u32;
+    // Cells flipping in several planes at once only change state once.
+    let mut changed_cells: HashMap<BlockCoordinate, CellBlock> = HashMap::new();
+    for &(_, coordinate, cells) in changed {
+      *changed_cells.entry(coordinate).or_insert(EMPTY_BLOCK) |= cells;
+    }
+
+    for (coordinate, cells) in changed_cells {
+      let block = self.blocks.entry(coordinate).or_insert_with(|| BlockMetadata {
+        last_changed: [0; CELLS_PER_BLOCK as usize],
+        changes: [0; CELLS_PER_BLOCK as usize],
+      });
+      let mut remaining = cells;
+      while remaining != 0 {
+        let cell = remaining.trailing_zeros() as usize;
+        remaining &= !(FIRST_CELL << cell);
+        block.last_changed[cell] = generation;
+        block.changes[cell] += 1;
+        self.max_changes = self.max_changes.max(block.changes[cell]);
+      }
+    }
+  }
+
+  pub fn block(&self, coordinate: BlockCoordinate) -> Option<&BlockMetadata> {
+    self.blocks.get(&coordinate)
+  }
+
+  // How many generations ago a cell last changed, given when it did as recorded in its block.
+  pub fn generations_since(&self, generation: u64, last_changed: u32) -> u64 {
+    generation.saturating_sub(self.start + last_changed as u64)
+  }
+
+  // The most times any one cell has changed.
+  pub fn max_changes(&self) -> u32 {
+    self.max_changes
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::conway::{CELL_BLOCK_WIDTH, FIRST_CELL};
+  use crate::history::History;
+  use crate::universe::{Buffers, Universe};
+
+  #[test]
+  fn cells_flipping_in_several_planes_change_once() {
+    let mut metadata = Metadata::new(10);
+    metadata.record(11, &[(0, (0, 0), FIRST_CELL | FIRST_CELL << 1), (1, (0, 0), FIRST_CELL << 1 | FIRST_CELL << 2), (0, (-1, 2), FIRST_CELL << 5)]);
+    metadata.record(14, &[(1, (0, 0), FIRST_CELL << 2)]);
+    let block = metadata.block((0, 0)).unwrap();
+    assert_eq!((&block.last_changed[..4], &block.changes[..4]), (&[1, 1, 4, 0][..], &[1, 1, 2, 0][..]));
+    assert_eq!(metadata.block((-1, 2)).unwrap().changes[5], 1);
+    assert!(metadata.block((1, 0)).is_none());
+    assert_eq!(metadata.max_changes(), 2);
+    assert_eq!(metadata.generations_since(20, block.last_changed[2]), 6);
+    // Cells that never changed count from when tracking started.
+    assert_eq!(metadata.generations_since(20, block.last_changed[3]), 10);
+  }
+
+  // A blinker's middle cell stays alive while the four cells around it flip every generation.
+  #[test]
+  fn tracks_blinker_steps_through_the_history() {
+    let mut universe = Universe::new(Buffers::for_rule(None), None);
+    for x in 3..6 {
+      universe.set_cell(x, 4, 1);
+    }
+    let mut history = History::new(&universe);
+    history.track_metadata(&universe, true);
+    for _ in 0..6 {
+      history.step(&mut universe);
+    }
+
+    let metadata = history.metadata().unwrap();
+    let block = metadata.block((0, 0)).unwrap();
+    let cell = |x: usize, y: usize| y * CELL_BLOCK_WIDTH as usize + x;
+    assert_eq!((block.changes[cell(4, 4)], metadata.generations_since(6, block.last_changed[cell(4, 4)])), (0, 6));
+    for (x, y) in [(3, 4), (5, 4), (4, 3), (4, 5)] {
+      assert_eq!((block.changes[cell(x, y)], block.last_changed[cell(x, y)]), (6, 6), "cell ({}, {})", x, y);
+    }
+    assert_eq!(metadata.max_changes(), 6);
+
+    // Moving back starts tracking over from there.
+    history.back(&mut universe);
+    assert_eq!(history.metadata().unwrap().max_changes(), 0);
+    history.step(&mut universe);
+    assert_eq!(history.metadata().unwrap().max_changes(), 1);
+  }
+}
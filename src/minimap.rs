@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+use crate::conway;
+use crate::history::History;
+use crate::render::Viewport;
+use crate::sparse::{TileCoordinate, TILE_HEIGHT_CELLS, TILE_WIDTH_CELLS};
+use crate::universe::{Buffers, Universe};
+
+// The minimap is drawn this many pixels across its longer side, this far from the bottom-left corner of the window.
+const MINIMAP_SIZE: f64 = 192.0;
+const MARGIN: u32 = 8;
+
+const BACKGROUND_COLOR: u32 = 0xFF080810;
+const BORDER_COLOR: u32 = 0xFF404050;
+const TILE_COLOR: u32 = 0xFF8090A0;
+const VIEW_COLOR: u32 = 0xFFF0C040;
+
+// Where the minimap was last drawn, and the cells it showed there.
+#[derive(Clone, Copy)]
+struct Layout {
+  pixel_x: u32,
+  pixel_y: u32,
+  width: u32,
+  height: u32,
+  // The cell at the top-left corner, and how many cells across each pixel covers.
+  cell_x: f64,
+  cell_y: f64,
+  cells_per_pixel: f64,
+}
+
+// A small map of the whole universe in a corner of the window, showing which tiles hold any cells and where the view
+// is, which can be clicked to move the view there.
+pub struct Minimap {
+  pub visible: bool,
+  tiles: HashSet<TileCoordinate>,
+  layout: Option<Layout>,
+}
+
+impl Minimap {
+  pub fn new() -> Self {
+    Minimap { visible: true, tiles: HashSet::new(), layout: None }
+  }
+
+  // Catches up with the cells flipped since the last refresh, looking through only the tiles they are in. Finding every
+  // occupied tile of the dense board means scanning all of it, which is left for when the history cannot tell what
+  // changed.
+  pub fn refresh(&mut self, universe: &Universe, history: &mut History) {
+    match history.take_touched_tiles() {
+      Some(touched_tiles) => {
+        for tile_coordinate in touched_tiles {
+          if universe.is_tile_occupied(tile_coordinate) {
+            self.tiles.insert(tile_coordinate);
+          } else {
+            self.tiles.remove(&tile_coordinate);
+          }
+        }
+      }
+      None => self.tiles = universe.occupied_tiles().into_iter().collect(),
+    }
+  }
+
+  // Whether the minimap was last drawn over the given pixel.
+  pub fn contains(&self, pixel_x: f64, pixel_y: f64) -> bool {
+    self.visible && self.layout.is_some_and(|layout| {
+      (layout.pixel_x as f64..(layout.pixel_x + layout.width) as f64).contains(&pixel_x)
+          && (layout.pixel_y as f64..(layout.pixel_y + layout.height) as f64).contains(&pixel_y)
+    })
+  }
+
+  // The point of the universe, measured in cells, shown at the given pixel of the minimap.
+  pub fn cell_at(&self, pixel_x: f64, pixel_y: f64) -> Option<(f64, f64)> {
+    let layout = self.layout?;
+    Some((
+      layout.cell_x + (pixel_x - layout.pixel_x as f64) * layout.cells_per_pixel,
+      layout.cell_y + (pixel_y - layout.pixel_y as f64) * layout.cells_per_pixel,
+    ))
+  }
+
+  // Draws the minimap over a buffer of the viewport's size. It shows the whole of the dense board, and otherwise all of
+  // the occupied tiles along with the view, wherever that is.
+  pub fn draw(&mut self, universe: &Universe, viewport: &Viewport, pixels: &mut [u32]) {
+    if pixels.is_empty() {
+      return;
+    }
+    let corners = [(0.0, 0.0), (viewport.width as f64, 0.0), (0.0, viewport.height as f64), (viewport.width as f64, viewport.height as f64)]
+        .map(|(pixel_x, pixel_y)| viewport.cell_at(pixel_x, pixel_y));
+    let view = bounds(corners.iter().map(|&(x, y)| (x as f64, y as f64, (x + 1) as f64, (y + 1) as f64))).unwrap();
+    let tiles = bounds(self.tiles.iter().map(|&(tile_x, tile_y)| {
+      let (x, y) = ((tile_x * TILE_WIDTH_CELLS) as f64, (tile_y * TILE_HEIGHT_CELLS) as f64);
+      (x, y, x + TILE_WIDTH_CELLS as f64, y + TILE_HEIGHT_CELLS as f64)
+    }));
+    let (left, top, right, bottom) = match (&universe.buffers, tiles) {
+      (Buffers::Dense(..), _) => (0.0, 0.0, conway::BOARD_WIDTH_CELLS as f64, conway::BOARD_HEIGHT_CELLS as f64),
+      (_, Some(tiles)) => bounds([view, tiles].into_iter()).unwrap(),
+      (_, None) => view,
+    };
+
+    let cells_per_pixel = (right - left).max(bottom - top) / MINIMAP_SIZE;
+    let width = (((right - left) / cells_per_pixel).ceil() as u32).clamp(1, viewport.width.saturating_sub(2 * MARGIN).max(1));
+    let height = (((bottom - top) / cells_per_pixel).ceil() as u32).clamp(1, viewport.height.saturating_sub(2 * MARGIN).max(1));
+    let layout = Layout {
+      pixel_x: MARGIN,
+      pixel_y: viewport.height.saturating_sub(MARGIN + height),
+      width,
+      height,
+      cell_x: left,
+      cell_y: top,
+      cells_per_pixel,
+    };
+    self.layout = Some(layout);
+
+    let mut fill = |(left, top, right, bottom): (f64, f64, f64, f64), color: u32, outline: bool| {
+      // Every tile gets at least a pixel, however small it is drawn.
+      let to_pixels = |from: f64, to: f64, cell: f64, size: u32| {
+        let first = (((from - cell) / cells_per_pixel).floor().max(0.0) as u32).min(size);
+        let last = ((((to - cell) / cells_per_pixel).ceil().max(0.0) as u32).min(size)).max((first + 1).min(size));
+        first..last
+      };
+      let (columns, rows) = (to_pixels(left, right, layout.cell_x, layout.width), to_pixels(top, bottom, layout.cell_y, layout.height));
+      for row in rows.clone() {
+        for column in columns.clone() {
+          let on_edge = row == rows.start || row + 1 == rows.end || column == columns.start || column + 1 == columns.end;
+          if !outline || on_edge {
+            pixels[(layout.pixel_y + row) as usize * viewport.width as usize + (layout.pixel_x + column) as usize] = color;
+          }
+        }
+      }
+    };
+
+    fill((left, top, right, bottom), BACKGROUND_COLOR, false);
+    fill((left, top, right, bottom), BORDER_COLOR, true);
+    for &(tile_x, tile_y) in &self.tiles {
+      let (x, y) = ((tile_x * TILE_WIDTH_CELLS) as f64, (tile_y * TILE_HEIGHT_CELLS) as f64);
+      fill((x, y, x + TILE_WIDTH_CELLS as f64, y + TILE_HEIGHT_CELLS as f64), TILE_COLOR, false);
+    }
+    fill(view, VIEW_COLOR, true);
+  }
+}
+
+// The smallest box, as (left, top, right, bottom), holding all of the given boxes.
+fn bounds<I: Iterator<Item = (f64, f64, f64, f64)>>(boxes: I) -> Option<(f64, f64, f64, f64)> {
+  boxes.reduce(|(left, top, right, bottom), (other_left, other_top, other_right, other_bottom)| {
+    (left.min(other_left), top.min(other_top), right.max(other_right), bottom.max(other_bottom))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sorted(tiles: &HashSet<TileCoordinate>) -> Vec<TileCoordinate> {
+    let mut tiles: Vec<TileCoordinate> = tiles.iter().copied().collect();
+    tiles.sort_unstable();
+    tiles
+  }
+
+  fn occupied_tiles(universe: &Universe) -> Vec<TileCoordinate> {
+    let mut tiles = universe.occupied_tiles();
+    tiles.sort_unstable();
+    tiles
+  }
+
+  // Gliders flying out of opposite corners of a tile leave it empty, which the minimap follows from the tiles the history
+  // touched.
+  #[test]
+  fn follows_the_tiles_the_history_touched() {
+    let mut universe = Universe::new(Buffers::for_rule(None), None);
+    for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+      universe.set_cell(TILE_WIDTH_CELLS - 12 + x, TILE_HEIGHT_CELLS - 12 + y, 1);
+      universe.set_cell(8 - x, 8 - y, 1);
+    }
+    let mut history = History::new(&universe);
+    let mut minimap = Minimap::new();
+    for generation in 0..400 {
+      if generation % 7 == 0 {
+        minimap.refresh(&universe, &mut history);
+        assert_eq!(sorted(&minimap.tiles), occupied_tiles(&universe), "generation {}", generation);
+      }
+      history.step(&mut universe);
+    }
+    assert_eq!(sorted(&minimap.tiles), [(-1, -1), (1, 1)]);
+
+    history.edit_cell(&mut universe, -3 * TILE_WIDTH_CELLS, 0, 1);
+    for _ in 0..150 {
+      history.back(&mut universe);
+    }
+    minimap.refresh(&universe, &mut history);
+    assert_eq!(sorted(&minimap.tiles), occupied_tiles(&universe));
+    history.reset(&mut universe);
+    minimap.refresh(&universe, &mut history);
+    assert_eq!(sorted(&minimap.tiles), [(0, 0)]);
+  }
+
+  #[test]
+  fn clicks_map_back_to_the_tiles_drawn() {
+    let mut universe = Universe::new(Buffers::for_rule(None), None);
+    universe.set_cell(0, 0, 1);
+    universe.set_cell(9 * TILE_WIDTH_CELLS + 100, 200, 1);
+    let mut history = History::new(&universe);
+    let viewport = Viewport::new(640, 480, false);
+    let mut pixels = vec![0; 640 * 480];
+    let mut minimap = Minimap::new();
+    minimap.refresh(&universe, &mut history);
+    minimap.draw(&universe, &viewport, &mut pixels);
+
+    for (pixel, &color) in pixels.iter().enumerate() {
+      let (pixel_x, pixel_y) = ((pixel % 640) as f64 + 0.5, (pixel / 640) as f64 + 0.5);
+      assert_eq!(minimap.contains(pixel_x, pixel_y), color != 0, "pixel ({}, {})", pixel_x, pixel_y);
+      // Tiles are drawn out to every pixel they partly cover.
+      if color == TILE_COLOR {
+        let (left, top) = minimap.cell_at(pixel_x - 0.5, pixel_y - 0.5).unwrap();
+        let (right, bottom) = minimap.cell_at(pixel_x + 0.5, pixel_y + 0.5).unwrap();
+        let overlaps = [(0, 0), (9, 0)].iter().any(|&(tile_x, tile_y)| {
+          let (x, y) = ((tile_x * TILE_WIDTH_CELLS) as f64, (tile_y * TILE_HEIGHT_CELLS) as f64);
+          left < x + TILE_WIDTH_CELLS as f64 && x < right && top < y + TILE_HEIGHT_CELLS as f64 && y < bottom
+        });
+        assert!(overlaps, "pixel ({}, {})", pixel_x, pixel_y);
+      }
+    }
+    for (x, y) in [(TILE_WIDTH_CELLS / 2, TILE_HEIGHT_CELLS / 2), (9 * TILE_WIDTH_CELLS + TILE_WIDTH_CELLS / 2, TILE_HEIGHT_CELLS / 2)] {
+      let layout = minimap.layout.unwrap();
+      let pixel_x = layout.pixel_x + ((x as f64 - layout.cell_x) / layout.cells_per_pixel) as u32;
+      let pixel_y = layout.pixel_y + ((y as f64 - layout.cell_y) / layout.cells_per_pixel) as u32;
+      assert_eq!(pixels[(pixel_y * 640 + pixel_x) as usize], TILE_COLOR);
+    }
+    assert!(pixels.contains(&VIEW_COLOR));
+  }
+}
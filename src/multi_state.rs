@@ -0,0 +1,181 @@
+use crate::conway::{CellBlock, CELLS_PER_BLOCK, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK};
+use crate::rule_table::RuleTable;
+use crate::sparse;
+use crate::sparse::{SparseBoard, Tile, TileCoordinate, TILE_HEIGHT_BLOCKS, TILE_HEIGHT_CELLS, TILE_WIDTH_BLOCKS, TILE_WIDTH_CELLS};
+
+// The most bit-planes a board may need, enough for the 255 states a rule table can describe.
+const MAX_PLANES: usize = 8;
+
+// Each tile is unpacked into one byte per cell, with a one cell border around it for the neighbors of its edge cells.
+const UNPACKED_WIDTH: i64 = TILE_WIDTH_CELLS + 2;
+const UNPACKED_HEIGHT: i64 = TILE_HEIGHT_CELLS + 2;
+
+// A board for rule tables, stored as bit-planes of sparse boards holding each cell's state in binary. State 0 is
+// empty, so only tiles containing cells in other states are allocated.
+pub struct MultiStateBoard {
+  planes: Vec<SparseBoard>,
+  states: u8,
+}
+
+impl MultiStateBoard {
+  pub fn new(states: u8) -> Self {
+    assert!(states >= 2);
+    let planes = (u8::BITS - (states - 1).leading_zeros()) as usize;
+    MultiStateBoard { planes: (0..planes).map(|_| SparseBoard::new()).collect(), states }
+  }
+
+  pub fn get_cell(&self, x: i64, y: i64) -> u8 {
+    self.planes.iter()
+        .enumerate()
+        .fold(0u8, |state, (bit, plane)| state | ((plane.get_cell(x, y) as u8) << bit))
+  }
+
+  pub fn set_cell(&mut self, x: i64, y: i64, state: u8) {
+    assert!(state < self.states);
+    for (bit, plane) in self.planes.iter_mut().enumerate() {
+      plane.set_cell(x, y, (state >> bit) & 1 == 1);
+    }
+  }
+
+  // Returns the state of every cell of the block at the given block coordinates, in the same order as a block's bits.
+  pub fn block_states(&self, block_x: i64, block_y: i64) -> [u8; CELLS_PER_BLOCK as usize] {
+    let blocks: Vec<CellBlock> = self.planes.iter().map(|plane| plane.block(block_x, block_y)).collect();
+    states_from_planes(&blocks)
+  }
+
+  // Counts the cells in any state other than 0.
+  pub fn population(&self) -> u64 {
+    let mut population = 0;
+    for coordinate in self.occupied_tiles() {
+      let tiles: Vec<&Tile> = self.planes.iter().filter_map(|plane| plane.tile(coordinate)).collect();
+      for block_index in 0..TILE_WIDTH_BLOCKS * TILE_HEIGHT_BLOCKS {
+        let occupied = tiles.iter().fold(EMPTY_BLOCK, |occupied, tile| occupied | tile[block_index]);
+        population += occupied.count_ones() as u64;
+      }
+    }
+    population
+  }
+
+  pub fn tile_count(&self) -> usize {
+    self.occupied_tiles().len()
+  }
+
+  // The bit-planes the board stores each cell's state in.
+  pub fn planes(&self) -> &[SparseBoard] {
+    &self.planes
+  }
+
+  pub fn planes_mut(&mut self) -> &mut [SparseBoard] {
+    &mut self.planes
+  }
+
+  pub fn clear(&mut self) {
+    for plane in &mut self.planes {
+      plane.clear();
+    }
+  }
+
+  fn occupied_tiles(&self) -> Vec<TileCoordinate> {
+    let mut tiles: Vec<TileCoordinate> = self.planes.iter().flat_map(|plane| plane.tiles().map(|(&coordinate, _)| coordinate)).collect();
+    tiles.sort_unstable();
+    tiles.dedup();
+    tiles
+  }
+}
+
+// Converts a block of each of a board's planes into the states of the block's cells.
+pub fn states_from_planes(blocks: &[CellBlock]) -> [u8; CELLS_PER_BLOCK as usize] {
+  let mut states = [0u8; CELLS_PER_BLOCK as usize];
+  for (bit, &block) in blocks.iter().enumerate() {
+    if block == 0 {
+      continue;
+    }
+    for (cell, state) in states.iter_mut().enumerate() {
+      *state |= u8::from((block >> cell) & 1 == 1) << bit;
+    }
+  }
+  states
+}
+
+// Converts the states of a block's cells into a block of each of the given number of planes.
+pub fn planes_from_states(states: &[u8; CELLS_PER_BLOCK as usize], plane_count: usize) -> Vec<CellBlock> {
+  (0..plane_count)
+      .map(|bit| states.iter().enumerate().fold(EMPTY_BLOCK, |block, (cell, &state)| block | (CellBlock::from((state >> bit) & 1) << cell)))
+      .collect()
+}
+
+pub fn compute_next_board_state(source: &MultiStateBoard, destination: &mut MultiStateBoard, rule: &RuleTable) {
+  assert_eq!(source.states, destination.states);
+  assert_eq!(source.states, rule.states);
+
+  let mut candidates: Vec<TileCoordinate> = source.planes.iter().flat_map(|plane| plane.candidate_tiles(1)).collect();
+  candidates.sort_unstable();
+  candidates.dedup();
+
+  destination.clear();
+  for (coordinate, tiles) in sparse::compute_tiles_in_parallel(&candidates, |coordinate| new_value_for_tile(source, coordinate, rule)) {
+    for (plane, tile) in destination.planes.iter_mut().zip(tiles) {
+      plane.insert_tile(coordinate, tile);
+    }
+  }
+}
+
+fn new_value_for_tile(board: &MultiStateBoard, tile_coordinate: TileCoordinate, rule: &RuleTable) -> Vec<Box<Tile>> {
+  let tiles: Vec<sparse::TileNeighborhood> = board.planes.iter().map(|plane| plane.tile_neighborhood(tile_coordinate)).collect();
+
+  // Unpack the tile and its border into bytes, noting which blocks hold anything so that empty space can be skipped.
+  const OCCUPIED_WIDTH: usize = TILE_WIDTH_BLOCKS + 2;
+  let mut cells = vec![0u8; (UNPACKED_WIDTH * UNPACKED_HEIGHT) as usize];
+  let mut occupied = vec![false; OCCUPIED_WIDTH * (TILE_HEIGHT_BLOCKS + 2)];
+  let mut blocks = [EMPTY_BLOCK; MAX_PLANES];
+  for block_y in -1..=TILE_HEIGHT_BLOCKS as i64 {
+    for block_x in -1..=TILE_WIDTH_BLOCKS as i64 {
+      for (block, plane) in blocks.iter_mut().zip(&tiles) {
+        *block = plane.block(block_x, block_y);
+      }
+      if blocks.iter().all(|&block| block == 0) {
+        continue;
+      }
+      occupied[(block_y + 1) as usize * OCCUPIED_WIDTH + (block_x + 1) as usize] = true;
+
+      for cell in 0..CELLS_PER_BLOCK {
+        let x = block_x * CELL_BLOCK_WIDTH as i64 + (cell % CELL_BLOCK_WIDTH) as i64;
+        let y = block_y * CELL_BLOCK_HEIGHT as i64 + (cell / CELL_BLOCK_WIDTH) as i64;
+        if x < -1 || y < -1 || x > TILE_WIDTH_CELLS || y > TILE_HEIGHT_CELLS {
+          continue;
+        }
+        let state = blocks[..tiles.len()].iter()
+            .enumerate()
+            .fold(0u8, |state, (bit, &block)| state | (u8::from((block >> cell) & 1 == 1) << bit));
+        cells[((y + 1) * UNPACKED_WIDTH + x + 1) as usize] = state;
+      }
+    }
+  }
+
+  let offsets: Vec<i64> = rule.neighbor_offsets().iter().map(|&(dx, dy)| dy * UNPACKED_WIDTH + dx).collect();
+  let mut new_tiles: Vec<Box<Tile>> = board.planes.iter().map(|_| sparse::new_tile()).collect();
+  let mut neighborhood = [0u8; 9];
+  for block_index in 0..TILE_WIDTH_BLOCKS * TILE_HEIGHT_BLOCKS {
+    let block_x = block_index % TILE_WIDTH_BLOCKS;
+    let block_y = block_index / TILE_WIDTH_BLOCKS;
+    let is_near_occupied = (0..3).any(|dy| (0..3).any(|dx| occupied[(block_y + dy) * OCCUPIED_WIDTH + block_x + dx]));
+    if !is_near_occupied {
+      continue;
+    }
+
+    for cell in 0..CELLS_PER_BLOCK {
+      let x = (block_x as u64 * CELL_BLOCK_WIDTH + cell % CELL_BLOCK_WIDTH) as i64;
+      let y = (block_y as u64 * CELL_BLOCK_HEIGHT + cell / CELL_BLOCK_WIDTH) as i64;
+      let index = (y + 1) * UNPACKED_WIDTH + x + 1;
+      neighborhood[0] = cells[index as usize];
+      for (state, offset) in neighborhood[1..].iter_mut().zip(&offsets) {
+        *state = cells[(index + offset) as usize];
+      }
+      let state = rule.new_state(&neighborhood[..offsets.len() + 1]);
+      for (bit, tile) in new_tiles.iter_mut().enumerate() {
+        tile[block_index] |= CellBlock::from((state >> bit) & 1) << cell;
+      }
+    }
+  }
+  new_tiles
+}
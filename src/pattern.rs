@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::conway;
+use crate::conway::{CellBlock, ALL_COLUMNS, ALL_ROWS, CELLS_PER_BLOCK, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK, FIRST_CELL, FIRST_ROW};
+use crate::universe::Universe;
+
+const WIDTH: i64 = CELL_BLOCK_WIDTH as i64;
+const HEIGHT: i64 = CELL_BLOCK_HEIGHT as i64;
+
+// Lines of RLE text are kept to this many characters, as most other programs do.
+const MAX_RLE_LINE_LENGTH: usize = 70;
+
+// Macrocell files and long runs of RLE can describe patterns far too large to list cell by cell, so loading stops past
+// this many cells.
+const MAX_PATTERN_CELLS: usize = 1 << 24;
+
+// Cells read from or written to pattern text, as (x, y, state) for every cell that is not in state 0.
+pub struct PatternCells {
+  pub width: i64,
+  pub height: i64,
+  pub cells: Vec<(i64, i64, u8)>,
+}
+
+// A rectangle of cells stored the same way as the board it came from, as blocks of each of its bit-planes, so that it
+// can be moved and transformed a block at a time. Its top-left cell is the first cell of its first block, and cells
+// past its width and height are always empty.
+#[derive(Clone)]
+pub struct Pattern {
+  pub width: i64,
+  pub height: i64,
+  planes: Vec<Vec<CellBlock>>,
+}
+
+impl Pattern {
+  pub fn new(width: i64, height: i64, plane_count: usize) -> Self {
+    let blocks = (blocks_spanned(width, WIDTH) * blocks_spanned(height, HEIGHT)) as usize;
+    Pattern { width, height, planes: vec![vec![EMPTY_BLOCK; blocks]; plane_count] }
+  }
+
+  // Copies the cells of a rectangle of the universe, whose top-left cell is at (x, y).
+  pub fn from_universe(universe: &Universe, x: i64, y: i64, width: i64, height: i64) -> Self {
+    let mut pattern = Pattern::new(width, height, universe.plane_count());
+    pattern.fill(|plane, cell_x, cell_y| window(|block_x, block_y| universe.block(plane, block_x, block_y), x + cell_x, y + cell_y));
+    pattern
+  }
+
+  pub fn from_cells(cells: &PatternCells, universe: &Universe) -> Self {
+    let mut block_states: HashMap<(i64, i64), [u8; CELLS_PER_BLOCK as usize]> = HashMap::new();
+    for &(x, y, state) in &cells.cells {
+      let states = block_states.entry((x.div_euclid(WIDTH), y.div_euclid(HEIGHT))).or_insert([0; CELLS_PER_BLOCK as usize]);
+      states[(y.rem_euclid(HEIGHT) * WIDTH + x.rem_euclid(WIDTH)) as usize] = state;
+    }
+
+    let mut pattern = Pattern::new(cells.width, cells.height, universe.plane_count());
+    for ((block_x, block_y), states) in block_states {
+      let index = pattern.block_index(block_x, block_y).unwrap();
+      for (plane, block) in universe.planes_from_states(&states).into_iter().enumerate() {
+        pattern.planes[plane][index] = block;
+      }
+    }
+    pattern
+  }
+
+  pub fn to_cells(&self, universe: &Universe) -> PatternCells {
+    let mut cells = Vec::new();
+    for block_y in 0..blocks_spanned(self.height, HEIGHT) {
+      for block_x in 0..blocks_spanned(self.width, WIDTH) {
+        let blocks: Vec<CellBlock> = (0..self.planes.len()).map(|plane| self.block(plane, block_x, block_y)).collect();
+        if blocks.iter().all(|&block| block == 0) {
+          continue;
+        }
+        for (cell, &state) in universe.states_from_planes(&blocks).iter().enumerate() {
+          if state != 0 {
+            cells.push((block_x * WIDTH + cell as i64 % WIDTH, block_y * HEIGHT + cell as i64 / WIDTH, state));
+          }
+        }
+      }
+    }
+    PatternCells { width: self.width, height: self.height, cells }
+  }
+
+  pub fn plane_count(&self) -> usize {
+    self.planes.len()
+  }
+
+  // Returns the block at the given block coordinates, where blocks outside of the pattern are empty.
+  pub fn block(&self, plane: usize, block_x: i64, block_y: i64) -> CellBlock {
+    self.block_index(block_x, block_y).map_or(EMPTY_BLOCK, |index| self.planes[plane][index])
+  }
+
+  // Whether the cell at the given coordinates, relative to the pattern's top-left cell, is in any state but 0.
+  pub fn is_occupied(&self, x: i64, y: i64) -> bool {
+    let bit = y.rem_euclid(HEIGHT) * WIDTH + x.rem_euclid(WIDTH);
+    (0..self.planes.len()).any(|plane| (self.block(plane, x.div_euclid(WIDTH), y.div_euclid(HEIGHT)) >> bit) & 1 == 1)
+  }
+
+  pub fn flipped_horizontally(&self) -> Pattern {
+    // Flipping whole blocks moves the padding past the right edge over to the left, so the blocks are flipped into a
+    // pattern padded out to whole blocks, and the cells are moved back from there.
+    let width_blocks = blocks_spanned(self.width, WIDTH);
+    let mut flipped = Pattern::new(width_blocks * WIDTH, self.height, self.planes.len());
+    flipped.fill(|plane, x, y| reverse_rows(self.block(plane, width_blocks - 1 - x / WIDTH, y / HEIGHT).reverse_bits()));
+    flipped.shifted(width_blocks * WIDTH - self.width, 0, self.width, self.height)
+  }
+
+  pub fn flipped_vertically(&self) -> Pattern {
+    let height_blocks = blocks_spanned(self.height, HEIGHT);
+    let mut flipped = Pattern::new(self.width, height_blocks * HEIGHT, self.planes.len());
+    flipped.fill(|plane, x, y| reverse_rows(self.block(plane, x / WIDTH, height_blocks - 1 - y / HEIGHT)));
+    flipped.shifted(0, height_blocks * HEIGHT - self.height, self.width, self.height)
+  }
+
+  // Turns the pattern a quarter turn clockwise, by transposing it and then flipping it horizontally.
+  pub fn rotated_clockwise(&self) -> Pattern {
+    let mut transposed = Pattern::new(self.height, self.width, self.planes.len());
+    transposed.fill(|plane, x, y| transposed_window(|block_x, block_y| self.block(plane, block_x, block_y), x, y));
+    transposed.flipped_horizontally()
+  }
+
+  // Returns a pattern of the given size holding the cells of this one moved up and to the left by the given number of
+  // cells.
+  fn shifted(&self, dx: i64, dy: i64, width: i64, height: i64) -> Pattern {
+    let mut shifted = Pattern::new(width, height, self.planes.len());
+    shifted.fill(|plane, x, y| window(|block_x, block_y| self.block(plane, block_x, block_y), x + dx, y + dy));
+    shifted
+  }
+
+  // Sets every block from the cells returned for the top-left cell of the block, dropping any past the pattern's edges.
+  fn fill<F: Fn(usize, i64, i64) -> CellBlock>(&mut self, cells_at: F) {
+    let width_blocks = blocks_spanned(self.width, WIDTH);
+    for (plane, blocks) in self.planes.iter_mut().enumerate() {
+      for (index, block) in blocks.iter_mut().enumerate() {
+        let (block_x, block_y) = (index as i64 % width_blocks, index as i64 / width_blocks);
+        *block = cells_at(plane, block_x * WIDTH, block_y * HEIGHT) & rectangle_mask(block_x * WIDTH, block_y * HEIGHT, self.width, self.height);
+      }
+    }
+  }
+
+  fn block_index(&self, block_x: i64, block_y: i64) -> Option<usize> {
+    let (width_blocks, height_blocks) = (blocks_spanned(self.width, WIDTH), blocks_spanned(self.height, HEIGHT));
+    let inside = (0..width_blocks).contains(&block_x) && (0..height_blocks).contains(&block_y);
+    inside.then(|| (block_y * width_blocks + block_x) as usize)
+  }
+}
+
+// How many blocks of the given size it takes to cover the given number of cells, which is never negative.
+fn blocks_spanned(cells: i64, block_size: i64) -> i64 {
+  (cells as u64).div_ceil(block_size as u64) as i64
+}
+
+// Returns the block of cells whose top-left cell is at (x, y), which may straddle up to four of the blocks returned
+// by the given function for block coordinates.
+pub fn window<F: Fn(i64, i64) -> CellBlock>(block_at: F, x: i64, y: i64) -> CellBlock {
+  let (block_x, column) = (x.div_euclid(WIDTH), x.rem_euclid(WIDTH) as u64);
+  let (block_y, row) = (y.div_euclid(HEIGHT), y.rem_euclid(HEIGHT) as u64);
+  let row_of_blocks = |block_y: i64| {
+    let left = block_at(block_x, block_y);
+    if column == 0 {
+      return left;
+    }
+    let right = block_at(block_x + 1, block_y);
+    let left_columns = columns_before(CELL_BLOCK_WIDTH - column);
+    ((left >> column) & left_columns) | ((right << (CELL_BLOCK_WIDTH - column)) & !left_columns)
+  };
+
+  let top = row_of_blocks(block_y);
+  if row == 0 {
+    return top;
+  }
+  (top >> (row * CELL_BLOCK_WIDTH)) | (row_of_blocks(block_y + 1) << ((CELL_BLOCK_HEIGHT - row) * CELL_BLOCK_WIDTH))
+}
+
+// The cells of the block whose top-left cell is at (x, y) that fall inside the rectangle from the origin to the given
+// width and height.
+pub fn rectangle_mask(x: i64, y: i64, width: i64, height: i64) -> CellBlock {
+  let (first_column, last_column) = ((-x).clamp(0, WIDTH) as u64, (width - x).clamp(0, WIDTH) as u64);
+  let (first_row, last_row) = ((-y).clamp(0, HEIGHT) as u64, (height - y).clamp(0, HEIGHT) as u64);
+  let columns = columns_before(last_column) & !columns_before(first_column);
+  columns & rows_before(last_row) & !rows_before(first_row)
+}
+
+fn columns_before(column: u64) -> CellBlock {
+  conway::cells_in(((1 << column) - 1) & ALL_COLUMNS, ALL_ROWS)
+}
+
+fn rows_before(row: u64) -> CellBlock {
+  conway::cells_in(ALL_COLUMNS, ((1 << row) - 1) & ALL_ROWS)
+}
+
+fn reverse_rows(block: CellBlock) -> CellBlock {
+  (0..CELL_BLOCK_HEIGHT).fold(EMPTY_BLOCK, |reversed, row| {
+    reversed | (((block >> (row * CELL_BLOCK_WIDTH)) & FIRST_ROW) << ((CELL_BLOCK_HEIGHT - 1 - row) * CELL_BLOCK_WIDTH))
+  })
+}
+
+// Returns the block whose top-left cell is at (x, y) once the rows and columns of the blocks returned by the given
+// function are swapped. Blocks can be wider than they are tall, so the cells are gathered from as many windows of the
+// untransposed cells as it takes to cover a block's width with their rows.
+fn transposed_window<F: Fn(i64, i64) -> CellBlock>(block_at: F, x: i64, y: i64) -> CellBlock {
+  let windows: Vec<CellBlock> = (0..WIDTH / HEIGHT).map(|index| window(&block_at, y, x + index * HEIGHT)).collect();
+  (0..CELLS_PER_BLOCK).fold(EMPTY_BLOCK, |transposed, cell| {
+    let (column, row) = (cell % CELL_BLOCK_WIDTH, cell / CELL_BLOCK_WIDTH);
+    let window = windows[(column / CELL_BLOCK_HEIGHT) as usize];
+    let alive = (window >> ((column % CELL_BLOCK_HEIGHT) * CELL_BLOCK_WIDTH + row)) & 1 == 1;
+    if alive { transposed | (FIRST_CELL << cell) } else { transposed }
+  })
+}
+
+pub fn is_pattern_file(path: &Path) -> bool {
+  let extension = path.extension().map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+  matches!(extension.as_deref(), Some("rle" | "cells" | "mc"))
+}
+
+pub fn load_pattern_file(path: &Path) -> Result<PatternCells, String> {
+  let text = std::fs::read_to_string(path).map_err(|error| format!("Could not read pattern file \"{}\": {}", path.display(), error))?;
+  let extension = path.extension().map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+  let cells = match extension.as_deref() {
+    Some("rle") => parse_rle(&text),
+    Some("cells") => parse_plaintext(&text),
+    Some("mc") => parse_macrocell(&text),
+    _ => Err("Pattern files should end in .rle, .cells or .mc".to_string()),
+  };
+  cells.map_err(|error| format!("{} in \"{}\"", error, path.display()))
+}
+
+// Parses the plaintext format, which draws every row with "." for dead cells and "O" for live ones, after any comment
+// lines starting with "!".
+pub fn parse_plaintext(text: &str) -> Result<PatternCells, String> {
+  let mut cells = Vec::new();
+  let (mut width, mut height) = (0, 0);
+  for line in text.lines().filter(|line| !line.starts_with('!')) {
+    for (x, character) in line.trim_end().chars().enumerate() {
+      match character {
+        '.' => {}
+        'O' | '*' => cells.push((x as i64, height, 1)),
+        _ => return Err(format!("Invalid character '{}' in plaintext pattern", character)),
+      }
+      width = width.max(x as i64 + 1);
+    }
+    height += 1;
+  }
+  Ok(PatternCells { width, height, cells })
+}
+
+// Parses Golly's macrocell format, which stores the pattern as a quadtree with identical subtrees shared. Every line is
+// a node, numbered from 1, and the last one is the root. Two-state patterns end in 8x8 leaves drawn the same way as
+// plaintext rows, separated by "$". Other lines list a node's level, where a node at level k is 2^k cells across, then
+// its top-left, top-right, bottom-left and bottom-right children, with 0 for empty ones. Multi-state patterns end in
+// level 1 nodes whose children are the states of their four cells.
+pub fn parse_macrocell(text: &str) -> Result<PatternCells, String> {
+  enum Node {
+    Leaf(Vec<(i64, i64)>),
+    Branch(u32, [usize; 4]),
+  }
+
+  let mut nodes = vec![Node::Leaf(Vec::new())];
+  for line in text.lines().map(|line| line.trim()) {
+    if line.is_empty() || line.starts_with('[') || line.starts_with('#') {
+      continue;
+    }
+    if line.starts_with(['.', '*', '$']) {
+      let mut cells = Vec::new();
+      for (y, row) in line.split('$').enumerate() {
+        cells.extend(row.chars().enumerate().filter(|&(_, character)| character == '*').map(|(x, _)| (x as i64, y as i64)));
+      }
+      nodes.push(Node::Leaf(cells));
+      continue;
+    }
+
+    let numbers: Vec<usize> = line.split_whitespace()
+        .map(|number| number.parse().map_err(|_| format!("Invalid macrocell node \"{}\"", line)))
+        .collect::<Result<_, _>>()?;
+    let (level, children) = match numbers[..] {
+      [level, top_left, top_right, bottom_left, bottom_right] if (1..63).contains(&level) => (level as u32, [top_left, top_right, bottom_left, bottom_right]),
+      _ => return Err(format!("Invalid macrocell node \"{}\"", line)),
+    };
+    if level > 1 && children.iter().any(|&child| child >= nodes.len()) {
+      return Err(format!("Macrocell node \"{}\" refers to a later node", line));
+    }
+    nodes.push(Node::Branch(level, children));
+  }
+
+  fn collect(nodes: &[Node], index: usize, x: i64, y: i64, cells: &mut Vec<(i64, i64, u8)>) -> Result<(), String> {
+    match &nodes[index] {
+      Node::Leaf(leaf_cells) => cells.extend(leaf_cells.iter().map(|&(cell_x, cell_y)| (x + cell_x, y + cell_y, 1))),
+      Node::Branch(1, states) => {
+        for (quadrant, &state) in states.iter().enumerate() {
+          let state = u8::try_from(state).map_err(|_| format!("State {} in macrocell pattern is out of range", state))?;
+          if state != 0 {
+            cells.push((x + (quadrant % 2) as i64, y + (quadrant / 2) as i64, state));
+          }
+        }
+      }
+      Node::Branch(level, children) => {
+        let half = 1i64 << (level - 1);
+        for (quadrant, &child) in children.iter().enumerate() {
+          if child != 0 {
+            collect(nodes, child, x + (quadrant % 2) as i64 * half, y + (quadrant / 2) as i64 * half, cells)?;
+          }
+        }
+      }
+    }
+    if cells.len() > MAX_PATTERN_CELLS {
+      return Err(format!("Macrocell pattern has more than {} cells", MAX_PATTERN_CELLS));
+    }
+    Ok(())
+  }
+
+  let mut cells = Vec::new();
+  collect(&nodes, nodes.len() - 1, 0, 0, &mut cells)?;
+  let min_x = cells.iter().map(|&(x, _, _)| x).min().unwrap_or(0);
+  let min_y = cells.iter().map(|&(_, y, _)| y).min().unwrap_or(0);
+  let width = cells.iter().map(|&(x, _, _)| x - min_x + 1).max().unwrap_or(0);
+  let height = cells.iter().map(|&(_, y, _)| y - min_y + 1).max().unwrap_or(0);
+  for (x, y, _) in &mut cells {
+    (*x, *y) = (*x - min_x, *y - min_y);
+  }
+  Ok(PatternCells { width, height, cells })
+}
+
+// Parses Golly's extended RLE format, where runs of dead and live cells are written as "b" and "o", or as "." and "A"
+// to "X" for the first 24 states, with "p" to "y" in front for the states after those.
+pub fn parse_rle(text: &str) -> Result<PatternCells, String> {
+  let (mut width, mut height) = (0, 0);
+  let mut cells = Vec::new();
+  let (mut x, mut y) = (0i64, 0i64);
+  let mut count: Option<i64> = None;
+  let mut prefix: Option<u8> = None;
+  let mut has_header = false;
+  'lines: for line in text.lines().map(|line| line.trim()) {
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    if !has_header && line.starts_with('x') {
+      for setting in line.split(',') {
+        let (name, value) = setting.split_once('=').unwrap_or((setting, ""));
+        match name.trim() {
+          "x" => width = value.trim().parse().map_err(|_| format!("Invalid width in RLE header \"{}\"", line))?,
+          "y" => height = value.trim().parse().map_err(|_| format!("Invalid height in RLE header \"{}\"", line))?,
+          _ => {}
+        }
+      }
+      has_header = true;
+      continue;
+    }
+
+    for character in line.chars() {
+      if let Some(digit) = character.to_digit(10) {
+        let run = count.unwrap_or(0).checked_mul(10).and_then(|run| run.checked_add(digit as i64));
+        count = Some(run.ok_or("Run in RLE pattern is too long")?);
+        continue;
+      }
+      let run = count.take().unwrap_or(1);
+      let state = match character {
+        'b' | '.' => 0,
+        'o' => 1,
+        'p'..='y' => {
+          prefix = Some(character as u8 - b'p' + 1);
+          count = Some(run);
+          continue;
+        }
+        'A'..='X' => {
+          let state = prefix.take().unwrap_or(0) as u32 * 24 + (character as u8 - b'A') as u32 + 1;
+          u8::try_from(state).map_err(|_| format!("State {} in RLE pattern is out of range", state))?
+        }
+        '$' => {
+          (x, y) = (0, y.checked_add(run).ok_or("RLE pattern is too tall")?);
+          continue;
+        }
+        '!' => break 'lines,
+        character if character.is_whitespace() => continue,
+        _ => return Err(format!("Invalid character '{}' in RLE pattern", character)),
+      };
+      let end = x.checked_add(run).ok_or("RLE pattern is too wide")?;
+      if state != 0 {
+        if run as usize > MAX_PATTERN_CELLS - cells.len() {
+          return Err(format!("RLE pattern has more than {} cells", MAX_PATTERN_CELLS));
+        }
+        cells.extend((x..end).map(|x| (x, y, state)));
+      }
+      x = end;
+    }
+  }
+
+  for &(x, y, _) in &cells {
+    width = width.max(x + 1);
+    height = height.max(y + 1);
+  }
+  Ok(PatternCells { width, height, cells })
+}
+
+// Writes cells as RLE, using "b" and "o" for two-state rules and the multi-state letters otherwise.
+pub fn format_rle(cells: &PatternCells, states: u8, rule: &str) -> String {
+  let symbol = |state: u8| match (states, state) {
+    (2, 0) => "b".to_string(),
+    (2, _) => "o".to_string(),
+    (_, 0) => ".".to_string(),
+    (_, state) => {
+      let prefix = (state - 1) / 24;
+      let letter = ((state - 1) % 24 + b'A') as char;
+      if prefix == 0 { letter.to_string() } else { format!("{}{}", (b'p' + prefix - 1) as char, letter) }
+    }
+  };
+  let mut runs = Vec::<(String, i64)>::new();
+  let mut push_run = |symbol: String, length: i64| match runs.last_mut() {
+    Some((last_symbol, last_length)) if *last_symbol == symbol => *last_length += length,
+    _ => runs.push((symbol, length)),
+  };
+
+  let mut sorted_cells = cells.cells.clone();
+  sorted_cells.sort_unstable_by_key(|&(x, y, _)| (y, x));
+  let (mut x, mut y) = (0, 0);
+  for (cell_x, cell_y, state) in sorted_cells {
+    if cell_y > y {
+      push_run("$".to_string(), cell_y - y);
+      (x, y) = (0, cell_y);
+    }
+    if cell_x > x {
+      push_run(symbol(0), cell_x - x);
+    }
+    push_run(symbol(state), 1);
+    x = cell_x + 1;
+  }
+  push_run("!".to_string(), 1);
+
+  let mut text = format!("x = {}, y = {}, rule = {}\n", cells.width, cells.height, rule);
+  let mut line_length = 0;
+  for (symbol, length) in runs {
+    let token = if length == 1 { symbol } else { format!("{}{}", length, symbol) };
+    if line_length + token.len() > MAX_RLE_LINE_LENGTH {
+      text.push('\n');
+      line_length = 0;
+    }
+    line_length += token.len();
+    text.push_str(&token);
+  }
+  text.push('\n');
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::universe::Buffers;
+
+  const GLIDER: &str = "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+
+  fn sorted(cells: &PatternCells) -> (i64, i64, Vec<(i64, i64, u8)>) {
+    let mut sorted = cells.cells.clone();
+    sorted.sort_unstable_by_key(|&(x, y, _)| (y, x));
+    (cells.width, cells.height, sorted)
+  }
+
+  #[test]
+  fn parses_rle() {
+    let glider = parse_rle(GLIDER).unwrap();
+    assert_eq!(sorted(&glider), (3, 3, vec![(1, 0, 1), (2, 1, 1), (0, 2, 1), (1, 2, 1), (2, 2, 1)]));
+    // Runs of rows, multi-state letters and lines split anywhere, with the size taken from the cells when it is larger.
+    let cells = parse_rle("#C comment\nx = 1, y = 1\n2.A2$\nxB3p\nA!ignored").unwrap();
+    assert_eq!(sorted(&cells), (4, 3, vec![(2, 0, 1), (0, 2, 218), (1, 2, 25), (2, 2, 25), (3, 2, 25)]));
+    assert!(parse_rle("3o?").is_err());
+    assert!(parse_rle("yX!").is_err());
+  }
+
+  #[test]
+  fn rotates_patterns_spanning_several_blocks() {
+    let universe = Universe::new(Buffers::for_rule(None), None);
+    let (width, height) = (3 * WIDTH + 5, 2 * HEIGHT + 3);
+    let cells: Vec<(i64, i64, u8)> = (0..width * height).filter(|cell| cell % 7 == 0 || cell % 11 == 0).map(|cell| (cell % width, cell / width, 1)).collect();
+    let pattern = Pattern::from_cells(&PatternCells { width, height, cells: cells.clone() }, &universe);
+
+    let rotated = pattern.rotated_clockwise();
+    let expected = cells.iter().map(|&(x, y, state)| (height - 1 - y, x, state)).collect();
+    assert_eq!(sorted(&rotated.to_cells(&universe)), sorted(&PatternCells { width: height, height: width, cells: expected }));
+
+    let turned_around = (0..3).fold(rotated, |pattern, _| pattern.rotated_clockwise());
+    assert_eq!(sorted(&turned_around.to_cells(&universe)), sorted(&pattern.to_cells(&universe)));
+  }
+
+  #[test]
+  fn rle_round_trips() {
+    let glider = parse_rle(GLIDER).unwrap();
+    assert_eq!(format_rle(&glider, 2, "B3/S23"), GLIDER);
+
+    let cells = (0..200).map(|index| (index * 7 % 150, index / 4, (index % 255) as u8 + 1)).collect();
+    let cells = PatternCells { width: 150, height: 50, cells };
+    let text = format_rle(&cells, 255, "Many");
+    assert!(text.lines().all(|line| line.len() <= MAX_RLE_LINE_LENGTH), "{}", text);
+    assert_eq!(sorted(&parse_rle(&text).unwrap()), sorted(&cells));
+  }
+
+  #[test]
+  fn parses_macrocell() {
+    let gliders = parse_macrocell("[M2] (golly 2.0)\n#R B3/S23\n.*$..*$***$\n4 1 0 0 1\n").unwrap();
+    let glider = [(1, 0, 1), (2, 1, 1), (0, 2, 1), (1, 2, 1), (2, 2, 1)];
+    let shifted = glider.map(|(x, y, state)| (x + 8, y + 8, state));
+    assert_eq!(sorted(&gliders), (11, 11, [glider, shifted].concat()));
+
+    // Multi-state cells, and the pattern moved to start at the origin.
+    let cells = parse_macrocell("[M2]\n1 0 2 3 0\n2 0 0 0 1\n3 0 0 2 2\n").unwrap();
+    assert_eq!(sorted(&cells), (6, 2, vec![(1, 0, 2), (5, 0, 2), (0, 1, 3), (4, 1, 3)]));
+
+    assert!(parse_macrocell("[M2]\n3 2 0 0 0\n").is_err());
+    assert!(parse_macrocell("[M2]\n1 0 256 0 0\n").is_err());
+  }
+}
@@ -0,0 +1,305 @@
+// The eight bytes every PNG file starts with.
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// Deflate looks this far back for repeated bytes, and repeats at most this many at once.
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+
+// The shortest length and distance of every length and distance code, and how many extra bits follow each code.
+const LENGTH_BASES: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u16; 30] = [
+  1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193,
+  12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+// Writes 32-bit ARGB pixels, row by row, to a PNG file of 8-bit RGB.
+pub fn write_png(path: &str, width: u32, height: u32, pixels: &[u32]) -> Result<(), String> {
+  std::fs::write(path, encode(width, height, pixels)).map_err(|error| format!("Could not write image \"{}\": {}", path, error))
+}
+
+pub fn encode(width: u32, height: u32, pixels: &[u32]) -> Vec<u8> {
+  let mut png = SIGNATURE.to_vec();
+  write_chunk(&mut png, b"IHDR", &header(width, height));
+  write_chunk(&mut png, b"IDAT", &zlib(&scanlines(width, pixels)));
+  write_chunk(&mut png, b"IEND", &[]);
+  png
+}
+
+// The IHDR chunk, for 8 bits per channel of RGB, without interlacing.
+pub fn header(width: u32, height: u32) -> Vec<u8> {
+  let mut header = Vec::new();
+  header.extend(width.to_be_bytes());
+  header.extend(height.to_be_bytes());
+  header.extend([8, 2, 0, 0, 0]);
+  header
+}
+
+// Each row of image data starts with the filter applied to it, which here is always none.
+pub fn scanlines(width: u32, pixels: &[u32]) -> Vec<u8> {
+  let mut data = Vec::with_capacity(pixels.len() * 3 + pixels.len() / (width as usize).max(1));
+  for row in pixels.chunks((width as usize).max(1)) {
+    data.push(0);
+    for pixel in row {
+      data.extend(&pixel.to_be_bytes()[1..]);
+    }
+  }
+  data
+}
+
+pub fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+  png.extend((data.len() as u32).to_be_bytes());
+  let start = png.len();
+  png.extend(kind);
+  png.extend(data);
+  let crc = crc32(&png[start..]);
+  png.extend(crc.to_be_bytes());
+}
+
+// Compresses data into a zlib stream of a single deflate block. Rendered boards are mostly long runs of the same few
+// colors, which the fixed Huffman codes and a simple search for repeats already shrink to a small fraction.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+  let mut writer = BitWriter { bytes: vec![0x78, 0x01], buffer: 0, bit_count: 0 };
+  // The last block, using fixed Huffman codes.
+  writer.write_bits(1, 1);
+  writer.write_bits(1, 2);
+
+  let mut last_positions = vec![usize::MAX; 1 << HASH_BITS];
+  let mut position = 0;
+  while position < data.len() {
+    let mut match_length = 0;
+    if position + MIN_MATCH <= data.len() {
+      let hash = hash(&data[position..position + MIN_MATCH]);
+      let candidate = last_positions[hash];
+      last_positions[hash] = position;
+      if candidate != usize::MAX && position - candidate <= WINDOW_SIZE {
+        let max_length = MAX_MATCH.min(data.len() - position);
+        match_length = (0..max_length).take_while(|&offset| data[candidate + offset] == data[position + offset]).count();
+        if match_length >= MIN_MATCH {
+          writer.write_match(match_length, position - candidate);
+        }
+      }
+    }
+
+    if match_length >= MIN_MATCH {
+      // Only the start of the match is hashed, which keeps the search fast at some cost to how much is found.
+      position += match_length;
+    } else {
+      writer.write_literal(data[position] as u16);
+      position += 1;
+    }
+  }
+  writer.write_literal(256);
+  writer.flush();
+
+  let mut zlib = writer.bytes;
+  zlib.extend(adler32(data).to_be_bytes());
+  zlib
+}
+
+fn hash(bytes: &[u8]) -> usize {
+  let value = (bytes[0] as u32) | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16;
+  (value.wrapping_mul(0x9E3779B1) >> (32 - HASH_BITS)) as usize
+}
+
+// Packs bits into bytes starting from the lowest bit, as deflate expects.
+struct BitWriter {
+  bytes: Vec<u8>,
+  buffer: u64,
+  bit_count: u32,
+}
+
+impl BitWriter {
+  fn write_bits(&mut self, value: u32, count: u32) {
+    self.buffer |= (value as u64) << self.bit_count;
+    self.bit_count += count;
+    while self.bit_count >= 8 {
+      self.bytes.push(self.buffer as u8);
+      self.buffer >>= 8;
+      self.bit_count -= 8;
+    }
+  }
+
+  // Huffman codes are packed starting from their highest bit.
+  fn write_code(&mut self, code: u32, length: u32) {
+    self.write_bits(code.reverse_bits() >> (32 - length), length);
+  }
+
+  // Writes a literal byte, a length code or the end of the block, in the fixed code for literals and lengths.
+  fn write_literal(&mut self, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+      0..=143 => self.write_code(0x30 + symbol, 8),
+      144..=255 => self.write_code(0x190 + symbol - 144, 9),
+      256..=279 => self.write_code(symbol - 256, 7),
+      _ => self.write_code(0xC0 + symbol - 280, 8),
+    }
+  }
+
+  fn write_match(&mut self, length: usize, distance: usize) {
+    let length_code = LENGTH_BASES.iter().rposition(|&base| base as usize <= length).unwrap();
+    self.write_literal(257 + length_code as u16);
+    self.write_bits((length - LENGTH_BASES[length_code] as usize) as u32, LENGTH_EXTRA_BITS[length_code] as u32);
+
+    let distance_code = DISTANCE_BASES.iter().rposition(|&base| base as usize <= distance).unwrap();
+    self.write_code(distance_code as u32, 5);
+    self.write_bits((distance - DISTANCE_BASES[distance_code] as usize) as u32, DISTANCE_EXTRA_BITS[distance_code] as u32);
+  }
+
+  fn flush(&mut self) {
+    if self.bit_count > 0 {
+      self.bytes.push(self.buffer as u8);
+    }
+    self.buffer = 0;
+    self.bit_count = 0;
+  }
+}
+
+const fn crc_table() -> [u32; 256] {
+  let mut table = [0u32; 256];
+  let mut index = 0;
+  while index < 256 {
+    let mut value = index as u32;
+    let mut bit = 0;
+    while bit < 8 {
+      value = if value & 1 == 1 { 0xEDB88320 ^ (value >> 1) } else { value >> 1 };
+      bit += 1;
+    }
+    table[index] = value;
+    index += 1;
+  }
+  table
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+  !bytes.iter().fold(!0u32, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+  let (mut a, mut b) = (1u32, 0u32);
+  for chunk in bytes.chunks(5552) {
+    for &byte in chunk {
+      a += byte as u32;
+      b += a;
+    }
+    (a, b) = (a % 65521, b % 65521);
+  }
+  (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Reads the bits deflate packs, starting from the lowest bit of each byte.
+  struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+  }
+
+  impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> u32 {
+      (0..count).fold(0, |value, bit| {
+        let byte = self.bytes[self.position / 8];
+        let position = self.position % 8;
+        self.position += 1;
+        value | (((byte >> position) & 1) as u32) << bit
+      })
+    }
+
+    // Huffman codes start from their highest bit.
+    fn code(&mut self, length: u32) -> u32 {
+      (0..length).fold(0, |code, _| (code << 1) | self.bits(1))
+    }
+
+    // Decodes a symbol of the fixed code for literals and lengths by reading the shortest code first.
+    fn literal(&mut self) -> u32 {
+      let code = self.code(7);
+      if code <= 0x17 {
+        return code + 256;
+      }
+      let code = (code << 1) | self.bits(1);
+      match code {
+        0x30..=0xBF => code - 0x30,
+        0xC0..=0xC7 => code - 0xC0 + 280,
+        _ => ((code << 1) | self.bits(1)) - 0x190 + 144,
+      }
+    }
+  }
+
+  // Inflates a zlib stream made of a single block with the fixed Huffman codes, as written by zlib.
+  fn inflate(zlib: &[u8]) -> Vec<u8> {
+    assert_eq!(zlib[..2], [0x78, 0x01]);
+    let mut reader = BitReader { bytes: &zlib[2..zlib.len() - 4], position: 0 };
+    assert_eq!((reader.bits(1), reader.bits(2)), (1, 1));
+    let mut data = Vec::new();
+    loop {
+      match reader.literal() {
+        literal @ 0..=255 => data.push(literal as u8),
+        256 => break,
+        symbol => {
+          let code = symbol as usize - 257;
+          let length = LENGTH_BASES[code] as usize + reader.bits(LENGTH_EXTRA_BITS[code] as u32) as usize;
+          let code = reader.code(5) as usize;
+          let distance = DISTANCE_BASES[code] as usize + reader.bits(DISTANCE_EXTRA_BITS[code] as u32) as usize;
+          for _ in 0..length {
+            data.push(data[data.len() - distance]);
+          }
+        }
+      }
+    }
+    assert_eq!(zlib[zlib.len() - 4..], adler32(&data).to_be_bytes());
+    data
+  }
+
+  #[test]
+  fn checksums_match_known_values() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32(b"IEND"), 0xAE426082);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    assert_eq!(adler32(&[0xFF; 100000]), {
+      let (a, b) = (1 + 100000 * 255u64, (1..=100000u64).map(|count| 1 + count * 255).sum::<u64>());
+      (((b % 65521) << 16) | (a % 65521)) as u32
+    });
+  }
+
+  #[test]
+  fn zlib_round_trips() {
+    let runs: Vec<u8> = (0..20000u32).map(|index| if (index / 700) % 3 == 0 { 0x10 } else { 0xF0 }).collect();
+    let noise: Vec<u8> = (0..5000u32).map(|index| (index.wrapping_mul(2654435761) >> 13) as u8).collect();
+    for data in [Vec::new(), b"a".to_vec(), b"abcabcabcabcabcabcabcabc".to_vec(), runs.clone(), noise] {
+      assert_eq!(inflate(&zlib(&data)), data);
+    }
+    // Long runs come out as a few matches of the longest length.
+    assert!(zlib(&runs).len() < runs.len() / 20);
+  }
+
+  #[test]
+  fn encodes_chunks_of_rgb_scanlines() {
+    let pixels = [0xFF102030, 0xFFFFFFFF, 0xFF000000, 0xFF0A0B0C, 0xFF010203, 0xFF040506];
+    let png = encode(3, 2, &pixels);
+    assert_eq!(png[..8], SIGNATURE);
+
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+    while offset < png.len() {
+      let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+      let kind = &png[offset + 4..offset + 8];
+      let crc = u32::from_be_bytes(png[offset + 8 + length..offset + 12 + length].try_into().unwrap());
+      assert_eq!(crc, crc32(&png[offset + 4..offset + 8 + length]));
+      chunks.push((kind.to_vec(), png[offset + 8..offset + 8 + length].to_vec()));
+      offset += 12 + length;
+    }
+    assert_eq!(chunks.iter().map(|(kind, _)| kind.as_slice()).collect::<Vec<_>>(), [b"IHDR", b"IDAT", b"IEND"]);
+    assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+    assert_eq!(inflate(&chunks[1].1), [
+      0, 0x10, 0x20, 0x30, 0xFF, 0xFF, 0xFF, 0, 0, 0,
+      0, 0x0A, 0x0B, 0x0C, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+    ]);
+  }
+}
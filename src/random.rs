@@ -0,0 +1,51 @@
+use crate::conway::CellBlock;
+
+// A xorshift generator, which is plenty random enough for soups and filling selections, and never gets stuck as long
+// as it does not start at 0.
+pub struct Random(u64);
+
+impl Random {
+  // The same seed always gives the same numbers, so that runs can be compared with each other.
+  pub fn seeded(seed: u64) -> Self {
+    Random(seed.max(1))
+  }
+
+  pub fn from_clock() -> Self {
+    Random::seeded(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos() as u64)
+  }
+
+  pub fn next(&mut self) -> u64 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+    self.0
+  }
+
+  // A block of cells each alive with even odds, drawing as many numbers as it takes to fill a block of any size.
+  pub fn block(&mut self) -> CellBlock {
+    let mut bytes = [0; size_of::<CellBlock>()];
+    for chunk in bytes.chunks_mut(size_of::<u64>()) {
+      chunk.copy_from_slice(&self.next().to_le_bytes());
+    }
+    CellBlock::from_le_bytes(bytes)
+  }
+
+  // A number from 0 up to but not including 1.
+  pub fn fraction(&mut self) -> f64 {
+    (self.next() >> 11) as f64 / (1u64 << 53) as f64
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::conway::EMPTY_BLOCK;
+
+  #[test]
+  fn blocks_fill_every_cell() {
+    let mut random = Random::seeded(1);
+    let blocks: Vec<CellBlock> = (0..64).map(|_| random.block()).collect();
+    assert_eq!(blocks.iter().fold(EMPTY_BLOCK, |cells, &block| cells | block), CellBlock::MAX);
+    assert_eq!(blocks.iter().fold(CellBlock::MAX, |cells, &block| cells & block), EMPTY_BLOCK);
+  }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use crate::png;
+use crate::render;
+use crate::render::{Colors, Viewport};
+use crate::selection;
+use crate::universe::Universe;
+
+// Space left around the pattern when the view follows it.
+const FOLLOW_MARGIN_CELLS: i64 = 4;
+
+// GIF images are at most this many pixels across and down, and their LZW codes at most this many bits long.
+const MAX_GIF_SIZE: u32 = u16::MAX as u32;
+const MAX_GIF_CODE_BITS: u32 = 12;
+
+// Where the acTL chunk starts in an APNG file, right after the signature and the IHDR chunk.
+const ANIMATION_CONTROL_OFFSET: u64 = 8 + 12 + 13;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+  Gif,
+  Apng,
+  // Uncompressed frames for ffmpeg and other video tools to read from a file or pipe.
+  Y4m,
+}
+
+impl Format {
+  // Picks the format from the extension of the path, where .png files are animated.
+  pub fn from_path(path: &str) -> Result<Format, String> {
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+      Some("gif") => Ok(Format::Gif),
+      Some("png" | "apng") => Ok(Format::Apng),
+      Some("y4m") => Ok(Format::Y4m),
+      _ => Err(format!("Recordings should end in .gif, .png, .apng or .y4m, not \"{}\"", path)),
+    }
+  }
+}
+
+// Writes frames of the universe as it runs to an animation, one frame for each call to record. Every frame is drawn
+// through the same viewport, unless the recorder follows the pattern, in which case the view moves and zooms to fit
+// everything there is in each frame.
+pub struct Recorder {
+  path: String,
+  writer: BufWriter<File>,
+  format: Format,
+  viewport: Viewport,
+  follow: bool,
+  frames_per_second: u32,
+  frame_count: u32,
+  // The GIF palette index of every color in the palette.
+  palette: HashMap<u32, u8>,
+  // APNG numbers its frame control and frame data chunks in a single sequence.
+  sequence_number: u32,
+}
+
+impl Recorder {
+  pub fn start(path: &str, viewport: Viewport, follow: bool, frames_per_second: u32, colors: &Colors) -> Result<Self, String> {
+    let format = Format::from_path(path)?;
+    if format == Format::Gif && (viewport.width > MAX_GIF_SIZE || viewport.height > MAX_GIF_SIZE) {
+      return Err(format!("GIF frames can be at most {} pixels across, try a larger --scale", MAX_GIF_SIZE));
+    }
+    let file = File::create(path).map_err(|error| format!("Could not create recording \"{}\": {}", path, error))?;
+    let mut recorder = Recorder {
+      path: path.to_string(),
+      writer: BufWriter::new(file),
+      format,
+      viewport,
+      follow,
+      frames_per_second: frames_per_second.max(1),
+      frame_count: 0,
+      palette: HashMap::new(),
+      sequence_number: 0,
+    };
+    // Earlier states take the index when several share a color.
+    for (index, &color) in colors.iter().enumerate().rev() {
+      recorder.palette.insert(color, index as u8);
+    }
+
+    let header = match format {
+      Format::Gif => gif_header(recorder.viewport.width, recorder.viewport.height, colors),
+      Format::Apng => apng_header(recorder.viewport.width, recorder.viewport.height, 0),
+      Format::Y4m => format!("YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444\n", recorder.viewport.width, recorder.viewport.height, recorder.frames_per_second).into_bytes(),
+    };
+    recorder.write(&header)?;
+    Ok(recorder)
+  }
+
+  pub fn record(&mut self, universe: &Universe, colors: &Colors) -> Result<(), String> {
+    let (width, height) = (self.viewport.width, self.viewport.height);
+    if self.follow && let Some(bounds) = selection::bounding_rectangle(universe) {
+      let margin = FOLLOW_MARGIN_CELLS;
+      let bounds = selection::Rectangle { x: bounds.x - margin, y: bounds.y - margin, width: bounds.width + 2 * margin, height: bounds.height + 2 * margin };
+      self.viewport = Viewport::fitting(width, height, self.viewport.hexagonal, &bounds);
+    }
+    let mut pixels = vec![0u32; width as usize * height as usize];
+    render::render(universe, &self.viewport, colors, &mut pixels);
+
+    let frame = match self.format {
+      Format::Gif => self.gif_frame(&pixels),
+      Format::Apng => self.apng_frame(&pixels),
+      Format::Y4m => y4m_frame(&pixels),
+    };
+    self.write(&frame)?;
+    self.frame_count += 1;
+    Ok(())
+  }
+
+  // Ends the animation, returning how many frames it has.
+  pub fn finish(mut self) -> Result<u32, String> {
+    match self.format {
+      Format::Gif => self.write(&[0x3B])?,
+      Format::Apng => {
+        let mut trailer = Vec::new();
+        png::write_chunk(&mut trailer, b"IEND", &[]);
+        self.write(&trailer)?;
+        // The number of frames comes before any of them, so it is filled in now that it is known.
+        let animation_control = apng_header(self.viewport.width, self.viewport.height, self.frame_count)[ANIMATION_CONTROL_OFFSET as usize..].to_vec();
+        self.writer.seek(SeekFrom::Start(ANIMATION_CONTROL_OFFSET)).and_then(|_| self.writer.write_all(&animation_control))
+            .map_err(|error| format!("Could not write recording \"{}\": {}", self.path, error))?;
+      }
+      Format::Y4m => {}
+    }
+    self.writer.flush().map_err(|error| format!("Could not write recording \"{}\": {}", self.path, error))?;
+    Ok(self.frame_count)
+  }
+
+  fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+    self.writer.write_all(bytes).map_err(|error| format!("Could not write recording \"{}\": {}", self.path, error))
+  }
+
+  fn gif_frame(&self, pixels: &[u32]) -> Vec<u8> {
+    let (width, height) = (self.viewport.width as u16, self.viewport.height as u16);
+    let delay = (100 / self.frames_per_second) as u16;
+    // A graphic control extension with the delay before the next frame.
+    let mut frame = vec![0x21, 0xF9, 4, 0];
+    frame.extend(delay.to_le_bytes());
+    frame.extend([0, 0]);
+    // An image descriptor covering the whole screen, using the global palette.
+    frame.push(0x2C);
+    frame.extend([0, 0, 0, 0]);
+    frame.extend(width.to_le_bytes());
+    frame.extend(height.to_le_bytes());
+    frame.push(0);
+
+    let indices: Vec<u8> = pixels.iter().map(|pixel| self.palette.get(pixel).copied().unwrap_or(0)).collect();
+    frame.push(8);
+    for block in lzw(&indices).chunks(255) {
+      frame.push(block.len() as u8);
+      frame.extend(block);
+    }
+    frame.push(0);
+    frame
+  }
+
+  fn apng_frame(&mut self, pixels: &[u32]) -> Vec<u8> {
+    let mut frame_control = self.sequence_number.to_be_bytes().to_vec();
+    frame_control.extend(self.viewport.width.to_be_bytes());
+    frame_control.extend(self.viewport.height.to_be_bytes());
+    frame_control.extend([0; 8]);
+    frame_control.extend(1u16.to_be_bytes());
+    frame_control.extend((self.frames_per_second as u16).to_be_bytes());
+    frame_control.extend([0, 0]);
+    self.sequence_number += 1;
+
+    let mut chunks = Vec::new();
+    png::write_chunk(&mut chunks, b"fcTL", &frame_control);
+    let data = png::zlib(&png::scanlines(self.viewport.width, pixels));
+    // The first frame doubles as the still image shown by programs that do not support animation.
+    if self.frame_count == 0 {
+      png::write_chunk(&mut chunks, b"IDAT", &data);
+    } else {
+      let mut frame_data = self.sequence_number.to_be_bytes().to_vec();
+      frame_data.extend(data);
+      png::write_chunk(&mut chunks, b"fdAT", &frame_data);
+      self.sequence_number += 1;
+    }
+    chunks
+  }
+}
+
+// The GIF header, with the rule's colors as the global palette and an extension to loop forever.
+fn gif_header(width: u32, height: u32, colors: &Colors) -> Vec<u8> {
+  let mut header = b"GIF89a".to_vec();
+  header.extend((width as u16).to_le_bytes());
+  header.extend((height as u16).to_le_bytes());
+  header.extend([0xF7, 0, 0]);
+  for color in colors {
+    header.extend(&color.to_be_bytes()[1..]);
+  }
+  header.extend([0x21, 0xFF, 11]);
+  header.extend(b"NETSCAPE2.0");
+  header.extend([3, 1, 0, 0, 0]);
+  header
+}
+
+// The PNG signature, IHDR chunk and animation control chunk, set to loop forever.
+fn apng_header(width: u32, height: u32, frame_count: u32) -> Vec<u8> {
+  let mut header = png::SIGNATURE.to_vec();
+  png::write_chunk(&mut header, b"IHDR", &png::header(width, height));
+  let mut animation_control = frame_count.to_be_bytes().to_vec();
+  animation_control.extend(0u32.to_be_bytes());
+  png::write_chunk(&mut header, b"acTL", &animation_control);
+  header
+}
+
+// A frame of full resolution Y, Cb and Cr planes, converted from RGB as in BT.601.
+fn y4m_frame(pixels: &[u32]) -> Vec<u8> {
+  let mut frame = b"FRAME\n".to_vec();
+  let channels = |pixel: u32| (((pixel >> 16) & 0xFF) as i32, ((pixel >> 8) & 0xFF) as i32, (pixel & 0xFF) as i32);
+  frame.extend(pixels.iter().map(|&pixel| {
+    let (red, green, blue) = channels(pixel);
+    (((66 * red + 129 * green + 25 * blue + 128) >> 8) + 16) as u8
+  }));
+  frame.extend(pixels.iter().map(|&pixel| {
+    let (red, green, blue) = channels(pixel);
+    (((-38 * red - 74 * green + 112 * blue + 128) >> 8) + 128) as u8
+  }));
+  frame.extend(pixels.iter().map(|&pixel| {
+    let (red, green, blue) = channels(pixel);
+    (((112 * red - 94 * green - 18 * blue + 128) >> 8) + 128) as u8
+  }));
+  frame
+}
+
+// Compresses 8-bit palette indices with GIF's variant of LZW, starting over with a clear code whenever the table of
+// codes fills up.
+fn lzw(indices: &[u8]) -> Vec<u8> {
+  const CLEAR_CODE: u32 = 256;
+  const END_CODE: u32 = 257;
+
+  let mut bytes = Vec::new();
+  let (mut buffer, mut bit_count) = (0u32, 0u32);
+  let mut write_code = |code: u32, code_bits: u32| {
+    buffer |= code << bit_count;
+    bit_count += code_bits;
+    while bit_count >= 8 {
+      bytes.push(buffer as u8);
+      buffer >>= 8;
+      bit_count -= 8;
+    }
+  };
+
+  let mut codes: HashMap<(u32, u8), u32> = HashMap::new();
+  let mut next_code = END_CODE + 1;
+  let mut code_bits = 9;
+  write_code(CLEAR_CODE, code_bits);
+  let Some((&first, rest)) = indices.split_first() else {
+    write_code(END_CODE, code_bits);
+    write_code(0, 7);
+    return bytes;
+  };
+
+  let mut prefix = first as u32;
+  for &index in rest {
+    if let Some(&code) = codes.get(&(prefix, index)) {
+      prefix = code;
+      continue;
+    }
+    write_code(prefix, code_bits);
+    // Decoders add each code a step behind, as they only learn its last index from the code after it, so codes grow
+    // once the table is full before the code about to be added.
+    if next_code >= 1 << code_bits && code_bits < MAX_GIF_CODE_BITS {
+      code_bits += 1;
+    }
+    if next_code < 1 << MAX_GIF_CODE_BITS {
+      codes.insert((prefix, index), next_code);
+      next_code += 1;
+    } else {
+      write_code(CLEAR_CODE, code_bits);
+      codes.clear();
+      next_code = END_CODE + 1;
+      code_bits = 9;
+    }
+    prefix = index as u32;
+  }
+  write_code(prefix, code_bits);
+  if next_code >= 1 << code_bits && code_bits < MAX_GIF_CODE_BITS {
+    code_bits += 1;
+  }
+  write_code(END_CODE, code_bits);
+  write_code(0, 7);
+  bytes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::universe::Buffers;
+
+  // Decodes GIF's LZW the way decoders do, adding each code a step behind the encoder and growing the codes as soon as
+  // the table is full. Returns the indices along with the width of every code read.
+  fn unlzw(bytes: &[u8]) -> (Vec<u8>, Vec<u32>) {
+    let (mut indices, mut widths) = (Vec::new(), Vec::new());
+    let mut table: Vec<Vec<u8>> = (0..=257u32).map(|code| vec![code as u8]).collect();
+    let (mut position, mut code_bits) = (0, 9);
+    let mut previous: Option<u32> = None;
+    loop {
+      let code = (0..code_bits).fold(0, |code, bit| code | (((bytes[(position + bit) / 8] >> ((position + bit) % 8)) & 1) as u32) << bit);
+      position += code_bits;
+      widths.push(code_bits as u32);
+      match code {
+        256 => {
+          table.truncate(258);
+          (code_bits, previous) = (9, None);
+          continue;
+        }
+        257 => return (indices, widths),
+        _ => {}
+      }
+      let entry = match (table.get(code as usize), previous) {
+        (Some(entry), _) => entry.clone(),
+        (None, Some(previous)) => {
+          let mut entry = table[previous as usize].clone();
+          entry.push(entry[0]);
+          entry
+        }
+        (None, None) => panic!("code {} before any other", code),
+      };
+      if let Some(previous) = previous && table.len() < 1 << MAX_GIF_CODE_BITS {
+        let mut added = table[previous as usize].clone();
+        added.push(entry[0]);
+        table.push(added);
+      }
+      if table.len() == 1 << code_bits && code_bits < MAX_GIF_CODE_BITS as usize {
+        code_bits += 1;
+      }
+      indices.extend(&entry);
+      previous = Some(code);
+    }
+  }
+
+  #[test]
+  fn lzw_round_trips_through_every_code_width() {
+    // Noise keeps adding codes, so the table fills up and starts over several times.
+    let noise: Vec<u8> = (0..40000u32).map(|index| (index.wrapping_mul(2654435761) >> 24) as u8 % 7).collect();
+    let (indices, widths) = unlzw(&lzw(&noise));
+    assert_eq!(indices, noise);
+    for width in 9..=12 {
+      assert!(widths.contains(&width), "no {}-bit codes", width);
+    }
+    // After the clear code, the first code adds nothing to the table and each one after it adds a code, so codes grow
+    // to 10 bits once the 254 codes left below 512 have been added.
+    let first_wide_code = widths.iter().position(|&width| width == 10).unwrap();
+    assert_eq!(first_wide_code, 1 + 1 + (512 - 258));
+    assert!(widths.iter().skip(1).filter(|&&width| width == 9).count() > 256);
+
+    for indices in [vec![], vec![3], vec![0; 100000]] {
+      assert_eq!(unlzw(&lzw(&indices)).0, indices);
+    }
+  }
+
+  // Reads the chunks of a PNG file as their kinds and data, checking their CRCs.
+  fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+    while offset < png.len() {
+      let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+      let crc = u32::from_be_bytes(png[offset + 8 + length..offset + 12 + length].try_into().unwrap());
+      assert_eq!(crc, png::crc32(&png[offset + 4..offset + 8 + length]));
+      chunks.push((String::from_utf8(png[offset + 4..offset + 8].to_vec()).unwrap(), png[offset + 8..offset + 8 + length].to_vec()));
+      offset += 12 + length;
+    }
+    chunks
+  }
+
+  #[test]
+  fn apng_frame_count_is_filled_in() {
+    let path = std::env::temp_dir().join(format!("lifer-recording-{}.png", std::process::id()));
+    let path = path.to_str().unwrap();
+    let mut universe = Universe::new(Buffers::for_rule(None), None);
+    for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+      universe.set_cell(x, y, 1);
+    }
+    let colors = render::colors_for_rule(None);
+    let viewport = Viewport::new(16, 12, false);
+    let mut recorder = Recorder::start(path, viewport, true, 10, &colors).unwrap();
+    for _ in 0..3 {
+      recorder.record(&universe, &colors).unwrap();
+      universe.step();
+    }
+    assert_eq!(recorder.finish().unwrap(), 3);
+    let apng = std::fs::read(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let chunks = chunks(&apng);
+    let kinds: Vec<&str> = chunks.iter().map(|(kind, _)| kind.as_str()).collect();
+    assert_eq!(kinds, ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"]);
+    assert_eq!(chunks[1].1, [0, 0, 0, 3, 0, 0, 0, 0]);
+    // Frame control and frame data chunks share one sequence, which the first frame's image data is not part of.
+    let sequence_numbers: Vec<u32> = chunks[2..8].iter()
+        .filter(|(kind, _)| kind != "IDAT")
+        .map(|(_, data)| u32::from_be_bytes(data[..4].try_into().unwrap()))
+        .collect();
+    assert_eq!(sequence_numbers, [0, 1, 2, 3, 4]);
+  }
+}
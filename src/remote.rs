@@ -0,0 +1,320 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc;
+use crate::conway::{CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH};
+use crate::history::History;
+use crate::json;
+use crate::json::Json;
+use crate::pattern;
+use crate::pattern::{Pattern, PatternCells};
+use crate::rule;
+use crate::rule::Rule;
+use crate::selection;
+use crate::selection::Rectangle;
+use crate::universe::{Buffers, Universe};
+
+// Regions bigger than this many cells are refused rather than written out as RLE.
+const MAX_REGION_CELLS: i64 = 1 << 24;
+// Steps are carried out between frames, so a single command may not take more generations than this and hold up the
+// window for long. Scripts wanting more send several.
+const MAX_STEP_GENERATIONS: i64 = 10_000;
+// Patterns sent as RLE make for long lines, but a client that never ends its line is cut off past this many bytes
+// rather than left to fill up memory.
+const MAX_LINE_LENGTH: u64 = 1 << 24;
+
+// A command sent to a running instance, already checked and with any pattern or rule it names loaded, so that all
+// that is left is to carry it out on the board. Commands arrive a few at a time, so the size of rules is not a concern.
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
+  // Places a pattern with its top-left cell at (x, y).
+  Load { cells: PatternCells, x: i64, y: i64 },
+  // Switches to a rule, along with the name it is written into patterns with.
+  Rule { rule: Rule, name: String },
+  Step(u64),
+  Pause,
+  Resume,
+  Population,
+  Region(Rectangle),
+}
+
+pub struct Request {
+  pub command: Command,
+  reply: mpsc::Sender<Result<Json, String>>,
+}
+
+impl Request {
+  // Answers the client that sent the command with the fields of a JSON object, or an error.
+  pub fn reply(self, result: Result<Json, String>) {
+    // The client may have gone away in the meantime.
+    self.reply.send(result).ok();
+  }
+}
+
+// Listens for scripts controlling the simulation, on a TCP port when given "host:port" and on a Unix socket at the
+// given path otherwise. Every client sends commands as JSON objects, one per line, and gets a line of JSON back for
+// each, in order:
+//
+//   {"command": "load", "path": "glider.rle", "x": 10, "y": 20}   or "rle" with the pattern itself instead of "path"
+//   {"command": "rule", "rule": "B36/S23"}
+//   {"command": "step", "generations": 100}
+//   {"command": "pause"}   {"command": "resume"}
+//   {"command": "population"}
+//   {"command": "region", "x": 0, "y": 0, "width": 64, "height": 64}
+//
+// Replies have "ok" set to true along with whatever the command reports, or to false along with an "error". An "id"
+// given with a command is copied into its reply. Commands are carried out by the main loop between frames, so that
+// they see the board as it is drawn.
+//
+// Loading a "path" reads a file as Lifer's user, so only clients of a Unix socket, which its file permissions guard,
+// may do it. Clients over TCP have to send the pattern as "rle".
+pub struct Server {
+  requests: mpsc::Receiver<Request>,
+}
+
+impl Server {
+  pub fn listen(address: &str) -> Result<Self, String> {
+    let (sender, requests) = mpsc::channel();
+    let listen_error = |error: io::Error| format!("Failed to listen on {}: {}", address, error);
+    if address.contains(':') {
+      let listener = TcpListener::bind(address).map_err(listen_error)?;
+      std::thread::spawn(move || accept(listener.incoming(), TcpStream::try_clone, false, sender));
+    } else {
+      // A socket left behind by an earlier run is replaced, but nothing else is.
+      if std::fs::symlink_metadata(address).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(address).map_err(listen_error)?;
+      }
+      let listener = UnixListener::bind(Path::new(address)).map_err(listen_error)?;
+      std::thread::spawn(move || accept(listener.incoming(), UnixStream::try_clone, true, sender));
+    }
+    println!("Listening for commands on {}", address);
+    Ok(Server { requests })
+  }
+
+  // The commands that arrived since the last call, without waiting for any more.
+  pub fn requests(&self) -> impl Iterator<Item = Request> + '_ {
+    self.requests.try_iter()
+  }
+}
+
+fn accept<S: Read + Write + Send + 'static>(streams: impl Iterator<Item = io::Result<S>>, try_clone: fn(&S) -> io::Result<S>, reads_files: bool, requests: mpsc::Sender<Request>) {
+  for stream in streams.flatten() {
+    let Ok(reader) = try_clone(&stream) else { continue };
+    let requests = requests.clone();
+    std::thread::spawn(move || serve(BufReader::new(reader), stream, reads_files, requests));
+  }
+}
+
+// Answers a client's commands until it disconnects. Commands that cannot be parsed are answered straight away, and
+// the rest wait for the main loop.
+fn serve(mut reader: impl BufRead, mut writer: impl Write, reads_files: bool, requests: mpsc::Sender<Request>) {
+  loop {
+    let mut line = String::new();
+    match reader.by_ref().take(MAX_LINE_LENGTH).read_line(&mut line) {
+      Ok(0) | Err(_) => return,
+      // There is no telling where the next command starts in the rest of a line that was too long.
+      Ok(length) if length as u64 == MAX_LINE_LENGTH && !line.ends_with('\n') => {
+        let error = format!("Commands are limited to {} bytes", MAX_LINE_LENGTH);
+        writeln!(writer, "{}", reply_json(None, Err(error))).ok();
+        return;
+      }
+      Ok(_) => {}
+    }
+    if line.trim().is_empty() {
+      continue;
+    }
+    let message = json::parse(&line);
+    let result = message.clone().and_then(|message| parse_command(&message, reads_files)).and_then(|command| {
+      let (reply, replies) = mpsc::channel();
+      requests.send(Request { command, reply }).map_err(|_| "Lifer is shutting down".to_string())?;
+      replies.recv().map_err(|_| "Lifer is shutting down".to_string())?
+    });
+
+    let id = message.ok().and_then(|message| message.get("id").cloned());
+    if writeln!(writer, "{}", reply_json(id, result)).and_then(|_| writer.flush()).is_err() {
+      return;
+    }
+  }
+}
+
+// The line sent back for a command, with the "id" it was given if any.
+fn reply_json(id: Option<Json>, result: Result<Json, String>) -> Json {
+  let mut fields = match result {
+    Ok(Json::Object(fields)) => [("ok".to_string(), Json::Bool(true))].into_iter().chain(fields).collect(),
+    Ok(value) => vec![("ok".to_string(), Json::Bool(true)), ("result".to_string(), value)],
+    Err(error) => vec![("ok".to_string(), Json::Bool(false)), ("error".to_string(), Json::String(error))],
+  };
+  if let Some(id) = id {
+    fields.insert(0, ("id".to_string(), id));
+  }
+  Json::Object(fields)
+}
+
+// Commands loading a "path" are refused unless the client may have files read for it.
+fn parse_command(message: &Json, reads_files: bool) -> Result<Command, String> {
+  let name = message.get("command").and_then(Json::as_str).ok_or("Expected an object with a \"command\"")?;
+  match name {
+    "load" => {
+      let cells = match (message.get("path").and_then(Json::as_str), message.get("rle").and_then(Json::as_str)) {
+        (Some(path), _) if reads_files => pattern::load_pattern_file(Path::new(path))?,
+        (Some(_), _) => return Err("Loading a \"path\" is only allowed over a Unix socket, send the pattern as \"rle\" instead".to_string()),
+        (None, Some(rle)) => pattern::parse_rle(rle)?,
+        (None, None) => return Err("Loading needs a \"path\" or \"rle\"".to_string()),
+      };
+      Ok(Command::Load { cells, x: integer(message, "x", Some(0))?, y: integer(message, "y", Some(0))? })
+    }
+    "rule" => {
+      let source = message.get("rule").and_then(Json::as_str).ok_or("Expected a \"rule\"")?;
+      let rule = rule::load_rule(source)?;
+      Ok(Command::Rule { name: rule::rule_name(&rule, source), rule })
+    }
+    "step" => match integer(message, "generations", Some(1))? {
+      generations if generations < 0 => Err("Cannot step a negative number of generations".to_string()),
+      generations if generations > MAX_STEP_GENERATIONS => Err(format!("Steps are limited to {} generations at a time", MAX_STEP_GENERATIONS)),
+      generations => Ok(Command::Step(generations as u64)),
+    },
+    "pause" => Ok(Command::Pause),
+    "resume" => Ok(Command::Resume),
+    "population" => Ok(Command::Population),
+    "region" => {
+      let region = Rectangle {
+        x: integer(message, "x", None)?,
+        y: integer(message, "y", None)?,
+        width: integer(message, "width", None)?,
+        height: integer(message, "height", None)?,
+      };
+      if region.width <= 0 || region.height <= 0 {
+        return Err("Regions need a positive width and height".to_string());
+      }
+      if region.width.saturating_mul(region.height) > MAX_REGION_CELLS {
+        return Err(format!("Regions are limited to {} cells", MAX_REGION_CELLS));
+      }
+      Ok(Command::Region(region))
+    }
+    _ => Err(format!("Unknown command \"{}\"", name)),
+  }
+}
+
+// An integer field of a command, which falls back to the default when missing unless there is none.
+fn integer(message: &Json, key: &str, default: Option<i64>) -> Result<i64, String> {
+  match message.get(key) {
+    Some(value) => value.as_i64().ok_or_else(|| format!("Expected \"{}\" to be an integer", key)),
+    None => default.ok_or_else(|| format!("Expected \"{}\"", key)),
+  }
+}
+
+pub fn rectangle_json(rectangle: &Rectangle) -> Json {
+  Json::object([
+    ("x", rectangle.x.into()),
+    ("y", rectangle.y.into()),
+    ("width", rectangle.width.into()),
+    ("height", rectangle.height.into()),
+  ])
+}
+
+pub fn population(universe: &Universe) -> Json {
+  Json::object([("generation", universe.generation.into()), ("population", universe.counted_population().into())])
+}
+
+// The cells of a region of the universe as RLE, relative to the region's top-left cell.
+pub fn region(universe: &Universe, region: &Rectangle, rule_name: &str) -> Json {
+  let cells = selection::copy(universe, *region).to_cells(universe);
+  Json::object([
+    ("x", region.x.into()),
+    ("y", region.y.into()),
+    ("width", region.width.into()),
+    ("height", region.height.into()),
+    ("rle", pattern::format_rle(&cells, universe.states(), rule_name).as_str().into()),
+  ])
+}
+
+// Switches the universe to another rule. The dense board can only change between the rules it runs, but other boards
+// are replaced by a board for the new rule holding the same cells, where states the new rule does not have become
+// its last state.
+pub fn change_rule(universe: &mut Universe, rule: Rule) -> Result<(), String> {
+  let runs_on_dense_board = matches!(rule, Rule::Table(_) | Rule::Margolus(_)) && rule.states() == 2;
+  match universe.buffers {
+    Buffers::Dense(..) if runs_on_dense_board => {
+      universe.rule = Some(rule);
+      return Ok(());
+    }
+    Buffers::Dense(..) => return Err("The dense board only runs two-state rules with a transition table, start with --sparse to switch to other rules".to_string()),
+    _ => {}
+  }
+
+  let mut changed = Universe::new(Buffers::for_rule(Some(&rule)), Some(rule));
+  changed.generation = universe.generation;
+  let blocks: BTreeSet<(i64, i64)> = universe.occupied_blocks().into_iter().map(|(_, block, _)| block).collect();
+  let (width, height) = (CELL_BLOCK_WIDTH as i64, CELL_BLOCK_HEIGHT as i64);
+  for (block_x, block_y) in blocks {
+    for (cell, state) in universe.block_states(block_x, block_y).into_iter().enumerate().filter(|&(_, state)| state != 0) {
+      let (x, y) = (block_x * width + cell as i64 % width, block_y * height + cell as i64 / width);
+      changed.set_cell(x, y, state.min(changed.states() - 1));
+    }
+  }
+  *universe = changed;
+  Ok(())
+}
+
+// Places loaded cells through the history, so that loading a pattern can be undone like pasting one.
+pub fn load(history: &mut History, universe: &mut Universe, cells: &PatternCells, x: i64, y: i64) -> Json {
+  let pattern = Pattern::from_cells(cells, universe);
+  rectangle_json(&selection::paste(history, universe, &pattern, x, y))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn command(text: &str, reads_files: bool) -> Result<Command, String> {
+    parse_command(&json::parse(text).unwrap(), reads_files)
+  }
+
+  #[test]
+  fn limits_steps() {
+    assert!(matches!(command(r#"{"command": "step"}"#, false), Ok(Command::Step(1))));
+    assert!(matches!(command(&format!(r#"{{"command": "step", "generations": {}}}"#, MAX_STEP_GENERATIONS), false), Ok(Command::Step(_))));
+    assert!(command(&format!(r#"{{"command": "step", "generations": {}}}"#, MAX_STEP_GENERATIONS + 1), false).is_err());
+    assert!(command(r#"{"command": "step", "generations": -1}"#, false).is_err());
+  }
+
+  #[test]
+  fn limits_patterns() {
+    assert!(matches!(command(r#"{"command": "load", "rle": "1000000b$3o!"}"#, false), Ok(Command::Load { .. })));
+    let rle = |rle: &str| command(&format!(r#"{{"command": "load", "rle": "{}"}}"#, rle), false);
+    assert!(rle("99999999999999999999o!").is_err_and(|error| error.contains("too long")));
+    assert!(rle(&format!("{}o!", 1 << 30)).is_err_and(|error| error.contains("cells")));
+    assert!(rle(&format!("{}b{}o!", i64::MAX, 2)).is_err_and(|error| error.contains("too wide")));
+  }
+
+  // Replies come back in order, and a client sending a line too long to be a command is answered and cut off.
+  #[test]
+  fn limits_lines() {
+    let serve_text = |text: String| {
+      let (requests, _) = mpsc::channel();
+      let mut replies = Vec::new();
+      serve(text.as_bytes(), &mut replies, false, requests);
+      String::from_utf8(replies).unwrap().lines().map(|line| json::parse(line).unwrap()).collect::<Vec<_>>()
+    };
+    let replies = serve_text("{\"command\": \"jump\", \"id\": 7}\n\n[]\n".to_string());
+    assert_eq!(replies.len(), 2);
+    assert_eq!((replies[0].get("id"), replies[0].get("ok")), (Some(&Json::Number(7.0)), Some(&Json::Bool(false))));
+
+    let long_line = format!("{{\"command\": \"load\", \"rle\": \"{}!\"}}\n{{\"command\": \"jump\"}}\n", "o".repeat(MAX_LINE_LENGTH as usize));
+    let replies = serve_text(long_line);
+    assert_eq!(replies.len(), 1);
+    assert!(replies[0].get("error").and_then(Json::as_str).is_some_and(|error| error.contains("limited")));
+  }
+
+  #[test]
+  fn loads_paths_only_for_clients_that_may_read_files() {
+    let missing = r#"{"command": "load", "path": "/nonexistent/pattern.rle"}"#;
+    assert!(command(missing, false).is_err_and(|error| error.contains("Unix socket")));
+    assert!(command(missing, true).is_err_and(|error| !error.contains("Unix socket")));
+    assert!(matches!(command(r#"{"command": "load", "rle": "3o!", "x": 5}"#, false), Ok(Command::Load { x: 5, y: 0, .. })));
+  }
+}
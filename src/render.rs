@@ -0,0 +1,384 @@
+use std::ops::Range;
+use crate::conway::{CELLS_PER_BLOCK, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH};
+use crate::metadata::Metadata;
+use crate::pattern::Pattern;
+use crate::rule::Rule;
+use crate::selection::Rectangle;
+use crate::universe::{BlockCoordinate, Universe};
+
+// Pixels are 32-bit ARGB.
+pub const BACKGROUND_COLOR: u32 = 0xFF101018;
+pub const LIVE_COLOR: u32 = 0xFFF0F0E8;
+// The first state past live. Later states fade from it toward the background.
+pub const REFRACTORY_COLOR: u32 = 0xFF3A5A80;
+// Blended over the cells of the selection.
+pub const SELECTION_COLOR: u32 = 0xFF3070F0;
+// Drawn over the cells of a pattern that is being dragged onto the board.
+pub const GHOST_COLOR: u32 = 0xFF60D090;
+
+// Cells that died this many generations ago or fewer leave a fading trail behind them.
+const TRAIL_LENGTH: u64 = 32;
+const DIED_COLOR: u32 = 0xFFC04040;
+// Live cells get darker with age up to this many generations.
+const MAX_SHADED_AGE: u64 = 1000;
+const HEAT_COLORS: [u32; 5] = [0xFF000000, 0xFF2040C0, 0xFFE02020, 0xFFF0D020, 0xFFFFFFF0];
+
+pub const MIN_CELL_SIZE: f64 = 1.0 / 256.0;
+pub const MAX_CELL_SIZE: f64 = 128.0;
+
+// Rows of a hexagonal grid are closer together than its columns, so that the hexagons fit together.
+const HEXAGON_ROW_HEIGHT: f64 = 0.866_025_403_784_438_6; // sqrt(3) / 2
+
+// The part of the universe shown on screen. (x, y) is the point at the top-left corner of the view, measured in cell
+// widths, and cell_size is how many pixels wide each cell is drawn. On a hexagonal grid every row is drawn half a cell
+// to the left of the row above, which turns the square grid's emulated hexagonal neighborhood into real hexagons.
+#[derive(Clone)]
+pub struct Viewport {
+  pub x: f64,
+  pub y: f64,
+  pub cell_size: f64,
+  pub width: u32,
+  pub height: u32,
+  pub hexagonal: bool,
+}
+
+impl Viewport {
+  // Starts out centered on the origin.
+  pub fn new(width: u32, height: u32, hexagonal: bool) -> Self {
+    let cell_size = 4.0;
+    Viewport {
+      x: -(width as f64) / cell_size / 2.0,
+      y: -(height as f64) / cell_size / 2.0,
+      cell_size,
+      width,
+      height,
+      hexagonal,
+    }
+  }
+
+  // A view of the given size centered on the given rectangle of cells, zoomed out just far enough to show all of it.
+  pub fn fitting(width: u32, height: u32, hexagonal: bool, rectangle: &Rectangle) -> Self {
+    let (left, right, top, bottom) = screen_bounds(rectangle, hexagonal);
+    let cell_size = (width as f64 / (right - left)).min(height as f64 / (bottom - top)).clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
+    Viewport {
+      x: (left + right) / 2.0 - width as f64 / cell_size / 2.0,
+      y: (top + bottom) / 2.0 - height as f64 / cell_size / 2.0,
+      cell_size,
+      width,
+      height,
+      hexagonal,
+    }
+  }
+
+  // A view of the given rectangle of cells, sized so that every pixel is the given number of cells across.
+  pub fn covering(rectangle: &Rectangle, cells_per_pixel: f64, hexagonal: bool) -> Self {
+    let (left, right, top, bottom) = screen_bounds(rectangle, hexagonal);
+    let size = |cells: f64| (cells / cells_per_pixel).ceil().clamp(1.0, u32::MAX as f64) as u32;
+    Viewport { x: left, y: top, cell_size: 1.0 / cells_per_pixel, width: size(right - left), height: size(bottom - top), hexagonal }
+  }
+
+  // Moves the view so that the given point, measured in cells, is in the middle of it.
+  pub fn center_on(&mut self, cell_x: f64, cell_y: f64) {
+    let (x, y) = if self.hexagonal { (cell_x - cell_y / 2.0, cell_y * HEXAGON_ROW_HEIGHT) } else { (cell_x, cell_y) };
+    self.x = x - self.width as f64 / self.cell_size / 2.0;
+    self.y = y - self.height as f64 / self.cell_size / 2.0;
+  }
+
+  pub fn resize(&mut self, width: u32, height: u32) {
+    self.width = width;
+    self.height = height;
+  }
+
+  pub fn pan(&mut self, pixels_x: f64, pixels_y: f64) {
+    self.x -= pixels_x / self.cell_size;
+    self.y -= pixels_y / self.cell_size;
+  }
+
+  // Zooms by the given factor, keeping the point under the given pixel in place.
+  pub fn zoom(&mut self, factor: f64, pixel_x: f64, pixel_y: f64) {
+    let cell_size = (self.cell_size * factor).clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
+    self.x += pixel_x / self.cell_size - pixel_x / cell_size;
+    self.y += pixel_y / self.cell_size - pixel_y / cell_size;
+    self.cell_size = cell_size;
+  }
+
+  // Returns the coordinates of the cell drawn at the given pixel.
+  pub fn cell_at(&self, pixel_x: f64, pixel_y: f64) -> (i64, i64) {
+    let x = self.x + pixel_x / self.cell_size;
+    let y = self.y + pixel_y / self.cell_size;
+    if !self.hexagonal {
+      return (x.floor() as i64, y.floor() as i64);
+    }
+
+    // Find the nearest hexagon center in axial coordinates, where the row r = y runs along the screen and q = x - y
+    // runs at 60 degrees to it, by rounding in cube coordinates.
+    let r = y / HEXAGON_ROW_HEIGHT;
+    let q = x - r / 2.0;
+    let (mut rounded_q, mut rounded_r, rounded_s) = (q.round(), r.round(), (-q - r).round());
+    let (q_error, r_error, s_error) = ((rounded_q - q).abs(), (rounded_r - r).abs(), (rounded_s + q + r).abs());
+    if q_error > r_error && q_error > s_error {
+      rounded_q = -rounded_r - rounded_s;
+    } else if r_error > s_error {
+      rounded_r = -rounded_q - rounded_s;
+    }
+    ((rounded_q + rounded_r) as i64, rounded_r as i64)
+  }
+}
+
+// The left, right, top and bottom edges of where a rectangle of cells is drawn, measured in cell widths.
+fn screen_bounds(rectangle: &Rectangle, hexagonal: bool) -> (f64, f64, f64, f64) {
+  let (x, y) = (rectangle.x as f64, rectangle.y as f64);
+  let (left, right, top, bottom) = (x, x + rectangle.width as f64, y, y + rectangle.height as f64);
+  if !hexagonal {
+    return (left, right, top, bottom);
+  }
+  // Hexagons are centered on their coordinates, and each row is shifted half a cell left of the one above.
+  (left - (bottom - 1.0) / 2.0 - 0.5, right - top / 2.0 - 0.5, (top - 0.5) * HEXAGON_ROW_HEIGHT, (bottom - 0.5) * HEXAGON_ROW_HEIGHT)
+}
+
+// What cells are colored by.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorMode {
+  States,
+  // Live cells are colored by how long ago they were born, from white when newborn to red.
+  Age,
+  // Cells that died recently fade out behind the live ones.
+  Trails,
+  // Every cell is colored by how many times it has changed since metadata started being tracked.
+  Activity,
+}
+
+impl ColorMode {
+  pub fn next(self) -> Self {
+    match self {
+      ColorMode::States => ColorMode::Age,
+      ColorMode::Age => ColorMode::Trails,
+      ColorMode::Trails => ColorMode::Activity,
+      ColorMode::Activity => ColorMode::States,
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      ColorMode::States => "state",
+      ColorMode::Age => "age",
+      ColorMode::Trails => "trails of dead cells",
+      ColorMode::Activity => "activity",
+    }
+  }
+}
+
+// A color for every state a cell can be in.
+pub type Colors = [u32; 256];
+
+// Colors for the states of the given rule, taken from its rule file where it has any.
+pub fn colors_for_rule(rule: Option<&Rule>) -> Colors {
+  let states = rule.map_or(2, |rule| rule.states()) as u32;
+  let mut colors = [BACKGROUND_COLOR; 256];
+  colors[1] = LIVE_COLOR;
+  for state in 2..states {
+    let fraction = (state - 2) * 192 / states.saturating_sub(2).max(1);
+    let channel = |shift: u32| {
+      let (from, to) = ((REFRACTORY_COLOR >> shift) & 0xFF, (BACKGROUND_COLOR >> shift) & 0xFF);
+      ((from * (256 - fraction) + to * fraction) / 256) << shift
+    };
+    colors[state as usize] = 0xFF000000 | channel(16) | channel(8) | channel(0);
+  }
+  if let Some(Rule::MultiState(rule)) = rule {
+    for &(state, rgb) in &rule.colors {
+      colors[state as usize] = 0xFF000000 | rgb;
+    }
+  }
+  colors
+}
+
+// Draws the universe into a buffer of width x height pixels, splitting the rows across threads.
+pub fn render(universe: &Universe, viewport: &Viewport, colors: &Colors, pixels: &mut [u32]) {
+  render_cells(universe, viewport, pixels, &|_, states, cell| colors[states[cell.unwrap_or_else(|| first_occupied(states))] as usize]);
+}
+
+// Draws the universe with its cells colored by what the metadata says has happened to them. Where pixels cover whole
+// blocks, they show the cell of the block that stands out most in the given mode.
+pub fn render_metadata(universe: &Universe, viewport: &Viewport, colors: &Colors, metadata: &Metadata, mode: ColorMode, pixels: &mut [u32]) {
+  let generation = universe.generation;
+  let max_changes = metadata.max_changes().max(1) as f64;
+  render_cells(universe, viewport, pixels, &|coordinate, states, cell| {
+    let block = metadata.block(coordinate);
+    let changes = |cell: usize| block.map_or(0, |block| block.changes[cell]);
+    let age = |cell: usize| metadata.generations_since(generation, block.map_or(0, |block| block.last_changed[cell]));
+    let cell = cell.unwrap_or_else(|| match mode {
+      ColorMode::Activity => (0..CELLS_PER_BLOCK as usize).max_by_key(|&cell| changes(cell)).unwrap(),
+      ColorMode::Trails if states.iter().all(|&state| state == 0) => {
+        (0..CELLS_PER_BLOCK as usize).filter(|&cell| changes(cell) > 0).min_by_key(|&cell| age(cell)).unwrap_or(0)
+      }
+      _ => first_occupied(states),
+    });
+
+    let state = states[cell] as usize;
+    match mode {
+      ColorMode::Age if state != 0 => heat(1.0 - 0.85 * ((age(cell) + 1) as f64).ln() / (MAX_SHADED_AGE as f64).ln()),
+      ColorMode::Trails if state == 0 && changes(cell) > 0 && age(cell) < TRAIL_LENGTH => {
+        mix(DIED_COLOR, colors[0], age(cell) as f64 / TRAIL_LENGTH as f64)
+      }
+      ColorMode::Activity if changes(cell) > 0 => heat(((changes(cell) + 1) as f64).ln() / (max_changes + 1.0).ln()),
+      ColorMode::States | ColorMode::Trails => colors[state],
+      _ => colors[0],
+    }
+  });
+}
+
+// Draws the universe a row of pixels at a time, splitting the rows across threads. Each pixel is given the color
+// returned for its block's coordinates and states and the index of its cell in the block, or no index where the pixel
+// covers at least the whole block.
+fn render_cells<F>(universe: &Universe, viewport: &Viewport, pixels: &mut [u32], color: &F)
+    where F: Fn(BlockCoordinate, &[u8; CELLS_PER_BLOCK as usize], Option<usize>) -> u32 + Sync {
+  let width = viewport.width as usize;
+  assert_eq!(pixels.len(), width * viewport.height as usize);
+  if width == 0 {
+    return;
+  }
+  let rows_per_chunk = (viewport.height as usize).div_ceil(crate::num_threads()).max(1);
+
+  std::thread::scope(|scope| {
+    for (chunk_index, chunk) in pixels.chunks_mut(rows_per_chunk * width).enumerate() {
+      scope.spawn(move || {
+        for (row_index, row) in chunk.chunks_mut(width).enumerate() {
+          render_row(universe, viewport, color, chunk_index * rows_per_chunk + row_index, row);
+        }
+      });
+    }
+  });
+}
+
+// Tints every pixel showing a cell inside the given rectangle.
+pub fn highlight(viewport: &Viewport, rectangle: &Rectangle, color: u32, pixels: &mut [u32]) {
+  let width = viewport.width as usize;
+  for (pixel_y, row) in pixels.chunks_mut(width.max(1)).enumerate() {
+    for (pixel_x, pixel) in row.iter_mut().enumerate() {
+      let (x, y) = viewport.cell_at(pixel_x as f64 + 0.5, pixel_y as f64 + 0.5);
+      if rectangle.contains(x, y) {
+        *pixel = blend(*pixel, color);
+      }
+    }
+  }
+}
+
+// Draws a pattern over the board with its top-left cell at (x, y), tinting the rectangle it covers and coloring its
+// occupied cells.
+pub fn ghost(viewport: &Viewport, pattern: &Pattern, x: i64, y: i64, pixels: &mut [u32]) {
+  let width = viewport.width as usize;
+  let rectangle = Rectangle { x, y, width: pattern.width, height: pattern.height };
+  for (pixel_y, row) in pixels.chunks_mut(width.max(1)).enumerate() {
+    for (pixel_x, pixel) in row.iter_mut().enumerate() {
+      let (cell_x, cell_y) = viewport.cell_at(pixel_x as f64 + 0.5, pixel_y as f64 + 0.5);
+      if pattern.is_occupied(cell_x - x, cell_y - y) {
+        *pixel = GHOST_COLOR;
+      } else if rectangle.contains(cell_x, cell_y) {
+        *pixel = blend(*pixel, GHOST_COLOR);
+      }
+    }
+  }
+}
+
+// Mixes a quarter of the second color into the first.
+fn blend(color: u32, tint: u32) -> u32 {
+  mix(color, tint, 0.25)
+}
+
+// Mixes two colors, going from the first to the second as the fraction goes from 0 to 1.
+fn mix(from: u32, to: u32, fraction: f64) -> u32 {
+  let channel = |shift: u32| {
+    let (from, to) = (((from >> shift) & 0xFF) as f64, ((to >> shift) & 0xFF) as f64);
+    ((from + (to - from) * fraction).round() as u32) << shift
+  };
+  0xFF000000 | channel(16) | channel(8) | channel(0)
+}
+
+fn render_row<F>(universe: &Universe, viewport: &Viewport, color: &F, pixel_y: usize, row: &mut [u32])
+    where F: Fn(BlockCoordinate, &[u8; CELLS_PER_BLOCK as usize], Option<usize>) -> u32 {
+  let whole_blocks = viewport.cell_size * CELL_BLOCK_WIDTH as f64 <= 1.0;
+
+  // Neighboring pixels usually fall within the same block, so the last block fetched is kept around.
+  let mut cached_block: Option<((i64, i64), [u8; CELLS_PER_BLOCK as usize])> = None;
+  for (pixel_x, pixel) in row.iter_mut().enumerate() {
+    let (x, y) = viewport.cell_at(pixel_x as f64 + 0.5, pixel_y as f64 + 0.5);
+    let mut block_coordinate = (x.div_euclid(CELL_BLOCK_WIDTH as i64), y.div_euclid(CELL_BLOCK_HEIGHT as i64));
+    // A pixel covering several blocks shows any of them that is occupied rather than the one at its center, so that
+    // sparse patterns do not vanish when zoomed out.
+    if whole_blocks {
+      let (columns, rows) = covered_blocks(viewport, pixel_x, pixel_y);
+      block_coordinate = universe.occupied_block_in(columns, rows).unwrap_or(block_coordinate);
+    }
+    let states = match cached_block {
+      Some((coordinate, states)) if coordinate == block_coordinate => states,
+      _ => {
+        let states = universe.block_states(block_coordinate.0, block_coordinate.1);
+        cached_block = Some((block_coordinate, states));
+        states
+      }
+    };
+
+    let cell = (!whole_blocks).then(|| (y.rem_euclid(CELL_BLOCK_HEIGHT as i64) as u64 * CELL_BLOCK_WIDTH + x.rem_euclid(CELL_BLOCK_WIDTH as i64) as u64) as usize);
+    *pixel = color(block_coordinate, &states, cell);
+  }
+}
+
+// The columns and rows of the blocks holding the cells drawn within a pixel, which on a hexagonal grid are those
+// around the skewed rows it covers.
+fn covered_blocks(viewport: &Viewport, pixel_x: usize, pixel_y: usize) -> (Range<i64>, Range<i64>) {
+  let corners = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| viewport.cell_at((pixel_x + x) as f64, (pixel_y + y) as f64));
+  let range = |cells: [i64; 4], block_size: u64| {
+    let (first, past_last) = (*cells.iter().min().unwrap(), *cells.iter().max().unwrap());
+    let block_size = block_size as i64;
+    first.div_euclid(block_size)..past_last.saturating_sub(1).max(first).div_euclid(block_size) + 1
+  };
+  (range(corners.map(|(x, _)| x), CELL_BLOCK_WIDTH), range(corners.map(|(_, y)| y), CELL_BLOCK_HEIGHT))
+}
+
+// The first occupied cell of a block, which pixels covering whole blocks show so that sparse patterns do not vanish
+// when zoomed out.
+fn first_occupied(states: &[u8; CELLS_PER_BLOCK as usize]) -> usize {
+  states.iter().position(|&state| state != 0).unwrap_or(0)
+}
+
+// A color from black through blue, red and yellow to white, as the fraction goes from 0 to 1.
+fn heat(fraction: f64) -> u32 {
+  let position = fraction.clamp(0.0, 1.0) * (HEAT_COLORS.len() - 1) as f64;
+  let index = (position as usize).min(HEAT_COLORS.len() - 2);
+  mix(HEAT_COLORS[index], HEAT_COLORS[index + 1], position - index as f64)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::universe::Buffers;
+
+  // Each cell's hexagon is centered half a cell further left for every row further down, and rows are
+  // HEXAGON_ROW_HEIGHT apart, so every point within a hexagon maps back to its cell.
+  #[test]
+  fn hexagonal_pixels_map_to_the_nearest_hexagon() {
+    let viewport = Viewport { x: 0.0, y: 0.0, cell_size: 1.0, width: 0, height: 0, hexagonal: true };
+    for (x, y) in [(0, 0), (3, 2), (-4, -1), (5, -7), (-2, 9)] {
+      let (center_x, center_y) = (x as f64 - y as f64 / 2.0, y as f64 * HEXAGON_ROW_HEIGHT);
+      for (offset_x, offset_y) in [(0.0, 0.0), (0.45, 0.0), (-0.45, 0.0), (0.0, 0.5), (0.2, -0.45)] {
+        assert_eq!(viewport.cell_at(center_x + offset_x, center_y + offset_y), (x, y), "offset ({}, {})", offset_x, offset_y);
+      }
+    }
+
+    let square = Viewport { hexagonal: false, cell_size: 4.0, ..viewport };
+    assert_eq!(square.cell_at(-0.5, 7.9), (-1, 1));
+  }
+
+  #[test]
+  fn zoomed_out_pixels_show_any_cell_they_cover() {
+    let mut universe = Universe::new(Buffers::for_rule(None), None);
+    universe.set_cell(300, 5, 1);
+    universe.set_cell(-1, 1000, 1);
+    let viewport = Viewport::covering(&Rectangle { x: -1024, y: -1024, width: 2048, height: 2048 }, 256.0, false);
+    let mut pixels = vec![0; (viewport.width * viewport.height) as usize];
+    render(&universe, &viewport, &colors_for_rule(None), &mut pixels);
+    let live: Vec<(usize, usize)> = (0..pixels.len()).filter(|&pixel| pixels[pixel] == LIVE_COLOR).map(|pixel| (pixel % 8, pixel / 8)).collect();
+    assert_eq!(viewport.width, 8);
+    assert_eq!(live, [(5, 4), (3, 7)]);
+  }
+}
@@ -0,0 +1,187 @@
+use std::time::Instant;
+use crate::conway;
+use crate::conway::{BlockRule, CellBlock, BOARD_HEIGHT_CELLS, BOARD_WIDTH_CELLS, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK};
+
+// The dense board laid out in rows of cells rather than blocks, with each word holding 64 cells side by side and the
+// leftmost cell in the lowest bit. The board is as many cells across as the block layout, which leaves the last word of
+// each row partly past the right edge of the board.
+pub const WORD_CELLS: usize = u64::BITS as usize;
+pub const BOARD_WIDTH_WORDS: usize = (BOARD_WIDTH_CELLS as usize).div_ceil(WORD_CELLS);
+pub const BOARD_TOTAL_WORDS: usize = BOARD_WIDTH_WORDS * BOARD_HEIGHT_CELLS as usize;
+
+pub type Board = [u64; BOARD_TOTAL_WORDS];
+
+const BLOCKS_PER_WORD: usize = WORD_CELLS / CELL_BLOCK_WIDTH as usize;
+const BLOCK_ROW_MASK: u64 = (1 << CELL_BLOCK_WIDTH) - 1;
+
+// Steps every cell of a board that is width_cells across, a row of blocks at a time so that rules that only step
+// blocks can be gathered out of the rows and scattered back into them. The rows of blocks are split evenly between
+// threads.
+pub fn compute_next_board_state<R: BlockRule + Sync + ?Sized>(source: &[u64], destination: &mut [u64], width_cells: usize, rule: &R) {
+  assert_eq!(source.len(), destination.len());
+  let width_words = width_cells.div_ceil(WORD_CELLS);
+  let width_blocks = width_cells.div_ceil(CELL_BLOCK_WIDTH as usize);
+  let block_row_words = width_words * CELL_BLOCK_HEIGHT as usize;
+  let height_cells = source.len() / width_words;
+  let height_blocks = source.len() / block_row_words;
+  let block_rows_per_thread = height_blocks.div_ceil(crate::num_threads());
+  // The cells past the right edge of the board in its last word, which have to stay dead.
+  let last_word_mask = match width_cells % WORD_CELLS {
+    0 => u64::MAX,
+    cells => (1 << cells) - 1,
+  };
+  // Stands in for the rows past the top and bottom edges.
+  let empty_row = &vec![0; width_words];
+  let row = |y: Option<usize>| y.filter(|&y| y < height_cells).map_or(&empty_row[..], |y| &source[y * width_words..(y + 1) * width_words]);
+
+  std::thread::scope(|scope| {
+    let mut threads = Vec::<std::thread::ScopedJoinHandle<()>>::new();
+    for (chunk_index, chunk) in destination.chunks_mut(block_rows_per_thread * block_row_words).enumerate() {
+      threads.push(scope.spawn(move || {
+        let start = Instant::now();
+        for (block_row_index, destination_rows) in chunk.chunks_mut(block_row_words).enumerate() {
+          let block_y = chunk_index * block_rows_per_thread + block_row_index;
+          let stepped_by_cell_row = destination_rows.chunks_mut(width_words).enumerate().all(|(row_index, destination_row)| {
+            let y = block_y * CELL_BLOCK_HEIGHT as usize + row_index;
+            let stepped = rule.new_values_for_cell_row([row(y.checked_sub(1)), row(Some(y)), row(Some(y + 1))], destination_row);
+            *destination_row.last_mut().unwrap() &= last_word_mask;
+            stepped
+          });
+          if !stepped_by_cell_row {
+            let rows = [block_y.checked_sub(1), Some(block_y), Some(block_y + 1)]
+                .map(|block_y| gather_block_row(source, width_words, width_blocks, block_y.filter(|&block_y| block_y < height_blocks)));
+            let mut blocks = vec![EMPTY_BLOCK; width_blocks];
+            rule.new_values_for_row([&rows[0], &rows[1], &rows[2]], &mut blocks);
+            scatter_block_row(&blocks, destination_rows, width_words);
+          }
+        }
+        crate::record_busy_time(chunk_index, start.elapsed());
+      }));
+    }
+  });
+}
+
+// The block at the given block coordinates, out of the rows of cells it spans.
+pub fn gather_block(board: &[u64], width_words: usize, block_x: usize, block_y: usize) -> CellBlock {
+  let (word, shift) = (block_x / BLOCKS_PER_WORD, block_x % BLOCKS_PER_WORD * CELL_BLOCK_WIDTH as usize);
+  (0..CELL_BLOCK_HEIGHT as usize).fold(EMPTY_BLOCK, |block, row| {
+    let cells = (board[(block_y * CELL_BLOCK_HEIGHT as usize + row) * width_words + word] >> shift) & BLOCK_ROW_MASK;
+    block | (CellBlock::from(cells) << (row * CELL_BLOCK_WIDTH as usize))
+  })
+}
+
+pub fn xor_block(board: &mut [u64], width_words: usize, block_x: usize, block_y: usize, cells: CellBlock) {
+  let (word, shift) = (block_x / BLOCKS_PER_WORD, block_x % BLOCKS_PER_WORD * CELL_BLOCK_WIDTH as usize);
+  for row in 0..CELL_BLOCK_HEIGHT as usize {
+    let row_cells = conway::first_cells(cells >> (row * CELL_BLOCK_WIDTH as usize)) & BLOCK_ROW_MASK;
+    board[(block_y * CELL_BLOCK_HEIGHT as usize + row) * width_words + word] ^= row_cells << shift;
+  }
+}
+
+// A whole row of blocks, or a row of empty blocks for rows past the edges.
+fn gather_block_row(board: &[u64], width_words: usize, width_blocks: usize, block_y: Option<usize>) -> Vec<CellBlock> {
+  match block_y {
+    Some(block_y) => (0..width_blocks).map(|block_x| gather_block(board, width_words, block_x, block_y)).collect(),
+    None => vec![EMPTY_BLOCK; width_blocks],
+  }
+}
+
+fn scatter_block_row(blocks: &[CellBlock], rows: &mut [u64], width_words: usize) {
+  rows.fill(0);
+  for (block_x, &block) in blocks.iter().enumerate() {
+    xor_block(rows, width_words, block_x, 0, block);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::conway;
+  use crate::conway::{BlockNeighborhood, Conway, EMPTY_BLOCK, FIRST_CELL};
+  use crate::random::Random;
+
+  // Wide enough that the last word of each row is only partly on the board.
+  const WIDTH_BLOCKS: usize = 19;
+  const HEIGHT_BLOCKS: usize = 5;
+  const WIDTH: usize = WIDTH_BLOCKS * CELL_BLOCK_WIDTH as usize;
+  const HEIGHT: usize = HEIGHT_BLOCKS * CELL_BLOCK_HEIGHT as usize;
+  const WIDTH_WORDS: usize = WIDTH.div_ceil(WORD_CELLS);
+
+  // Steps Conway's rule through the tables a block at a time, which has the rows gathered into blocks and scattered back.
+  struct Tables;
+
+  impl BlockRule for Tables {
+    fn new_value_for_neighborhood(&self, neighborhood: &BlockNeighborhood) -> CellBlock {
+      conway::new_value_for_neighborhood(neighborhood, &Conway)
+    }
+  }
+
+  fn soup(seed: u64) -> Vec<bool> {
+    let mut random = Random::seeded(seed);
+    (0..WIDTH * HEIGHT).map(|_| random.fraction() < 0.35).collect()
+  }
+
+  fn to_blocks(cells: &[bool]) -> Vec<CellBlock> {
+    let (block_width, block_height) = (CELL_BLOCK_WIDTH as usize, CELL_BLOCK_HEIGHT as usize);
+    let mut blocks = vec![EMPTY_BLOCK; WIDTH_BLOCKS * HEIGHT_BLOCKS];
+    for (x, y) in (0..cells.len()).filter(|&index| cells[index]).map(|index| (index % WIDTH, index / WIDTH)) {
+      blocks[y / block_height * WIDTH_BLOCKS + x / block_width] |= FIRST_CELL << (y % block_height * block_width + x % block_width);
+    }
+    blocks
+  }
+
+  fn to_rows(cells: &[bool]) -> Vec<u64> {
+    let mut rows = vec![0; WIDTH_WORDS * HEIGHT];
+    for (x, y) in (0..cells.len()).filter(|&index| cells[index]).map(|index| (index % WIDTH, index / WIDTH)) {
+      rows[y * WIDTH_WORDS + x / WORD_CELLS] |= 1 << (x % WORD_CELLS);
+    }
+    rows
+  }
+
+  #[test]
+  fn blocks_gather_and_scatter_like_the_block_layout() {
+    let cells = soup(1);
+    let (blocks, rows) = (to_blocks(&cells), to_rows(&cells));
+    let mut scattered = vec![0; rows.len()];
+    for block_y in 0..HEIGHT_BLOCKS {
+      for block_x in 0..WIDTH_BLOCKS {
+        let block = blocks[block_y * WIDTH_BLOCKS + block_x];
+        assert_eq!(gather_block(&rows, WIDTH_WORDS, block_x, block_y), block, "block ({}, {})", block_x, block_y);
+        xor_block(&mut scattered, WIDTH_WORDS, block_x, block_y, block);
+      }
+    }
+    assert_eq!(scattered, rows);
+
+    // Scattering a block twice takes it back out again without touching its neighbors.
+    xor_block(&mut scattered, WIDTH_WORDS, WIDTH_BLOCKS - 1, 2, blocks[2 * WIDTH_BLOCKS + WIDTH_BLOCKS - 1]);
+    assert_eq!(gather_block(&scattered, WIDTH_WORDS, WIDTH_BLOCKS - 1, 2), 0);
+    assert_eq!(gather_block(&scattered, WIDTH_WORDS, WIDTH_BLOCKS - 2, 2), blocks[2 * WIDTH_BLOCKS + WIDTH_BLOCKS - 2]);
+  }
+
+  // Conway's rule steps whole rows of cells at once, while the tables go through the gathered blocks. Either way the
+  // board has to end up as the block layout does, with nothing left past the right edge.
+  #[test]
+  fn steps_like_the_block_layout() {
+    let cells = soup(2);
+    let mut blocks = to_blocks(&cells);
+    let mut next_blocks = vec![EMPTY_BLOCK; blocks.len()];
+    let (mut by_cell_row, mut by_block) = (to_rows(&cells), to_rows(&cells));
+    let mut next_rows = vec![0; by_cell_row.len()];
+    for generation in 1..=20 {
+      conway::compute_next_board_state::<WIDTH_BLOCKS, _>(&blocks, &mut next_blocks, &Conway);
+      std::mem::swap(&mut blocks, &mut next_blocks);
+      compute_next_board_state(&by_cell_row, &mut next_rows, WIDTH, &Conway);
+      std::mem::swap(&mut by_cell_row, &mut next_rows);
+      compute_next_board_state(&by_block, &mut next_rows, WIDTH, &Tables);
+      std::mem::swap(&mut by_block, &mut next_rows);
+
+      let gathered = |rows: &[u64]| (0..blocks.len())
+          .map(|index| gather_block(rows, WIDTH_WORDS, index % WIDTH_BLOCKS, index / WIDTH_BLOCKS))
+          .collect::<Vec<_>>();
+      assert_eq!(gathered(&by_cell_row), blocks, "generation {} by cell row", generation);
+      assert_eq!(gathered(&by_block), blocks, "generation {} by block", generation);
+      let past_the_edge = !0 << (WIDTH % WORD_CELLS);
+      assert!(by_cell_row.iter().chain(&by_block).skip(WIDTH_WORDS - 1).step_by(WIDTH_WORDS).all(|word| word & past_the_edge == 0));
+    }
+  }
+}
@@ -0,0 +1,428 @@
+use crate::conway;
+use crate::conway::{CellRule, Neighborhood};
+use crate::larger_than_life;
+use crate::larger_than_life::LargerThanLifeRule;
+use crate::margolus;
+use crate::margolus::MargolusRule;
+use crate::rule_table;
+use crate::rule_table::RuleTable;
+
+// Every kind of rule the engine can run. Only one rule is alive at a time, so the size of the table is not a concern.
+#[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum Rule {
+  Table(TableRule),
+  LargerThanLife(LargerThanLifeRule),
+  MultiState(RuleTable),
+  Margolus(MargolusRule),
+}
+
+impl Rule {
+  pub fn states(&self) -> u8 {
+    match self {
+      Rule::Table(rule) => rule.states,
+      Rule::LargerThanLife(rule) => rule.states,
+      Rule::MultiState(rule) => rule.states,
+      Rule::Margolus(_) => 2,
+    }
+  }
+
+  pub fn neighborhood(&self) -> Neighborhood {
+    match self {
+      Rule::Table(rule) => rule.neighborhood,
+      Rule::LargerThanLife(rule) => rule.neighborhood,
+      Rule::MultiState(rule) => rule.neighborhood,
+      Rule::Margolus(_) => Neighborhood::Moore,
+    }
+  }
+}
+
+// A rule compiled into a transition table. Outer-totalistic rules only depend on how many neighbors are alive,
+// isotropic non-totalistic rules also depend on their arrangement, and MAP rules can map every arrangement arbitrarily.
+#[derive(Clone)]
+pub struct TableRule {
+  // Indexed by a cell's current state in bit 8 and its neighbor mask in bits 0-7.
+  pub table: [u8; 512],
+  // Two for Life-like rules. Generations rules have more: live cells that fail to survive pass through the refractory
+  // states 2 to states-1 before becoming dead, and neither count as neighbors nor can be born into meanwhile.
+  pub states: u8,
+  // The table ignores the bits of the neighbor mask outside of the neighborhood.
+  pub neighborhood: Neighborhood,
+}
+
+impl TableRule {
+  pub fn from_birth_and_survival(birth: &[u8; 256], survival: &[u8; 256], states: u8, neighborhood: Neighborhood) -> TableRule {
+    let mut table = [0u8; 512];
+    table[..256].copy_from_slice(birth);
+    table[256..].copy_from_slice(survival);
+    TableRule { table, states, neighborhood }
+  }
+}
+
+impl CellRule for TableRule {
+  #[inline(always)]
+  fn new_value_for_cell(&self, alive: bool, neighbor_mask: u8) -> u64 {
+    self.table[((alive as usize) << 8) | neighbor_mask as usize] as u64
+  }
+}
+
+// Loads a rule from a Golly .rule file if given a path to one, or parses it otherwise.
+pub fn load_rule(rule: &str) -> Result<Rule, String> {
+  if !rule_table::is_rule_file(rule) {
+    return parse_rule(rule);
+  }
+  let text = std::fs::read_to_string(rule.trim()).map_err(|error| format!("Could not read \"{}\": {}", rule, error))?;
+  rule_table::parse_rule_file(&text).map(Rule::MultiState)
+}
+
+// The name a rule is written into patterns with, given the text it was loaded from. Rule tables go by the name inside
+// the file rather than by its path.
+pub fn rule_name(rule: &Rule, source: &str) -> String {
+  match rule {
+    Rule::MultiState(rule) => rule.name.clone(),
+    _ => source.to_string(),
+  }
+}
+
+// Parses Larger than Life rules in Golly's notation, Margolus rules in MCell's notation, or any rule that compiles into
+// a transition table.
+pub fn parse_rule(rule: &str) -> Result<Rule, String> {
+  if larger_than_life::is_larger_than_life_rule(rule) {
+    return larger_than_life::parse_larger_than_life_rule(rule).map(Rule::LargerThanLife);
+  }
+  if margolus::is_margolus_rule(rule) {
+    return margolus::parse_margolus_rule(rule).map(Rule::Margolus);
+  }
+  parse_table_rule(rule).map(Rule::Table)
+}
+
+// Parses Life-like rules in B/S notation ("B3/S23") or Golly's S/B notation ("23/3"), and Generations rules in
+// B/S/C notation ("B2/S/C3") or S/B/C notation ("/2/3", "345/2/4"). Neighbor counts may be followed by Hensel letters
+// to pick out specific configurations ("B3-cnqy/S23-a", "B3/S2-i34q"). A trailing H selects the hexagonal neighborhood
+// ("B2/S34H") and a trailing V the von Neumann neighborhood ("B1/S1V"), in which case Hensel letters are not allowed.
+// Golly's MAP rules are also accepted, optionally followed by a number of states; Life is
+// "MAPARYXfhZofugWaH7oaIDogBZofuhogOiAaIDogIAAgAAWaH7oaIDogGiA6ICAAIAAaIDogIAAgACAAIAAAAAAAA".
+pub fn parse_table_rule(rule: &str) -> Result<TableRule, String> {
+  if rule.trim().starts_with("MAP") {
+    return parse_map_rule(rule);
+  }
+
+  // Neither H nor V is a Hensel letter, so a trailing one can only be a neighborhood suffix.
+  let (components, neighborhood) = match rule.trim() {
+    components if components.ends_with(['H', 'h']) => (&components[..components.len() - 1], Neighborhood::Hexagonal),
+    components if components.ends_with(['V', 'v']) => (&components[..components.len() - 1], Neighborhood::VonNeumann),
+    components => (components, Neighborhood::Moore),
+  };
+
+  let parts: Vec<&str> = components.split('/').collect();
+  if parts.len() < 2 || parts.len() > 3 {
+    return Err(format!("Unrecognized rule \"{}\"", rule));
+  }
+
+  let mut birth: Option<[u8; 256]> = None;
+  let mut survival: Option<[u8; 256]> = None;
+  let mut states: Option<u8> = None;
+  let prefixed = parts.iter().any(|part| part.starts_with(|c: char| c.is_ascii_alphabetic()));
+  for (index, part) in parts.iter().enumerate() {
+    let (kind, digits) = if prefixed {
+      match part.chars().next().map(|c| c.to_ascii_uppercase()) {
+        Some(kind @ ('B' | 'S' | 'C' | 'G')) => (kind, &part[1..]),
+        _ => return Err(format!("Unrecognized rule component \"{}\" in \"{}\"", part, rule)),
+      }
+    } else {
+      (['S', 'B', 'C'][index], *part)
+    };
+
+    match kind {
+      'B' => birth = Some(parse_neighborhoods(digits, neighborhood, rule)?),
+      'S' => survival = Some(parse_neighborhoods(digits, neighborhood, rule)?),
+      _ => states = Some(parse_states(digits, rule)?),
+    }
+  }
+
+  let birth = birth.ok_or_else(|| format!("Rule \"{}\" has no birth component", rule))?;
+  let survival = survival.ok_or_else(|| format!("Rule \"{}\" has no survival component", rule))?;
+  if birth[0] == 1 {
+    return Err(format!("Rule \"{}\" has B0, which is not supported", rule));
+  }
+  Ok(TableRule::from_birth_and_survival(&birth, &survival, states.unwrap_or(2), neighborhood))
+}
+
+// MAP rules encode one transition per bit in base64, most significant bit first. Each transition is indexed by the
+// cells of the neighborhood read left-to-right and top-to-bottom, with the top-left cell as the most significant bit.
+// The Moore neighborhood takes 512 transitions, the hexagonal neighborhood 128 and the von Neumann neighborhood 32.
+fn parse_map_rule(rule: &str) -> Result<TableRule, String> {
+  // The cells of each neighborhood in MAP order, as bits of this engine's table index: neighbor mask bits 0-7, then
+  // the cell itself.
+  const MOORE_CELLS: &[u8] = &[0, 1, 2, 3, 8, 4, 5, 6, 7];
+  const HEXAGONAL_CELLS: &[u8] = &[0, 1, 3, 8, 4, 6, 7];
+  const VON_NEUMANN_CELLS: &[u8] = &[1, 3, 8, 4, 6];
+
+  // The map may be followed by padding and then a number of states. The states cannot simply be split off at a slash,
+  // because slashes are also base64 characters, so the neighborhood is told apart by how many base64 characters there
+  // are and the map is split off at its exact length.
+  let map = &rule.trim()["MAP".len()..];
+  let base64_length = map.find(|c: char| !(c.is_ascii_alphanumeric() || c == '+' || c == '/')).unwrap_or(map.len());
+  let (neighborhood, cells) = match base64_length {
+    86.. => (Neighborhood::Moore, MOORE_CELLS),
+    22.. => (Neighborhood::Hexagonal, HEXAGONAL_CELLS),
+    6.. => (Neighborhood::VonNeumann, VON_NEUMANN_CELLS),
+    _ => return Err(format!("MAP rule \"{}\" is too short to hold 32 transitions", rule)),
+  };
+  let (map, suffix) = map.split_at((1usize << cells.len()).div_ceil(6));
+  let states = match suffix.trim_start_matches('=') {
+    "" => 2,
+    suffix => match suffix.strip_prefix('/') {
+      Some(states) => parse_states(states, rule)?,
+      None => return Err(format!("Unrecognized suffix \"{}\" in MAP rule \"{}\"", suffix, rule)),
+    },
+  };
+
+  let bytes = decode_base64(map).ok_or_else(|| format!("Invalid base64 in MAP rule \"{}\"", rule))?;
+
+  let mut table = [0u8; 512];
+  for (index, value) in table.iter_mut().enumerate() {
+    let map_index: usize = cells.iter().fold(0, |map_index, &bit| (map_index << 1) | ((index >> bit) & 1));
+    *value = (bytes[map_index / 8] >> (7 - map_index % 8)) & 1;
+  }
+  if table[0] == 1 {
+    return Err(format!("MAP rule \"{}\" has B0, which is not supported", rule));
+  }
+  Ok(TableRule { table, states, neighborhood })
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+  let mut bytes = Vec::<u8>::with_capacity(text.len() * 3 / 4);
+  let mut buffer: u32 = 0;
+  let mut buffered_bits = 0;
+  for c in text.trim_end_matches('=').chars() {
+    let value = match c {
+      'A'..='Z' => c as u32 - 'A' as u32,
+      'a'..='z' => c as u32 - 'a' as u32 + 26,
+      '0'..='9' => c as u32 - '0' as u32 + 52,
+      '+' => 62,
+      '/' => 63,
+      _ => return None,
+    };
+    buffer = (buffer << 6) | value;
+    buffered_bits += 6;
+    if buffered_bits >= 8 {
+      buffered_bits -= 8;
+      bytes.push((buffer >> buffered_bits) as u8);
+    }
+  }
+  Some(bytes)
+}
+
+// Hensel's letters for each neighbor count up to 4, each paired with one neighbor mask of the configuration it names.
+// The other masks of a configuration are its rotations and reflections. Counts 5 to 7 reuse the letters of their
+// complements 3 to 1, and counts 0 and 8 have a single configuration with no letter.
+const HENSEL_CONFIGURATIONS: [&[(char, u8)]; 5] = [
+  &[],
+  &[('c', 0b00000001), ('e', 0b00000010)],
+  &[
+    ('c', 0b00000101), ('e', 0b00001010), ('a', 0b00000011), ('i', 0b00011000), ('k', 0b00010001), ('n', 0b00100100),
+  ],
+  &[
+    ('c', 0b00100101), ('e', 0b00011010), ('a', 0b00001011), ('i', 0b00000111), ('k', 0b00110010), ('n', 0b00001101),
+    ('j', 0b00001110), ('q', 0b00100110), ('r', 0b00011001), ('y', 0b00110001),
+  ],
+  &[
+    ('c', 0b10100101), ('e', 0b01011010), ('a', 0b00001111), ('i', 0b00011101), ('k', 0b00110011), ('n', 0b00100111),
+    ('j', 0b00111010), ('q', 0b00110110), ('r', 0b00011011), ('y', 0b00110101), ('t', 0b00111001), ('w', 0b00101110),
+    ('z', 0b00111100),
+  ],
+];
+
+// Where each bit of a neighbor mask moves to when the neighborhood is rotated 90 degrees clockwise, or reflected
+// left-to-right.
+const ROTATED_NEIGHBOR_BITS: [u8; 8] = [2, 4, 7, 1, 6, 0, 3, 5];
+const REFLECTED_NEIGHBOR_BITS: [u8; 8] = [2, 1, 0, 4, 3, 7, 6, 5];
+
+fn permute_neighbor_mask(neighbor_mask: u8, permutation: &[u8; 8]) -> u8 {
+  (0..8).fold(0, |permuted, bit| permuted | (((neighbor_mask >> bit) & 1) << permutation[bit as usize]))
+}
+
+fn symmetries_of_neighbor_mask(neighbor_mask: u8) -> [u8; 8] {
+  let mut symmetries = [0u8; 8];
+  let mut mask = neighbor_mask;
+  for symmetry in symmetries.chunks_mut(2) {
+    symmetry[0] = mask;
+    symmetry[1] = permute_neighbor_mask(mask, &REFLECTED_NEIGHBOR_BITS);
+    mask = permute_neighbor_mask(mask, &ROTATED_NEIGHBOR_BITS);
+  }
+  symmetries
+}
+
+fn hensel_configuration(count: u8, letter: char) -> Option<u8> {
+  let (letters_count, complement) = if count > 4 { (8 - count, true) } else { (count, false) };
+  HENSEL_CONFIGURATIONS.get(letters_count as usize)?
+      .iter()
+      .find(|(configuration_letter, _)| *configuration_letter == letter)
+      .map(|&(_, neighbor_mask)| if complement { !neighbor_mask } else { neighbor_mask })
+}
+
+// Parses neighbor counts, each optionally followed by Hensel letters to include only those configurations, or by a
+// minus sign and letters to include every configuration except those, into a table of the matching neighbor masks.
+// Only the neighbors inside the neighborhood are counted.
+fn parse_neighborhoods(spec: &str, neighborhood: Neighborhood, rule: &str) -> Result<[u8; 256], String> {
+  let mut counts = [0u8; 256];
+  let mut chars = spec.chars().peekable();
+  while let Some(c) = chars.next() {
+    let count = match c.to_digit(10) {
+      Some(count) if count <= neighborhood.neighbor_count() as u32 => count as u8,
+      _ => return Err(format!("Invalid neighbor count '{}' in rule \"{}\"", c, rule)),
+    };
+
+    let excluding = chars.next_if_eq(&'-').is_some();
+    let mut letters = Vec::<char>::new();
+    while let Some(letter) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+      letters.push(letter.to_ascii_lowercase());
+    }
+    if excluding && letters.is_empty() {
+      return Err(format!("Expected Hensel letters after '{}-' in rule \"{}\"", count, rule));
+    }
+    if !letters.is_empty() && neighborhood != Neighborhood::Moore {
+      return Err(format!("Hensel letters are only supported in the Moore neighborhood, in rule \"{}\"", rule));
+    }
+
+    let mut configurations = [0u8; 256];
+    for letter in &letters {
+      let neighbor_mask = hensel_configuration(count, *letter)
+          .ok_or_else(|| format!("Invalid Hensel letter '{}{}' in rule \"{}\"", count, letter, rule))?;
+      for symmetry in symmetries_of_neighbor_mask(neighbor_mask) {
+        configurations[symmetry as usize] = 1;
+      }
+    }
+
+    let with_count = conway::neighbor_count_table(&[count]);
+    for neighbor_mask in 0..256 {
+      let included = letters.is_empty() || (configurations[neighbor_mask] == 1) != excluding;
+      if with_count[neighbor_mask] == 1 && included {
+        counts[neighbor_mask] = 1;
+      }
+    }
+  }
+
+  let mut table = [0u8; 256];
+  for (neighbor_mask, value) in table.iter_mut().enumerate() {
+    *value = counts[neighbor_mask & neighborhood.neighbor_mask() as usize];
+  }
+  Ok(table)
+}
+
+fn parse_states(digits: &str, rule: &str) -> Result<u8, String> {
+  match digits.parse::<u8>() {
+    Ok(states) if states >= 2 => Ok(states),
+    _ => Err(format!("Invalid number of states \"{}\" in rule \"{}\"", digits, rule)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::conway::Conway;
+
+  fn birth(rule: &str) -> [u8; 256] {
+    parse_table_rule(rule).unwrap().table[..256].try_into().unwrap()
+  }
+
+  #[test]
+  fn life_matches_conway() {
+    let life = parse_table_rule("B3/S23").unwrap();
+    for (index, &value) in life.table.iter().enumerate() {
+      assert_eq!(value as u64, Conway.new_value_for_cell(index >= 256, index as u8), "index {}", index);
+    }
+    assert_eq!(parse_table_rule("23/3").unwrap().table, life.table);
+  }
+
+  // Every arrangement of a number of live neighbors is named by exactly one of the letters for that number, so the
+  // letters split the arrangements between them with none left over.
+  #[test]
+  fn hensel_letters_partition_each_count() {
+    const LETTERS: [&str; 8] = ["ce", "aceikn", "aceijknqry", "aceijknqrtwyz", "aceijknqry", "aceikn", "ce", ""];
+    for (count, letters) in (1..).zip(LETTERS) {
+      let mut configurations = [0u8; 256];
+      for letter in letters.chars() {
+        for (neighbor_mask, value) in birth(&format!("B{}{}/S", count, letter)).iter().enumerate() {
+          configurations[neighbor_mask] += value;
+        }
+      }
+      if !letters.is_empty() {
+        assert_eq!(configurations, birth(&format!("B{}/S", count)), "count {}", count);
+      }
+    }
+    assert!(parse_table_rule("B3t/S").is_err());
+  }
+
+  #[test]
+  fn hensel_minus_excludes_letters() {
+    let rule = parse_table_rule("B3-cnqy/S23-a").unwrap();
+    let (three, excluded) = (birth("B3/S"), birth("B3cnqy/S"));
+    let (two, excluded_survival) = (birth("B2/S"), birth("B3a/S"));
+    for neighbor_mask in 0..256 {
+      assert_eq!(rule.table[neighbor_mask], three[neighbor_mask] & !excluded[neighbor_mask]);
+      assert_eq!(rule.table[256 + neighbor_mask], two[neighbor_mask] | (three[neighbor_mask] & !excluded_survival[neighbor_mask]));
+    }
+  }
+
+  const LIFE_MAP: &str = "MAPARYXfhZofugWaH7oaIDogBZofuhogOiAaIDogIAAgAAWaH7oaIDogGiA6ICAAIAAaIDogIAAgACAAIAAAAAAAA";
+
+  #[test]
+  fn life_map_matches_life() {
+    let map = parse_table_rule(LIFE_MAP).unwrap();
+    assert_eq!(map.table, parse_table_rule("B3/S23").unwrap().table);
+    assert_eq!((map.states, map.neighborhood), (2, Neighborhood::Moore));
+    let padded = parse_table_rule(&format!("{}==/3", LIFE_MAP)).unwrap();
+    assert_eq!((padded.table, padded.states), (map.table, 3));
+  }
+
+  #[test]
+  fn decodes_base64() {
+    assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+    assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+    assert_eq!(decode_base64("+/+/").unwrap(), [0xfb, 0xff, 0xbf]);
+    assert_eq!(decode_base64("TW-u"), None);
+  }
+
+  // Writes the transitions of a table out as a MAP rule over the given cells, in the order MAP rules list them.
+  fn map_rule(rule: &TableRule, cells: &[u8]) -> String {
+    let transitions = 1usize << cells.len();
+    let mut bits = vec![0u8; transitions.div_ceil(6) * 6];
+    for (map_index, bit) in bits.iter_mut().enumerate().take(transitions) {
+      let index = cells.iter().enumerate().fold(0, |index, (position, &cell)| index | ((map_index >> (cells.len() - 1 - position)) & 1) << cell);
+      *bit = rule.table[index];
+    }
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let digits = bits.chunks(6).map(|digit| ALPHABET[digit.iter().fold(0, |value, &bit| (value << 1) | bit as usize)] as char);
+    format!("MAP{}", digits.collect::<String>())
+  }
+
+  #[test]
+  fn map_rules_round_trip_every_neighborhood() {
+    for (rule, cells) in [("B3/S23", &[0, 1, 2, 3, 8, 4, 5, 6, 7][..]), ("B2/S34H", &[0, 1, 3, 8, 4, 6, 7]), ("B1/S1V", &[1, 3, 8, 4, 6])] {
+      let rule = parse_table_rule(rule).unwrap();
+      let map = parse_table_rule(&map_rule(&rule, cells)).unwrap();
+      assert_eq!(map.table, rule.table);
+      assert_eq!(map.neighborhood, rule.neighborhood);
+    }
+    assert_eq!(map_rule(&parse_table_rule("B3/S23").unwrap(), &[0, 1, 2, 3, 8, 4, 5, 6, 7]), LIFE_MAP);
+  }
+
+  // Smaller neighborhoods count only their own neighbors, so setting the bits outside of them changes nothing.
+  #[test]
+  fn smaller_neighborhoods_ignore_other_neighbors() {
+    for (rule, neighborhood) in [("B2/S34H", Neighborhood::Hexagonal), ("B1/S1V", Neighborhood::VonNeumann)] {
+      let rule = parse_table_rule(rule).unwrap();
+      assert_eq!(rule.neighborhood, neighborhood);
+      for index in 0..512 {
+        assert_eq!(rule.table[index], rule.table[index | !neighborhood.neighbor_mask() as usize], "index {}", index);
+      }
+    }
+    let hexagonal = parse_table_rule("B2/S34H").unwrap();
+    assert_eq!(hexagonal.table[0b00000011], 1);
+    assert_eq!(hexagonal.table[0b00000101], 0);
+    assert_eq!(hexagonal.table[256 + 0b01011000], 1);
+    assert!(parse_table_rule("B2a/SH").is_err());
+  }
+}
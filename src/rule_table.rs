@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use crate::conway::Neighborhood;
+
+// The most states a rule table may have, so that every state fits in a byte.
+pub const MAX_STATES: u16 = 255;
+
+// Rules with more combinations of states than this are looked up in a hash map of their transitions instead of being
+// compiled into a table indexed by every combination.
+const MAX_DENSE_TRANSITIONS: usize = 1 << 22;
+
+// Offsets of the neighbors of a cell in the order Golly lists them in a transition, clockwise starting from the top.
+const MOORE_NEIGHBORS: &[(i64, i64)] = &[(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+const VON_NEUMANN_NEIGHBORS: &[(i64, i64)] = &[(0, -1), (1, 0), (0, 1), (-1, 0)];
+const HEXAGONAL_NEIGHBORS: &[(i64, i64)] = &[(0, -1), (1, 0), (1, 1), (0, 1), (-1, 0), (-1, -1)];
+
+// A multi-state rule loaded from a Golly .rule file, such as WireWorld, Langton's Loops or Codd's CA. Each transition
+// maps the state of a cell and the states of its neighbors to the cell's next state. Neighborhoods that no transition
+// matches leave the cell unchanged.
+#[derive(Clone)]
+pub struct RuleTable {
+  pub name: String,
+  pub states: u8,
+  pub neighborhood: Neighborhood,
+  // Colors from the @COLORS section as (state, 0xRRGGBB).
+  pub colors: Vec<(u8, u32)>,
+  transitions: Transitions,
+}
+
+#[derive(Clone)]
+enum Transitions {
+  // Indexed by the cell and its neighbors in Golly's order, as the digits of a number in base states.
+  Dense(Vec<u8>),
+  // Keyed by the cell and its neighbors, one byte each. Neighbors are sorted first for rules where every permutation
+  // of them is equivalent.
+  Sparse(HashMap<u128, u8>, bool),
+}
+
+// The arrangements of neighbors that a rule treats as equivalent. Rotations and reflections are expanded into every
+// arrangement of each transition, tried in order the same way Golly does, so that the first arrangement that matches
+// decides which values variables take. Every permutation of the neighbors is far too many to expand, so those are
+// matched by sorting the neighbors instead.
+enum Symmetries {
+  Permutations(Vec<Vec<usize>>),
+  Permute,
+}
+
+impl RuleTable {
+  // Offsets of the neighbors of a cell, in the order new_state expects them.
+  pub fn neighbor_offsets(&self) -> &'static [(i64, i64)] {
+    neighbor_offsets(self.neighborhood)
+  }
+
+  // Returns the next state of a cell, given its current state followed by the states of its neighbors.
+  #[inline(always)]
+  pub fn new_state(&self, cells: &[u8]) -> u8 {
+    match &self.transitions {
+      Transitions::Dense(table) => {
+        let index = cells.iter().fold(0usize, |index, &state| index * self.states as usize + state as usize);
+        table[index]
+      }
+      Transitions::Sparse(transitions, sorted) => {
+        let mut neighbors = [0u8; 8];
+        let neighbors = &mut neighbors[..cells.len() - 1];
+        neighbors.copy_from_slice(&cells[1..]);
+        if *sorted {
+          neighbors.sort_unstable();
+        }
+        *transitions.get(&transition_key(cells[0], neighbors)).unwrap_or(&cells[0])
+      }
+    }
+  }
+}
+
+fn neighbor_offsets(neighborhood: Neighborhood) -> &'static [(i64, i64)] {
+  match neighborhood {
+    Neighborhood::Moore => MOORE_NEIGHBORS,
+    Neighborhood::VonNeumann => VON_NEUMANN_NEIGHBORS,
+    Neighborhood::Hexagonal => HEXAGONAL_NEIGHBORS,
+  }
+}
+
+fn transition_key(cell: u8, neighbors: &[u8]) -> u128 {
+  neighbors.iter().fold(cell as u128, |key, &neighbor| (key << 8) | neighbor as u128)
+}
+
+pub fn is_rule_file(rule: &str) -> bool {
+  rule.trim().to_ascii_lowercase().ends_with(".rule")
+}
+
+// Parses a Golly .rule file. Only the @TABLE and @COLORS sections are used; @TREE rules are not supported and other
+// sections such as @ICONS are ignored.
+pub fn parse_rule_file(text: &str) -> Result<RuleTable, String> {
+  let mut name = String::new();
+  let mut table: Option<Vec<&str>> = None;
+  let mut colors: Vec<&str> = Vec::new();
+  let mut section = "";
+  for line in text.lines() {
+    let line = line.split('#').next().unwrap().trim();
+    if let Some(header) = line.strip_prefix('@') {
+      let (header, argument) = header.split_once(char::is_whitespace).unwrap_or((header, ""));
+      section = header;
+      match header {
+        "RULE" => name = argument.trim().to_string(),
+        "TABLE" => table = Some(Vec::new()),
+        "TREE" => return Err(format!("Rule \"{}\" uses @TREE, which is not supported", name)),
+        _ => {}
+      }
+      continue;
+    }
+    if line.is_empty() {
+      continue;
+    }
+    match section {
+      "TABLE" => table.as_mut().unwrap().push(line),
+      "COLORS" => colors.push(line),
+      _ => {}
+    }
+  }
+
+  let table = table.ok_or_else(|| format!("Rule \"{}\" has no @TABLE section", name))?;
+  let mut rule = parse_table(&name, &table)?;
+  rule.colors = parse_colors(&name, &colors, rule.states)?;
+  Ok(rule)
+}
+
+fn parse_table(name: &str, lines: &[&str]) -> Result<RuleTable, String> {
+  let mut states: Option<u8> = None;
+  let mut neighborhood: Option<Neighborhood> = None;
+  let mut symmetry = "none";
+  let mut variables = HashMap::<&str, Vec<u8>>::new();
+  let mut transitions = Vec::<&str>::new();
+
+  for &line in lines {
+    if let Some((key, value)) = line.split_once(':') {
+      let value = value.trim();
+      match key.trim() {
+        "n_states" => states = match value.parse::<u16>() {
+          Ok(states) if (2..=MAX_STATES).contains(&states) => Some(states as u8),
+          _ => return Err(format!("Invalid number of states \"{}\" in rule \"{}\"", value, name)),
+        },
+        "neighborhood" => neighborhood = match value.to_ascii_lowercase().as_str() {
+          "moore" => Some(Neighborhood::Moore),
+          "vonneumann" => Some(Neighborhood::VonNeumann),
+          "hexagonal" => Some(Neighborhood::Hexagonal),
+          _ => return Err(format!("Unsupported neighborhood \"{}\" in rule \"{}\"", value, name)),
+        },
+        "symmetries" => symmetry = value,
+        key => return Err(format!("Unrecognized setting \"{}\" in rule \"{}\"", key, name)),
+      }
+    } else if let Some(variable) = line.strip_prefix("var ") {
+      let (variable, values) = variable.split_once('=')
+          .ok_or_else(|| format!("Invalid variable \"{}\" in rule \"{}\"", line, name))?;
+      let values = values.trim().strip_prefix('{').and_then(|values| values.strip_suffix('}'))
+          .ok_or_else(|| format!("Invalid variable \"{}\" in rule \"{}\"", line, name))?;
+      // Variables may be defined in terms of earlier ones.
+      let mut expanded = Vec::<u8>::new();
+      for value in values.split(',').map(str::trim) {
+        match variables.get(value) {
+          Some(values) => expanded.extend(values),
+          None => expanded.push(parse_state(value, name)?),
+        }
+      }
+      variables.insert(variable.trim(), expanded);
+    } else {
+      transitions.push(line);
+    }
+  }
+
+  let states = states.ok_or_else(|| format!("Rule \"{}\" has no n_states", name))?;
+  let neighborhood = neighborhood.ok_or_else(|| format!("Rule \"{}\" has no neighborhood", name))?;
+  let neighbors = neighbor_offsets(neighborhood).len();
+  let symmetries = parse_symmetries(symmetry, neighbors, name)?;
+  let (permutations, sorted) = match symmetries {
+    Symmetries::Permutations(permutations) => (permutations, false),
+    Symmetries::Permute => (vec![(0..neighbors).collect()], true),
+  };
+
+  // Expand every transition into each combination of values of its variables. Earlier transitions take precedence.
+  let mut sparse = HashMap::<u128, u8>::new();
+  for transition in transitions {
+    let tokens: Vec<&str> = if transition.contains(',') {
+      transition.split(',').map(str::trim).collect()
+    } else if transition.len() == neighbors + 2 && transition.bytes().all(|byte| byte.is_ascii_digit()) {
+      (0..transition.len()).map(|index| &transition[index..index + 1]).collect()
+    } else {
+      transition.split_whitespace().collect()
+    };
+    if tokens.len() != neighbors + 2 {
+      return Err(format!("Transition \"{}\" in rule \"{}\" should have {} states", transition, name, neighbors + 2));
+    }
+
+    // Every occurrence of a variable within a transition is bound to the same value.
+    let mut bound: Vec<&str> = Vec::new();
+    for token in &tokens[..neighbors + 1] {
+      if variables.contains_key(token) && !bound.contains(token) {
+        bound.push(token);
+      }
+    }
+    let output = *tokens.last().unwrap();
+    if variables.contains_key(output) && !bound.contains(&output) {
+      return Err(format!("Transition \"{}\" in rule \"{}\" outputs an unbound variable", transition, name));
+    }
+
+    for permutation in &permutations {
+      let mut choices = vec![0usize; bound.len()];
+      loop {
+        let value_of = |token: &str| -> Result<u8, String> {
+          match bound.iter().position(|variable| *variable == token) {
+            Some(index) => Ok(variables[token][choices[index]]),
+            None => parse_state(token, name),
+          }
+        };
+        let cell = value_of(tokens[0])?;
+        let mut neighbor_states = [0u8; 8];
+        for (&neighbor, token) in permutation.iter().zip(&tokens[1..neighbors + 1]) {
+          neighbor_states[neighbor] = value_of(token)?;
+        }
+        let neighbor_states = &mut neighbor_states[..neighbors];
+        let output = value_of(output)?;
+        if [cell, output].iter().chain(neighbor_states.iter()).any(|&state| state >= states) {
+          return Err(format!("Transition \"{}\" in rule \"{}\" uses a state past {}", transition, name, states - 1));
+        }
+        if sorted {
+          neighbor_states.sort_unstable();
+        }
+        sparse.entry(transition_key(cell, neighbor_states)).or_insert(output);
+
+        // Advance to the next combination, like counting in a mixed radix.
+        let Some(index) = (0..bound.len()).rev().find(|&index| choices[index] + 1 < variables[bound[index]].len()) else {
+          break;
+        };
+        choices[index] += 1;
+        choices[index + 1..].fill(0);
+      }
+    }
+  }
+
+  if sparse.get(&0).is_some_and(|&state| state != 0) {
+    return Err(format!("Rule \"{}\" brings empty space to life, which is not supported", name));
+  }
+
+  let dense_transitions = (states as usize).checked_pow(neighbors as u32 + 1).filter(|&size| size <= MAX_DENSE_TRANSITIONS);
+  let transitions = match dense_transitions {
+    Some(size) => {
+      let mut table = vec![0u8; size];
+      let mut cells = [0u8; 9];
+      for (index, value) in table.iter_mut().enumerate() {
+        let mut digits = index;
+        for cell in cells[..neighbors + 1].iter_mut().rev() {
+          *cell = (digits % states as usize) as u8;
+          digits /= states as usize;
+        }
+        let (cell, neighbor_states) = cells[..neighbors + 1].split_at_mut(1);
+        if sorted {
+          neighbor_states.sort_unstable();
+        }
+        *value = *sparse.get(&transition_key(cell[0], neighbor_states)).unwrap_or(&cell[0]);
+      }
+      Transitions::Dense(table)
+    }
+    None => Transitions::Sparse(sparse, sorted),
+  };
+
+  Ok(RuleTable { name: name.to_string(), states, neighborhood, colors: Vec::new(), transitions })
+}
+
+// Golly lists neighbors clockwise, so rotations shift them around the list, and reflecting left-to-right reverses the
+// list about the top neighbor.
+fn parse_symmetries(symmetry: &str, neighbors: usize, name: &str) -> Result<Symmetries, String> {
+  let unsupported = || format!("Unsupported symmetries \"{}\" in rule \"{}\"", symmetry, name);
+  let rotation = |shift: usize| -> Vec<usize> { (0..neighbors).map(|index| (index + shift) % neighbors).collect() };
+  let reflection: Vec<usize> = (0..neighbors).map(|index| (neighbors - index) % neighbors).collect();
+
+  let (rotations, reflect) = match symmetry {
+    "permute" => return Ok(Symmetries::Permute),
+    "none" => (1, false),
+    "reflect_horizontal" => (1, true),
+    symmetry => {
+      let rotations = symmetry.strip_prefix("rotate").ok_or_else(unsupported)?;
+      let (rotations, reflect) = match rotations.strip_suffix("reflect") {
+        Some(rotations) => (rotations, true),
+        None => (rotations, false),
+      };
+      (rotations.parse::<usize>().map_err(|_| unsupported())?, reflect)
+    }
+  };
+  if rotations == 0 || !neighbors.is_multiple_of(rotations) {
+    return Err(unsupported());
+  }
+
+  let mut permutations = Vec::<Vec<usize>>::new();
+  for rotation_index in 0..rotations {
+    let rotated = rotation(rotation_index * neighbors / rotations);
+    let reflected = reflection.iter().map(|&index| rotated[index]).collect();
+    permutations.push(rotated);
+    if reflect {
+      permutations.push(reflected);
+    }
+  }
+  Ok(Symmetries::Permutations(permutations))
+}
+
+fn parse_state(token: &str, name: &str) -> Result<u8, String> {
+  token.parse::<u8>().map_err(|_| format!("Invalid state or unknown variable \"{}\" in rule \"{}\"", token, name))
+}
+
+// Each line is either "state red green blue", or "red green blue red green blue" for a gradient from state 1 to the
+// last state.
+fn parse_colors(name: &str, lines: &[&str], states: u8) -> Result<Vec<(u8, u32)>, String> {
+  let mut colors = Vec::<(u8, u32)>::new();
+  for line in lines {
+    let numbers: Vec<u32> = line.split_whitespace()
+        .map(|number| number.parse::<u32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid color \"{}\" in rule \"{}\"", line, name))?;
+    let rgb = |numbers: &[u32]| numbers.iter().fold(0, |rgb, &channel| (rgb << 8) | channel.min(255));
+    match numbers.as_slice() {
+      [state, channels @ ..] if channels.len() == 3 && *state < states as u32 => colors.push((*state as u8, rgb(channels))),
+      channels if channels.len() == 6 => {
+        for state in 1..states {
+          let fraction = if states > 2 { (state - 1) as u32 * 256 / (states - 2) as u32 } else { 0 };
+          let channel = |index: usize| (channels[index] * (256 - fraction) + channels[index + 3] * fraction) / 256;
+          colors.push((state, rgb(&[channel(0), channel(1), channel(2)])));
+        }
+      }
+      _ => return Err(format!("Invalid color \"{}\" in rule \"{}\"", line, name)),
+    }
+  }
+  Ok(colors)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // The cell itself, then its neighbors in Golly's order.
+  fn next(rule: &RuleTable, cell: u8, neighbors: &[u8]) -> u8 {
+    rule.new_state(&[&[cell], neighbors].concat())
+  }
+
+  #[test]
+  fn wireworld() {
+    let rule = parse_rule_file(include_str!("../rules/WireWorld.rule")).unwrap();
+    assert_eq!((rule.name.as_str(), rule.states, rule.neighborhood), ("WireWorld", 4, Neighborhood::Moore));
+    assert_eq!(rule.colors, [(0, 0x303030), (1, 0x0080ff), (2, 0xffffff), (3, 0xff8000)]);
+    assert_eq!(next(&rule, 1, &[3, 3, 0, 0, 0, 0, 0, 0]), 2);
+    assert_eq!(next(&rule, 2, &[1, 0, 0, 0, 0, 0, 0, 3]), 3);
+    for heads in 0..=3 {
+      let mut neighbors = [3, 0, 2, 0, 0, 0, 0, 0];
+      neighbors[8 - heads..].fill(1);
+      assert_eq!(next(&rule, 3, &neighbors), if heads == 1 || heads == 2 { 1 } else { 3 }, "{} heads", heads);
+    }
+    assert_eq!(next(&rule, 0, &[1; 8]), 0);
+  }
+
+  #[test]
+  fn rotations_expand_transitions() {
+    let rule = parse_rule_file("@RULE Turn\n@TABLE\nn_states:3\nneighborhood:vonNeumann\nsymmetries:rotate4\n0,1,2,0,0,2\n").unwrap();
+    assert_eq!(next(&rule, 0, &[1, 2, 0, 0]), 2);
+    assert_eq!(next(&rule, 0, &[0, 1, 2, 0]), 2);
+    assert_eq!(next(&rule, 0, &[2, 0, 0, 1]), 2);
+    // Reflections are not rotations.
+    assert_eq!(next(&rule, 0, &[2, 1, 0, 0]), 0);
+  }
+
+  // Rules with too many states to tabulate every neighborhood keep their transitions in a map instead.
+  #[test]
+  fn sparse_transitions() {
+    let rule = parse_rule_file("@RULE Many\n@TABLE\nn_states:200\nneighborhood:Moore\nsymmetries:permute\nvar a={198,199}\n0,a,a,0,0,0,0,0,0,a\n").unwrap();
+    assert!(matches!(rule.transitions, Transitions::Sparse(..)));
+    assert_eq!(next(&rule, 0, &[0, 0, 199, 0, 0, 0, 199, 0]), 199);
+    assert_eq!(next(&rule, 0, &[198, 0, 0, 0, 0, 0, 198, 0]), 198);
+    assert_eq!(next(&rule, 0, &[198, 0, 0, 0, 0, 0, 199, 0]), 0);
+    assert_eq!(next(&rule, 7, &[198, 0, 0, 0, 0, 0, 198, 0]), 7);
+  }
+
+  #[test]
+  fn rejects_unsupported_rules() {
+    assert!(parse_rule_file("@RULE Tree\n@TREE\nnum_states=2\n").is_err());
+    assert!(parse_rule_file("@RULE Empty\n@COLORS\n0 0 0 0\n").is_err());
+    assert!(parse_rule_file("@RULE Birth\n@TABLE\nn_states:2\nneighborhood:Moore\n0,0,0,0,0,0,0,0,0,1\n").is_err());
+    assert!(parse_rule_file("@RULE Unbound\n@TABLE\nn_states:2\nneighborhood:vonNeumann\nvar a={0,1}\n1,0,0,0,0,a\n").is_err());
+  }
+}
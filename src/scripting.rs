@@ -0,0 +1,215 @@
+use std::cell::RefCell;
+use std::path::Path;
+use mlua::{Function, Lua, Table};
+use sdl3::keyboard::Keycode;
+use crate::history::History;
+use crate::pattern;
+use crate::pattern::Pattern;
+use crate::selection;
+use crate::selection::Rectangle;
+use crate::universe::Universe;
+
+// Where the script's chunk and the functions bound to keys are kept in the Lua registry, by the SDL name of the key.
+const MAIN: &str = "lifer.main";
+const BINDINGS: &str = "lifer.bindings";
+
+// What the functions scripts call can reach while a script is running.
+struct Context<'a> {
+  universe: &'a mut Universe,
+  // Edits and steps go through the history once the window is open, so that they can be undone.
+  history: Option<&'a mut History>,
+}
+
+// A Lua script automating the universe, much as Golly's scripts do. The script is run once the pattern given on the
+// command line has been loaded, before the universe is run, and can bind keys to functions that are called whenever
+// the key is pressed in the window. Everything it can do is in the lifer table:
+//
+//   lifer.get_cell(x, y)          the state of a cell
+//   lifer.set_cell(x, y, state)
+//   lifer.load(path, [x, y])      places a pattern file with its top-left cell at (x, y), or at the origin
+//   lifer.step([generations])
+//   lifer.generation()
+//   lifer.population()
+//   lifer.bounds()                the smallest rectangle holding every cell that is not in state 0, if there are any
+//   lifer.bind(key, function)     calls the function when the key is pressed, where keys are named as SDL names them
+//
+// Rectangles are tables of x, y, width and height. Patterns loaded in the window replace the cells underneath them as
+// pasting does, while those loaded before it opens are added to the board as --pattern is.
+pub struct Script {
+  lua: Lua,
+}
+
+impl Script {
+  // Compiles the script without running it yet.
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let source = std::fs::read_to_string(path).map_err(|error| format!("Could not read \"{}\": {}", path.display(), error))?;
+    let lua = Lua::new();
+    let setup = || -> mlua::Result<()> {
+      let main = lua.load(&source).set_name(format!("@{}", path.display())).into_function()?;
+      lua.set_named_registry_value(MAIN, main)?;
+      lua.set_named_registry_value(BINDINGS, lua.create_table()?)?;
+      let api = lua.create_table()?;
+      api.set("bind", lua.create_function(|lua, (key, function): (String, Function)| {
+        let keycode = Keycode::from_name(&key).ok_or_else(|| mlua::Error::runtime(format!("Unknown key \"{}\"", key)))?;
+        lua.named_registry_value::<Table>(BINDINGS)?.set(keycode.name(), function)
+      })?)?;
+      lua.globals().set("lifer", api)
+    };
+    setup().map_err(|error| error.to_string())?;
+    Ok(Script { lua })
+  }
+
+  pub fn run(&self, universe: &mut Universe, history: Option<&mut History>) -> Result<(), String> {
+    self.call(universe, history, |lua| lua.named_registry_value::<Function>(MAIN)?.call(()))
+  }
+
+  pub fn binds(&self, keycode: Keycode) -> bool {
+    self.lua.named_registry_value::<Table>(BINDINGS).and_then(|bindings| bindings.contains_key(keycode.name())).unwrap_or(false)
+  }
+
+  // Calls the function bound to the key.
+  pub fn press(&self, keycode: Keycode, universe: &mut Universe, history: Option<&mut History>) -> Result<(), String> {
+    self.call(universe, history, |lua| lua.named_registry_value::<Table>(BINDINGS)?.get::<_, Function>(keycode.name())?.call(()))
+  }
+
+  // Calls into the script with the functions of the lifer table reaching the universe, which they only can until the
+  // call returns. Cells set between steps and loads are a single edit of the history.
+  fn call<F: FnOnce(&Lua) -> mlua::Result<()>>(&self, universe: &mut Universe, history: Option<&mut History>, body: F) -> Result<(), String> {
+    let context = RefCell::new(Context { universe, history });
+    let result = self.lua.scope(|scope| {
+      let api: Table = self.lua.globals().get("lifer")?;
+      api.set("get_cell", scope.create_function(|_, (x, y): (i64, i64)| Ok(context.borrow().universe.get_cell(x, y)))?)?;
+      api.set("set_cell", scope.create_function(|_, (x, y, state): (i64, i64, u8)| {
+        let Context { universe, history } = &mut *context.borrow_mut();
+        if state >= universe.states() {
+          return Err(mlua::Error::runtime(format!("The rule has no state {}", state)));
+        }
+        match history {
+          Some(history) => history.edit_cell(universe, x, y, state),
+          None => universe.set_cell(x, y, state),
+        }
+        Ok(())
+      })?)?;
+      api.set("load", scope.create_function(|lua, (path, x, y): (String, Option<i64>, Option<i64>)| {
+        let cells = pattern::load_pattern_file(Path::new(&path)).map_err(mlua::Error::runtime)?;
+        let (x, y) = (x.unwrap_or(0), y.unwrap_or(0));
+        let Context { universe, history } = &mut *context.borrow_mut();
+        let placed = match history {
+          Some(history) => {
+            history.finish_edit(universe);
+            selection::paste(history, universe, &Pattern::from_cells(&cells, universe), x, y)
+          }
+          None => {
+            for &(cell_x, cell_y, state) in &cells.cells {
+              universe.set_cell(x + cell_x, y + cell_y, state);
+            }
+            Rectangle { x, y, width: cells.width, height: cells.height }
+          }
+        };
+        rectangle_table(lua, &placed)
+      })?)?;
+      api.set("step", scope.create_function(|_, generations: Option<u64>| {
+        let Context { universe, history } = &mut *context.borrow_mut();
+        for _ in 0..generations.unwrap_or(1) {
+          match history {
+            Some(history) => {
+              history.finish_edit(universe);
+              history.step(universe);
+            }
+            None => universe.step(),
+          }
+        }
+        Ok(())
+      })?)?;
+      api.set("generation", scope.create_function(|_, ()| Ok(context.borrow().universe.generation))?)?;
+      api.set("population", scope.create_function(|_, ()| Ok(context.borrow().universe.counted_population()))?)?;
+      api.set("bounds", scope.create_function(|lua, ()| {
+        selection::bounding_rectangle(context.borrow().universe).map(|bounds| rectangle_table(lua, &bounds)).transpose()
+      })?)?;
+      body(&self.lua)
+    });
+
+    let Context { universe, history } = context.into_inner();
+    if let Some(history) = history {
+      history.finish_edit(universe);
+    }
+    result.map_err(|error| error.to_string())
+  }
+}
+
+fn rectangle_table<'lua>(lua: &'lua Lua, rectangle: &Rectangle) -> mlua::Result<Table<'lua>> {
+  let table = lua.create_table()?;
+  table.set("x", rectangle.x)?;
+  table.set("y", rectangle.y)?;
+  table.set("width", rectangle.width)?;
+  table.set("height", rectangle.height)?;
+  Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::universe::Buffers;
+
+  fn script(name: &str, source: &str) -> Result<Script, String> {
+    let path = std::env::temp_dir().join(format!("lifer-{}-{}.lua", std::process::id(), name));
+    std::fs::write(&path, source).unwrap();
+    let script = Script::load(&path);
+    std::fs::remove_file(&path).ok();
+    script
+  }
+
+  fn universe() -> Universe {
+    Universe::new(Buffers::for_rule(None), None)
+  }
+
+  #[test]
+  fn scripts_edit_step_and_query_the_universe() {
+    let pattern = std::env::temp_dir().join(format!("lifer-{}-glider.rle", std::process::id()));
+    std::fs::write(&pattern, "x = 3, y = 3\nbo$2bo$3o!\n").unwrap();
+    let script = script("query", &format!(r#"
+      local placed = lifer.load({:?}, 10, 20)
+      assert(placed.x == 10 and placed.y == 20 and placed.width == 3 and placed.height == 3)
+      assert(lifer.get_cell(11, 20) == 1 and lifer.get_cell(10, 20) == 0)
+      lifer.set_cell(-5, -5, 1)
+      lifer.set_cell(-5, -5, 0)
+      lifer.step(4)
+      assert(lifer.generation() == 4 and lifer.population() == 5)
+      local bounds = lifer.bounds()
+      assert(bounds.x == 11 and bounds.y == 21 and bounds.width == 3 and bounds.height == 3)
+    "#, pattern.display().to_string())).unwrap();
+    let mut universe = universe();
+    let result = script.run(&mut universe, None);
+    std::fs::remove_file(&pattern).ok();
+    result.unwrap();
+    assert_eq!((universe.generation, universe.get_cell(13, 22), universe.get_cell(12, 22)), (4, 1, 0));
+  }
+
+  // Cells set in the window between steps are undone together, and every step on its own.
+  #[test]
+  fn edits_in_the_window_go_through_the_history() {
+    let script = script("history", "for x = 0, 2 do lifer.set_cell(x, 0, 1) end lifer.step() lifer.set_cell(5, 5, 1)").unwrap();
+    let mut universe = universe();
+    let mut history = History::new(&universe);
+    script.run(&mut universe, Some(&mut history)).unwrap();
+    assert_eq!((universe.generation, universe.counted_population()), (1, 4));
+
+    assert!(history.back(&mut universe));
+    assert_eq!((universe.get_cell(5, 5), universe.counted_population()), (0, 3));
+    assert!(history.back(&mut universe));
+    assert_eq!((universe.generation, universe.get_cell(0, 0)), (0, 1));
+    assert!(history.back(&mut universe));
+    assert_eq!(universe.counted_population(), 0);
+    assert!(!history.back(&mut universe));
+  }
+
+  #[test]
+  fn errors_name_the_script() {
+    assert!(script("syntax", "lifer.step(").is_err_and(|error| error.contains("lifer-") && error.contains("syntax")));
+    let script = script("state", "lifer.set_cell(0, 0, 1)\nlifer.set_cell(1, 0, 2)").unwrap();
+    let mut universe = universe();
+    assert!(script.run(&mut universe, None).is_err_and(|error| error.contains("no state 2")));
+    // Whatever the script did before failing stays done.
+    assert_eq!(universe.get_cell(0, 0), 1);
+  }
+}
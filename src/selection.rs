@@ -0,0 +1,141 @@
+use crate::conway::{CellBlock, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK, FIRST_ROW};
+use crate::history::History;
+use crate::pattern;
+use crate::pattern::Pattern;
+use crate::random::Random;
+use crate::universe::Universe;
+
+const WIDTH: i64 = CELL_BLOCK_WIDTH as i64;
+const HEIGHT: i64 = CELL_BLOCK_HEIGHT as i64;
+
+// A rectangle of cells, whose top-left cell is at (x, y).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rectangle {
+  pub x: i64,
+  pub y: i64,
+  pub width: i64,
+  pub height: i64,
+}
+
+impl Rectangle {
+  // The rectangle spanning two opposite corner cells, given in either order.
+  pub fn from_corners(corner: (i64, i64), opposite_corner: (i64, i64)) -> Self {
+    let (x, y) = (corner.0.min(opposite_corner.0), corner.1.min(opposite_corner.1));
+    Rectangle { x, y, width: (corner.0 - opposite_corner.0).abs() + 1, height: (corner.1 - opposite_corner.1).abs() + 1 }
+  }
+
+  pub fn contains(&self, x: i64, y: i64) -> bool {
+    (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+  }
+
+  // The coordinates of every block the rectangle overlaps.
+  pub fn blocks(&self) -> impl Iterator<Item = (i64, i64)> {
+    let columns = self.x.div_euclid(WIDTH)..=(self.x + self.width - 1).div_euclid(WIDTH);
+    (self.y.div_euclid(HEIGHT)..=(self.y + self.height - 1).div_euclid(HEIGHT))
+        .flat_map(move |block_y| columns.clone().map(move |block_x| (block_x, block_y)))
+  }
+
+  // The cells of the block at the given block coordinates that lie inside the rectangle.
+  pub fn block_mask(&self, block_x: i64, block_y: i64) -> CellBlock {
+    pattern::rectangle_mask(block_x * WIDTH - self.x, block_y * HEIGHT - self.y, self.width, self.height)
+  }
+}
+
+// The smallest rectangle holding every cell of the universe that is not in state 0, unless there are none.
+pub fn bounding_rectangle(universe: &Universe) -> Option<Rectangle> {
+  let mut bounds: Option<(i64, i64, i64, i64)> = None;
+  for (_, (block_x, block_y), cells) in universe.occupied_blocks() {
+    // Folding the rows of the block together leaves a row of its occupied columns.
+    let columns = (0..HEIGHT).fold(EMPTY_BLOCK, |columns, row| columns | ((cells >> (row * WIDTH)) & FIRST_ROW));
+    let (first_x, first_y) = (block_x * WIDTH + columns.trailing_zeros() as i64, block_y * HEIGHT + cells.trailing_zeros() as i64 / WIDTH);
+    let last_x = block_x * WIDTH + (CellBlock::BITS - 1 - columns.leading_zeros()) as i64;
+    let last_y = block_y * HEIGHT + (CellBlock::BITS - 1 - cells.leading_zeros()) as i64 / WIDTH;
+    let (min_x, min_y, max_x, max_y) = bounds.unwrap_or((first_x, first_y, last_x, last_y));
+    bounds = Some((min_x.min(first_x), min_y.min(first_y), max_x.max(last_x), max_y.max(last_y)));
+  }
+  bounds.map(|(min_x, min_y, max_x, max_y)| Rectangle::from_corners((min_x, min_y), (max_x, max_y)))
+}
+
+pub fn copy(universe: &Universe, selection: Rectangle) -> Pattern {
+  Pattern::from_universe(universe, selection.x, selection.y, selection.width, selection.height)
+}
+
+// Pastes a pattern with its top-left cell at (x, y), replacing the cells underneath it, and returns the rectangle it
+// covers.
+pub fn paste(history: &mut History, universe: &mut Universe, pattern: &Pattern, x: i64, y: i64) -> Rectangle {
+  let target = Rectangle { x, y, width: pattern.width, height: pattern.height };
+  if pattern.width > 0 && pattern.height > 0 {
+    paste_blocks(history, universe, pattern, target);
+    history.finish_edit(universe);
+  }
+  target
+}
+
+pub fn clear_inside(history: &mut History, universe: &mut Universe, selection: Rectangle) {
+  clear_blocks(history, universe, selection);
+  history.finish_edit(universe);
+}
+
+pub fn clear_outside(history: &mut History, universe: &mut Universe, selection: Rectangle) {
+  for (plane, (block_x, block_y), cells) in universe.occupied_blocks() {
+    history.edit_block(universe, plane, block_x, block_y, cells & selection.block_mask(block_x, block_y));
+  }
+  history.finish_edit(universe);
+}
+
+// Fills the selection with live cells at random, each with even odds.
+pub fn random_fill(history: &mut History, universe: &mut Universe, selection: Rectangle) {
+  let mut random = Random::from_clock();
+  for (block_x, block_y) in selection.blocks() {
+    let mask = selection.block_mask(block_x, block_y);
+    for plane in 0..universe.plane_count() {
+      let cells = universe.block(plane, block_x, block_y) & !mask;
+      let live_cells = if plane == 0 { random.block() & mask } else { EMPTY_BLOCK };
+      history.edit_block(universe, plane, block_x, block_y, cells | live_cells);
+    }
+  }
+  history.finish_edit(universe);
+}
+
+pub fn flip_horizontally(history: &mut History, universe: &mut Universe, selection: Rectangle) -> Rectangle {
+  transform(history, universe, selection, Pattern::flipped_horizontally)
+}
+
+pub fn flip_vertically(history: &mut History, universe: &mut Universe, selection: Rectangle) -> Rectangle {
+  transform(history, universe, selection, Pattern::flipped_vertically)
+}
+
+// Turns the selection a quarter turn clockwise about its center, and returns the rectangle it covers afterwards.
+pub fn rotate_clockwise(history: &mut History, universe: &mut Universe, selection: Rectangle) -> Rectangle {
+  transform(history, universe, selection, Pattern::rotated_clockwise)
+}
+
+// Replaces the selection with a transformed copy of itself centered on the same point, as a single edit.
+fn transform<F: Fn(&Pattern) -> Pattern>(history: &mut History, universe: &mut Universe, selection: Rectangle, transformation: F) -> Rectangle {
+  let pattern = transformation(&copy(universe, selection));
+  clear_blocks(history, universe, selection);
+  let x = selection.x + (selection.width - pattern.width) / 2;
+  let y = selection.y + (selection.height - pattern.height) / 2;
+  paste(history, universe, &pattern, x, y)
+}
+
+fn clear_blocks(history: &mut History, universe: &mut Universe, selection: Rectangle) {
+  for (block_x, block_y) in selection.blocks() {
+    let mask = selection.block_mask(block_x, block_y);
+    for plane in 0..universe.plane_count() {
+      let cells = universe.block(plane, block_x, block_y);
+      history.edit_block(universe, plane, block_x, block_y, cells & !mask);
+    }
+  }
+}
+
+fn paste_blocks(history: &mut History, universe: &mut Universe, pattern: &Pattern, target: Rectangle) {
+  for (block_x, block_y) in target.blocks() {
+    let mask = target.block_mask(block_x, block_y);
+    for plane in 0..pattern.plane_count().min(universe.plane_count()) {
+      let pasted = pattern::window(|x, y| pattern.block(plane, x, y), block_x * WIDTH - target.x, block_y * HEIGHT - target.y);
+      let cells = universe.block(plane, block_x, block_y);
+      history.edit_block(universe, plane, block_x, block_y, (cells & !mask) | (pasted & mask));
+    }
+  }
+}
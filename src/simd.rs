@@ -0,0 +1,466 @@
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+use crate::conway;
+use crate::conway::{BOTTOM, BOTTOM_LEFT, BOTTOM_RIGHT, CENTER, LEFT, RIGHT, TOP, TOP_LEFT, TOP_RIGHT};
+use crate::conway::{CellBlock, CELLS_PER_BLOCK, CELL_BLOCK_WIDTH, FIRST_COLUMN, LAST_COLUMN};
+
+// The instructions Conway's Game of Life is stepped with, from one block at a time up to eight at once.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kernel {
+  Portable,
+  Avx2,
+  Avx512,
+}
+
+impl Kernel {
+  pub const ALL: [Kernel; 3] = [Kernel::Portable, Kernel::Avx2, Kernel::Avx512];
+
+  // The widest kernel this processor can run, which is looked up once and then remembered by the standard library.
+  pub fn detect() -> Kernel {
+    *Kernel::ALL.iter().rev().find(|kernel| kernel.is_supported()).unwrap()
+  }
+
+  pub fn is_supported(self) -> bool {
+    match self {
+      Kernel::Portable => true,
+      #[cfg(target_arch = "x86_64")]
+      Kernel::Avx2 => is_x86_feature_detected!("avx2"),
+      #[cfg(target_arch = "x86_64")]
+      Kernel::Avx512 => is_x86_feature_detected!("avx512f"),
+      #[cfg(not(target_arch = "x86_64"))]
+      _ => false,
+    }
+  }
+
+  // The kernel blocks are stepped with when this one is asked for. The vector kernels work on 64-bit words, so blocks
+  // any wider are stepped one at a time with the portable kernel.
+  pub fn for_blocks(self) -> Kernel {
+    if CELLS_PER_BLOCK == u64::BITS as u64 { self } else { Kernel::Portable }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Kernel::Portable => "portable",
+      Kernel::Avx2 => "AVX2",
+      Kernel::Avx512 => "AVX-512",
+    }
+  }
+}
+
+// Steps a row of blocks under Conway's Game of Life with the given kernel, which has to be supported. The rows above
+// and below are all zero past the edges of the board.
+pub fn step_conway_row(kernel: Kernel, rows: [&[CellBlock]; 3], destination: &mut [CellBlock]) {
+  step::<CellBlock, Blocks>(kernel, rows, destination)
+}
+
+// Steps a row of cells laid out 64 to a word, rather than in blocks, in the same way.
+pub fn step_conway_cell_row(kernel: Kernel, rows: [&[u64]; 3], destination: &mut [u64]) {
+  step::<u64, Rows>(kernel, rows, destination)
+}
+
+fn step<W: Word, L: Layout<W>>(kernel: Kernel, rows: [&[W]; 3], destination: &mut [W]) {
+  assert!(kernel.is_supported());
+  assert!(rows.iter().all(|row| row.len() == destination.len()));
+  // Safe as the rows are all as long as the destination, and the kernel's instructions are supported.
+  unsafe { W::step_row_with::<L>(kernel, rows, destination) }
+}
+
+// The words cells are packed into, whether blocks or rows. The vector kernels only have lanes of 64-bit words, so any
+// wider words are stepped with the portable kernel whichever kernel is asked for.
+trait Word: Lanes<Word = Self> + Default {
+  unsafe fn step_row_with<L: Layout<Self>>(_kernel: Kernel, rows: [&[Self]; 3], destination: &mut [Self]) {
+    unsafe { step_row::<Self, Self, L>(rows, destination) }
+  }
+}
+
+impl Word for u64 {
+  unsafe fn step_row_with<L: Layout<u64>>(kernel: Kernel, rows: [&[u64]; 3], destination: &mut [u64]) {
+    unsafe {
+      match kernel {
+        Kernel::Portable => step_row::<u64, u64, L>(rows, destination),
+        #[cfg(target_arch = "x86_64")]
+        Kernel::Avx2 => step_row_avx2::<L>(rows, destination),
+        #[cfg(target_arch = "x86_64")]
+        Kernel::Avx512 => step_row_avx512::<L>(rows, destination),
+        #[cfg(not(target_arch = "x86_64"))]
+        _ => unreachable!(),
+      }
+    }
+  }
+}
+
+impl Word for u128 {}
+
+#[cfg(feature = "large-cell-blocks")]
+impl Word for crate::u256::U256 {}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn step_row_avx2<L: Layout<u64>>(rows: [&[u64]; 3], destination: &mut [u64]) {
+  unsafe { step_row::<u64, __m256i, L>(rows, destination) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn step_row_avx512<L: Layout<u64>>(rows: [&[u64]; 3], destination: &mut [u64]) {
+  unsafe { step_row::<u64, __m512i, L>(rows, destination) }
+}
+
+// The words at either end of the row are missing a neighbor, so they are stepped one at a time with the neighborhood
+// checked for the edges, leaving all of the words in between to be stepped several at once without any checks.
+unsafe fn step_row<W: Word, V: Lanes<Word = W>, L: Layout<W>>(rows: [&[W]; 3], destination: &mut [W]) {
+  let width = destination.len();
+  let mut column = 1;
+  while column + V::COUNT < width {
+    let mut neighborhood = [V::splat(W::default()); 9];
+    for (row, words) in rows.iter().enumerate() {
+      for offset in 0..3 {
+        neighborhood[row * 3 + offset] = unsafe { V::load(words.as_ptr().add(column + offset - 1)) };
+      }
+    }
+    unsafe { life(L::neighbors(&neighborhood), neighborhood[CENTER]).store(destination.as_mut_ptr().add(column)) };
+    column += V::COUNT;
+  }
+
+  for column in std::iter::once(0).chain(column..width) {
+    let neighborhood = conway::row_neighborhood(rows, column);
+    destination[column] = life(L::neighbors(&neighborhood), neighborhood[CENTER]);
+  }
+}
+
+// Where each cell's eight neighbors are, given a word of cells and the words around it, depends on how the cells are
+// laid out in the words. The neighbors come out as a word each, lined up with the cells they are next to, in the same
+// order as a neighbor mask.
+trait Layout<W> {
+  fn neighbors<V: Lanes<Word = W>>(neighborhood: &[V; 9]) -> [V; 8];
+}
+
+// Blocks of however many cells a CellBlock holds.
+struct Blocks;
+
+impl Layout<CellBlock> for Blocks {
+  #[inline(always)]
+  fn neighbors<V: Lanes<Word = CellBlock>>(neighborhood: &[V; 9]) -> [V; 8] {
+    let block = neighborhood[CENTER];
+    let left = from_left(block, neighborhood[LEFT]);
+    let right = from_right(block, neighborhood[RIGHT]);
+    [
+      from_above(left, from_left(neighborhood[TOP], neighborhood[TOP_LEFT])),
+      from_above(block, neighborhood[TOP]),
+      from_above(right, from_right(neighborhood[TOP], neighborhood[TOP_RIGHT])),
+      left,
+      right,
+      from_below(left, from_left(neighborhood[BOTTOM], neighborhood[BOTTOM_LEFT])),
+      from_below(block, neighborhood[BOTTOM]),
+      from_below(right, from_right(neighborhood[BOTTOM], neighborhood[BOTTOM_RIGHT])),
+    ]
+  }
+}
+
+// Rows of 64 cells, with the leftmost cell in the lowest bit. The rows above and below are simply the words above and
+// below, so only the cells to either side take any shifting.
+struct Rows;
+
+impl Layout<u64> for Rows {
+  #[inline(always)]
+  fn neighbors<V: Lanes<Word = u64>>(neighborhood: &[V; 9]) -> [V; 8] {
+    let from_left = |word: V, left: V| word.shift_left(1).or(left.shift_right(63));
+    let from_right = |word: V, right: V| word.shift_right(1).or(right.shift_left(63));
+    [
+      from_left(neighborhood[TOP], neighborhood[TOP_LEFT]),
+      neighborhood[TOP],
+      from_right(neighborhood[TOP], neighborhood[TOP_RIGHT]),
+      from_left(neighborhood[CENTER], neighborhood[LEFT]),
+      from_right(neighborhood[CENTER], neighborhood[RIGHT]),
+      from_left(neighborhood[BOTTOM], neighborhood[BOTTOM_LEFT]),
+      neighborhood[BOTTOM],
+      from_right(neighborhood[BOTTOM], neighborhood[BOTTOM_RIGHT]),
+    ]
+  }
+}
+
+// Counts the live neighbors of all of the cells at once, each bit of the count held in a word of its own, and applies
+// B3/S23 to the counts.
+#[inline(always)]
+fn life<V: Lanes>(neighbors: [V; 8], cells: V) -> V {
+  let [above_left, above, above_right, left, right, below_left, below, below_right] = neighbors;
+  let (sum_above, carry_above) = full_add(above_left, above, above_right);
+  let (sum_sides, carry_sides) = full_add(left, right, below_left);
+  let (sum_below, carry_below) = half_add(below, below_right);
+  let (ones, carry_ones) = full_add(sum_above, sum_sides, sum_below);
+  let (sum_twos, carry_twos) = full_add(carry_above, carry_sides, carry_below);
+  let (twos, carry_fours) = half_add(sum_twos, carry_ones);
+  // Four or more neighbors, where eight overflows into the same bit.
+  let fours = carry_twos.or(carry_fours);
+
+  // Two neighbors keeps a live cell alive, and three brings any cell to life.
+  twos.and_not(fours).and(ones.or(cells))
+}
+
+#[inline(always)]
+fn full_add<V: Lanes>(a: V, b: V, c: V) -> (V, V) {
+  let partial = a.xor(b);
+  (partial.xor(c), a.and(b).or(partial.and(c)))
+}
+
+#[inline(always)]
+fn half_add<V: Lanes>(a: V, b: V) -> (V, V) {
+  (a.xor(b), a.and(b))
+}
+
+// Each cell's neighbor to the left, taking the last column of the block to the left for the first column.
+#[inline(always)]
+fn from_left<V: Lanes<Word = CellBlock>>(block: V, left: V) -> V {
+  let first_column = V::splat(FIRST_COLUMN);
+  block.shift_left(1).and_not(first_column).or(left.shift_right(CELL_BLOCK_WIDTH as u32 - 1).and(first_column))
+}
+
+#[inline(always)]
+fn from_right<V: Lanes<Word = CellBlock>>(block: V, right: V) -> V {
+  let last_column = V::splat(LAST_COLUMN);
+  block.shift_right(1).and_not(last_column).or(right.shift_left(CELL_BLOCK_WIDTH as u32 - 1).and(last_column))
+}
+
+#[inline(always)]
+fn from_above<V: Lanes<Word = CellBlock>>(block: V, above: V) -> V {
+  block.shift_left(CELL_BLOCK_WIDTH as u32).or(above.shift_right((CELLS_PER_BLOCK - CELL_BLOCK_WIDTH) as u32))
+}
+
+#[inline(always)]
+fn from_below<V: Lanes<Word = CellBlock>>(block: V, below: V) -> V {
+  block.shift_right(CELL_BLOCK_WIDTH as u32).or(below.shift_left((CELLS_PER_BLOCK - CELL_BLOCK_WIDTH) as u32))
+}
+
+// Several words side by side, operated on all at once. The vector types are only used from inside functions that
+// enable the instructions they need, which lets their methods be inlined there.
+trait Lanes: Copy {
+  type Word: Copy + Default;
+  const COUNT: usize;
+  unsafe fn load(words: *const Self::Word) -> Self;
+  unsafe fn store(self, words: *mut Self::Word);
+  fn splat(word: Self::Word) -> Self;
+  fn and(self, other: Self) -> Self;
+  fn or(self, other: Self) -> Self;
+  fn xor(self, other: Self) -> Self;
+  fn and_not(self, other: Self) -> Self;
+  fn shift_left(self, bits: u32) -> Self;
+  fn shift_right(self, bits: u32) -> Self;
+}
+
+// A single word is the portable kernel's only lane, whatever its size.
+macro_rules! word_lanes {
+  ($($word:ty),*) => {$(
+    impl Lanes for $word {
+      type Word = $word;
+      const COUNT: usize = 1;
+
+      #[inline(always)]
+      unsafe fn load(words: *const $word) -> Self {
+        unsafe { *words }
+      }
+
+      #[inline(always)]
+      unsafe fn store(self, words: *mut $word) {
+        unsafe { *words = self }
+      }
+
+      #[inline(always)]
+      fn splat(word: $word) -> Self {
+        word
+      }
+
+      #[inline(always)]
+      fn and(self, other: Self) -> Self {
+        self & other
+      }
+
+      #[inline(always)]
+      fn or(self, other: Self) -> Self {
+        self | other
+      }
+
+      #[inline(always)]
+      fn xor(self, other: Self) -> Self {
+        self ^ other
+      }
+
+      #[inline(always)]
+      fn and_not(self, other: Self) -> Self {
+        self & !other
+      }
+
+      #[inline(always)]
+      fn shift_left(self, bits: u32) -> Self {
+        self << bits
+      }
+
+      #[inline(always)]
+      fn shift_right(self, bits: u32) -> Self {
+        self >> bits
+      }
+    }
+  )*};
+}
+
+word_lanes!(u64, u128);
+#[cfg(feature = "large-cell-blocks")]
+word_lanes!(crate::u256::U256);
+
+#[cfg(target_arch = "x86_64")]
+impl Lanes for __m256i {
+  type Word = u64;
+  const COUNT: usize = 4;
+
+  #[inline(always)]
+  unsafe fn load(words: *const u64) -> Self {
+    unsafe { _mm256_loadu_si256(words as *const __m256i) }
+  }
+
+  #[inline(always)]
+  unsafe fn store(self, words: *mut u64) {
+    unsafe { _mm256_storeu_si256(words as *mut __m256i, self) }
+  }
+
+  #[inline(always)]
+  fn splat(word: u64) -> Self {
+    unsafe { _mm256_set1_epi64x(word as i64) }
+  }
+
+  #[inline(always)]
+  fn and(self, other: Self) -> Self {
+    unsafe { _mm256_and_si256(self, other) }
+  }
+
+  #[inline(always)]
+  fn or(self, other: Self) -> Self {
+    unsafe { _mm256_or_si256(self, other) }
+  }
+
+  #[inline(always)]
+  fn xor(self, other: Self) -> Self {
+    unsafe { _mm256_xor_si256(self, other) }
+  }
+
+  #[inline(always)]
+  fn and_not(self, other: Self) -> Self {
+    unsafe { _mm256_andnot_si256(other, self) }
+  }
+
+  #[inline(always)]
+  fn shift_left(self, bits: u32) -> Self {
+    unsafe { _mm256_sll_epi64(self, _mm_cvtsi32_si128(bits as i32)) }
+  }
+
+  #[inline(always)]
+  fn shift_right(self, bits: u32) -> Self {
+    unsafe { _mm256_srl_epi64(self, _mm_cvtsi32_si128(bits as i32)) }
+  }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Lanes for __m512i {
+  type Word = u64;
+  const COUNT: usize = 8;
+
+  #[inline(always)]
+  unsafe fn load(words: *const u64) -> Self {
+    unsafe { _mm512_loadu_si512(words as *const __m512i) }
+  }
+
+  #[inline(always)]
+  unsafe fn store(self, words: *mut u64) {
+    unsafe { _mm512_storeu_si512(words as *mut __m512i, self) }
+  }
+
+  #[inline(always)]
+  fn splat(word: u64) -> Self {
+    unsafe { _mm512_set1_epi64(word as i64) }
+  }
+
+  #[inline(always)]
+  fn and(self, other: Self) -> Self {
+    unsafe { _mm512_and_si512(self, other) }
+  }
+
+  #[inline(always)]
+  fn or(self, other: Self) -> Self {
+    unsafe { _mm512_or_si512(self, other) }
+  }
+
+  #[inline(always)]
+  fn xor(self, other: Self) -> Self {
+    unsafe { _mm512_xor_si512(self, other) }
+  }
+
+  #[inline(always)]
+  fn and_not(self, other: Self) -> Self {
+    unsafe { _mm512_andnot_si512(other, self) }
+  }
+
+  #[inline(always)]
+  fn shift_left(self, bits: u32) -> Self {
+    unsafe { _mm512_sll_epi64(self, _mm_cvtsi32_si128(bits as i32)) }
+  }
+
+  #[inline(always)]
+  fn shift_right(self, bits: u32) -> Self {
+    unsafe { _mm512_srl_epi64(self, _mm_cvtsi32_si128(bits as i32)) }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::conway::{BlockNeighborhood, BlockRule, Conway};
+  use crate::random::Random;
+
+  // Wide enough for every kernel to step several lanes at once between the words at either end.
+  const WIDTH: usize = 37;
+
+  // Conway's rule looked up in the tables one block at a time.
+  struct Tables;
+
+  impl BlockRule for Tables {
+    fn new_value_for_neighborhood(&self, neighborhood: &BlockNeighborhood) -> CellBlock {
+      conway::new_value_for_neighborhood(neighborhood, &Conway)
+    }
+  }
+
+  fn supported_kernels() -> impl Iterator<Item = Kernel> {
+    Kernel::ALL.into_iter().filter(|kernel| kernel.is_supported())
+  }
+
+  #[test]
+  fn kernels_step_blocks_like_the_tables() {
+    let mut random = Random::seeded(7);
+    let board: Vec<CellBlock> = (0..3 * WIDTH).map(|_| random.block()).collect();
+    let rows = [&board[..WIDTH], &board[WIDTH..2 * WIDTH], &board[2 * WIDTH..]];
+    let mut expected = vec![conway::EMPTY_BLOCK; WIDTH];
+    Tables.new_values_for_row(rows, &mut expected);
+    for kernel in supported_kernels() {
+      let mut destination = vec![conway::EMPTY_BLOCK; WIDTH];
+      step_conway_row(kernel, rows, &mut destination);
+      assert_eq!(destination, expected, "{} kernel", kernel.name());
+    }
+  }
+
+  #[test]
+  fn kernels_step_rows_like_counting_neighbors() {
+    let mut random = Random::seeded(7);
+    let board: Vec<u64> = (0..3 * WIDTH).map(|_| random.next()).collect();
+    let rows = [&board[..WIDTH], &board[WIDTH..2 * WIDTH], &board[2 * WIDTH..]];
+    let alive = |row: usize, cell: i64| (0..WIDTH as i64 * 64).contains(&cell) && rows[row][cell as usize / 64] >> (cell % 64) & 1 != 0;
+    let mut expected = vec![0; WIDTH];
+    for cell in 0..WIDTH as i64 * 64 {
+      let neighbors = (0..3).flat_map(|row| (cell - 1..=cell + 1).map(move |x| (row, x))).filter(|&(row, x)| (row, x) != (1, cell) && alive(row, x)).count();
+      if neighbors == 3 || neighbors == 2 && alive(1, cell) {
+        expected[cell as usize / 64] |= 1 << (cell % 64);
+      }
+    }
+    for kernel in supported_kernels() {
+      let mut destination = vec![0; WIDTH];
+      step_conway_cell_row(kernel, rows, &mut destination);
+      assert_eq!(destination, expected, "{} kernel", kernel.name());
+    }
+  }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Instant;
+use crate::conway::{BlockNeighborhood, BlockRule, CellBlock, Conway, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK, FIRST_CELL};
+
+// The sparse board divides an unbounded universe into square tiles of cell blocks. Only tiles containing live cells
+// are allocated, so memory scales with the live area of the pattern rather than with the size of the universe.
+pub const TILE_WIDTH_BLOCKS: usize = 64;
+pub const TILE_HEIGHT_BLOCKS: usize = 64;
+pub const TILE_TOTAL_BLOCKS: usize = TILE_WIDTH_BLOCKS * TILE_HEIGHT_BLOCKS;
+
+pub const TILE_WIDTH_CELLS: i64 = TILE_WIDTH_BLOCKS as i64 * CELL_BLOCK_WIDTH as i64;
+pub const TILE_HEIGHT_CELLS: i64 = TILE_HEIGHT_BLOCKS as i64 * CELL_BLOCK_HEIGHT as i64;
+
+pub type Tile = [CellBlock; TILE_TOTAL_BLOCKS];
+
+// Tile coordinates are (x, y), with x increasing to the right and y increasing downward, same as cells.
+pub type TileCoordinate = (i64, i64);
+
+#[derive(Default)]
+pub struct SparseBoard {
+  tiles: HashMap<TileCoordinate, Box<Tile>>,
+}
+
+impl SparseBoard {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn get_cell(&self, x: i64, y: i64) -> bool {
+    let (tile_coordinate, block_index, cell) = locate_cell(x, y);
+    match self.tiles.get(&tile_coordinate) {
+      Some(tile) => (tile[block_index] >> cell) & 1 == 1,
+      None => false,
+    }
+  }
+
+  pub fn set_cell(&mut self, x: i64, y: i64, alive: bool) {
+    let (tile_coordinate, block_index, cell) = locate_cell(x, y);
+    if alive {
+      let tile = self.tiles.entry(tile_coordinate).or_insert_with(new_tile);
+      tile[block_index] |= FIRST_CELL << cell;
+    } else if let Some(tile) = self.tiles.get_mut(&tile_coordinate) {
+      tile[block_index] &= !(FIRST_CELL << cell);
+      if is_tile_empty(tile) {
+        self.tiles.remove(&tile_coordinate);
+      }
+    }
+  }
+
+  // Returns the block at the given block coordinates, which are cell coordinates divided by the block dimensions.
+  pub fn block(&self, block_x: i64, block_y: i64) -> CellBlock {
+    let tile_coordinate = (block_x.div_euclid(TILE_WIDTH_BLOCKS as i64), block_y.div_euclid(TILE_HEIGHT_BLOCKS as i64));
+    let block_x = block_x.rem_euclid(TILE_WIDTH_BLOCKS as i64) as usize;
+    let block_y = block_y.rem_euclid(TILE_HEIGHT_BLOCKS as i64) as usize;
+    match self.tiles.get(&tile_coordinate) {
+      Some(tile) => tile[block_y * TILE_WIDTH_BLOCKS + block_x],
+      None => EMPTY_BLOCK,
+    }
+  }
+
+  // Flips the cells of the block at the given block coordinates that are set in the given block.
+  pub fn xor_block(&mut self, block_x: i64, block_y: i64, cells: CellBlock) {
+    if cells == 0 {
+      return;
+    }
+    let tile_coordinate = (block_x.div_euclid(TILE_WIDTH_BLOCKS as i64), block_y.div_euclid(TILE_HEIGHT_BLOCKS as i64));
+    let block_index = block_y.rem_euclid(TILE_HEIGHT_BLOCKS as i64) as usize * TILE_WIDTH_BLOCKS
+        + block_x.rem_euclid(TILE_WIDTH_BLOCKS as i64) as usize;
+    let tile = self.tiles.entry(tile_coordinate).or_insert_with(new_tile);
+    tile[block_index] ^= cells;
+    if tile[block_index] == 0 && is_tile_empty(tile) {
+      self.tiles.remove(&tile_coordinate);
+    }
+  }
+
+  // Every non-empty block as its block coordinates and cells.
+  pub fn occupied_blocks(&self) -> Vec<((i64, i64), CellBlock)> {
+    let mut blocks = Vec::new();
+    for (&coordinate, tile) in &self.tiles {
+      blocks.extend(tile_blocks(coordinate, tile).filter(|&(_, block)| block != 0));
+    }
+    blocks
+  }
+
+  // Every block that differs from the same block of the other board, as its block coordinates and the cells that
+  // differ.
+  pub fn changed_blocks(&self, other: &SparseBoard) -> Vec<((i64, i64), CellBlock)> {
+    let empty_tile = new_tile();
+    let mut blocks = Vec::new();
+    for (&coordinate, tile) in &self.tiles {
+      let other_tile = other.tiles.get(&coordinate).unwrap_or(&empty_tile);
+      blocks.extend(tile_blocks(coordinate, tile)
+          .zip(other_tile.iter())
+          .map(|((block_coordinate, block), &other_block)| (block_coordinate, block ^ other_block))
+          .filter(|&(_, cells)| cells != 0));
+    }
+    for (&coordinate, tile) in &other.tiles {
+      if !self.tiles.contains_key(&coordinate) {
+        blocks.extend(tile_blocks(coordinate, tile).filter(|&(_, block)| block != 0));
+      }
+    }
+    blocks
+  }
+
+  pub fn clear(&mut self) {
+    self.tiles.clear();
+  }
+
+  pub fn population(&self) -> u64 {
+    self.tiles.values().flat_map(|tile| tile.iter()).map(|block| block.count_ones() as u64).sum()
+  }
+
+  pub fn tile_count(&self) -> usize {
+    self.tiles.len()
+  }
+
+  pub fn tiles(&self) -> impl Iterator<Item = (&TileCoordinate, &Tile)> {
+    self.tiles.iter().map(|(coordinate, tile)| (coordinate, tile.as_ref()))
+  }
+
+  pub fn tile(&self, tile_coordinate: TileCoordinate) -> Option<&Tile> {
+    self.tiles.get(&tile_coordinate).map(|tile| tile.as_ref())
+  }
+
+  // Any non-empty block within the given ranges of block columns and rows. Whichever is fewer of the tiles the ranges
+  // reach into and the tiles the board has are looked through, so that ranges over empty space are quick.
+  pub fn occupied_block_in(&self, columns: Range<i64>, rows: Range<i64>) -> Option<(i64, i64)> {
+    if columns.is_empty() || rows.is_empty() {
+      return None;
+    }
+    let (width, height) = (TILE_WIDTH_BLOCKS as i64, TILE_HEIGHT_BLOCKS as i64);
+    let tile_columns = columns.start.div_euclid(width)..=(columns.end - 1).div_euclid(width);
+    let tile_rows = rows.start.div_euclid(height)..=(rows.end - 1).div_euclid(height);
+    let occupied_block = |&(tile_x, tile_y): &TileCoordinate, tile: &Tile| {
+      let (left, top) = (tile_x * width, tile_y * height);
+      let block_columns = columns.start.max(left)..columns.end.min(left + width);
+      let block_rows = rows.start.max(top)..rows.end.min(top + height);
+      block_rows.flat_map(|block_y| block_columns.clone().map(move |block_x| (block_x, block_y)))
+          .find(|&(block_x, block_y)| tile[(block_y - top) as usize * TILE_WIDTH_BLOCKS + (block_x - left) as usize] != EMPTY_BLOCK)
+    };
+
+    let covered_tiles = (tile_columns.end().abs_diff(*tile_columns.start()) + 1).saturating_mul(tile_rows.end().abs_diff(*tile_rows.start()) + 1);
+    if covered_tiles <= self.tiles.len() as u64 {
+      tile_rows.flat_map(|tile_y| tile_columns.clone().map(move |tile_x| (tile_x, tile_y)))
+          .find_map(|coordinate| occupied_block(&coordinate, self.tile(coordinate)?))
+    } else {
+      self.tiles()
+          .filter(|((tile_x, tile_y), _)| tile_columns.contains(tile_x) && tile_rows.contains(tile_y))
+          .find_map(|(coordinate, tile)| occupied_block(coordinate, tile))
+    }
+  }
+
+  pub fn tile_neighborhood(&self, tile_coordinate: TileCoordinate) -> TileNeighborhood<'_> {
+    let mut tiles: [Option<&Tile>; 9] = [None; 9];
+    for (index, tile) in tiles.iter_mut().enumerate() {
+      let dx = (index % 3) as i64 - 1;
+      let dy = (index / 3) as i64 - 1;
+      *tile = self.tile((tile_coordinate.0 + dx, tile_coordinate.1 + dy));
+    }
+    TileNeighborhood { tiles }
+  }
+
+  pub(crate) fn insert_tile(&mut self, tile_coordinate: TileCoordinate, tile: Box<Tile>) {
+    if is_tile_empty(&tile) {
+      self.tiles.remove(&tile_coordinate);
+    } else {
+      self.tiles.insert(tile_coordinate, tile);
+    }
+  }
+
+  // Tiles that may contain live cells in the next generation: every allocated tile, plus any neighboring tile that
+  // cells within the given range of its edges could spill into.
+  pub fn candidate_tiles(&self, range: i64) -> Vec<TileCoordinate> {
+    let edge_blocks = (range as u64).div_ceil(CELL_BLOCK_WIDTH.min(CELL_BLOCK_HEIGHT)) as usize;
+    let mut candidates = Vec::<TileCoordinate>::with_capacity(self.tiles.len() * 2);
+    for (&(tile_x, tile_y), tile) in &self.tiles {
+      for dy in -1..=1 {
+        for dx in -1..=1 {
+          let coordinate = (tile_x + dx, tile_y + dy);
+          if (dx == 0 && dy == 0) || (!self.tiles.contains_key(&coordinate) && is_tile_edge_alive(tile, dx, dy, edge_blocks)) {
+            candidates.push(coordinate);
+          }
+        }
+      }
+    }
+    candidates.sort_unstable();
+    candidates.dedup();
+    candidates
+  }
+}
+
+// A tile and the eight tiles surrounding it, ordered the same way as a block neighborhood. Used to gather the blocks
+// around any block in the center tile without looking tiles up again for every block.
+pub struct TileNeighborhood<'a> {
+  tiles: [Option<&'a Tile>; 9],
+}
+
+impl TileNeighborhood<'_> {
+  // Returns the block at the given block offset relative to the top-left block of the center tile. Offsets may extend
+  // one tile past the edges of the center tile, in which case the block is fetched from the neighboring tile.
+  pub fn block(&self, block_x: i64, block_y: i64) -> CellBlock {
+    let tile_x = block_x.div_euclid(TILE_WIDTH_BLOCKS as i64) + 1;
+    let tile_y = block_y.div_euclid(TILE_HEIGHT_BLOCKS as i64) + 1;
+    let block_x = block_x.rem_euclid(TILE_WIDTH_BLOCKS as i64) as usize;
+    let block_y = block_y.rem_euclid(TILE_HEIGHT_BLOCKS as i64) as usize;
+    match self.tiles[tile_y as usize * 3 + tile_x as usize] {
+      Some(tile) => tile[block_y * TILE_WIDTH_BLOCKS + block_x],
+      None => EMPTY_BLOCK,
+    }
+  }
+
+  // Returns whether the cell at the given offset relative to the top-left cell of the center tile is alive. Offsets
+  // may extend one tile past the edges of the center tile.
+  pub fn cell(&self, x: i64, y: i64) -> bool {
+    let block = self.block(x.div_euclid(CELL_BLOCK_WIDTH as i64), y.div_euclid(CELL_BLOCK_HEIGHT as i64));
+    let cell = y.rem_euclid(CELL_BLOCK_HEIGHT as i64) as u64 * CELL_BLOCK_WIDTH + x.rem_euclid(CELL_BLOCK_WIDTH as i64) as u64;
+    (block >> cell) & 1 == 1
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.tiles.iter().all(|tile| tile.is_none())
+  }
+
+  pub fn neighborhood_for_block(&self, block_x: i64, block_y: i64) -> BlockNeighborhood {
+    let mut neighborhood: BlockNeighborhood = [EMPTY_BLOCK; 9];
+    for (index, block) in neighborhood.iter_mut().enumerate() {
+      let dx = (index % 3) as i64 - 1;
+      let dy = (index / 3) as i64 - 1;
+      *block = self.block(block_x + dx, block_y + dy);
+    }
+    neighborhood
+  }
+}
+
+pub fn new_tile() -> Box<Tile> {
+  Box::new([EMPTY_BLOCK; TILE_TOTAL_BLOCKS])
+}
+
+// The blocks of a tile along with their block coordinates.
+fn tile_blocks(tile_coordinate: TileCoordinate, tile: &Tile) -> impl Iterator<Item = ((i64, i64), CellBlock)> + '_ {
+  tile.iter().enumerate().map(move |(block_index, &block)| {
+    let block_x = tile_coordinate.0 * TILE_WIDTH_BLOCKS as i64 + (block_index % TILE_WIDTH_BLOCKS) as i64;
+    let block_y = tile_coordinate.1 * TILE_HEIGHT_BLOCKS as i64 + (block_index / TILE_WIDTH_BLOCKS) as i64;
+    ((block_x, block_y), block)
+  })
+}
+
+pub fn is_tile_empty(tile: &Tile) -> bool {
+  tile.iter().all(|&block| block == 0)
+}
+
+// Splits cell coordinates into the coordinate of the tile, the index of the block within that tile, and the index of
+// the cell within that block.
+pub fn locate_cell(x: i64, y: i64) -> (TileCoordinate, usize, u64) {
+  let tile_coordinate = (x.div_euclid(TILE_WIDTH_CELLS), y.div_euclid(TILE_HEIGHT_CELLS));
+  let x = x.rem_euclid(TILE_WIDTH_CELLS) as u64;
+  let y = y.rem_euclid(TILE_HEIGHT_CELLS) as u64;
+  let block_index = (y / CELL_BLOCK_HEIGHT) as usize * TILE_WIDTH_BLOCKS + (x / CELL_BLOCK_WIDTH) as usize;
+  let cell = (y % CELL_BLOCK_HEIGHT) * CELL_BLOCK_WIDTH + x % CELL_BLOCK_WIDTH;
+  (tile_coordinate, block_index, cell)
+}
+
+// Whether any block within the given number of blocks of the edge of a tile facing the direction (dx, dy) contains
+// live cells. Conservative at the block level, which is enough to decide whether a neighboring tile needs computing.
+fn is_tile_edge_alive(tile: &Tile, dx: i64, dy: i64, edge_blocks: usize) -> bool {
+  let edge_range = |direction: i64, length: usize| match direction {
+    -1 => 0..edge_blocks.min(length),
+    1 => length - edge_blocks.min(length)..length,
+    _ => 0..length,
+  };
+  edge_range(dy, TILE_HEIGHT_BLOCKS).any(|block_y| {
+    edge_range(dx, TILE_WIDTH_BLOCKS).any(|block_x| tile[block_y * TILE_WIDTH_BLOCKS + block_x] != 0)
+  })
+}
+
+// A rule that computes a whole tile at a time, for rules whose neighborhoods reach further than the blocks adjacent to
+// each block. Every rule that works block by block from a block neighborhood is also a tile rule.
+pub trait TileRule: Sync {
+  // How many cells away from a cell its neighborhood reaches.
+  fn range(&self) -> i64;
+
+  // Computes the tile that results from applying the rule to every cell of the center tile. Dead cells are given
+  // their birth value and live cells their survival value.
+  fn new_value_for_tile(&self, tiles: &TileNeighborhood) -> Box<Tile>;
+}
+
+impl<R: BlockRule + Sync> TileRule for R {
+  fn range(&self) -> i64 {
+    1
+  }
+
+  fn new_value_for_tile(&self, tiles: &TileNeighborhood) -> Box<Tile> {
+    let mut tile = new_tile();
+    for (block_index, block) in tile.iter_mut().enumerate() {
+      let block_x = (block_index % TILE_WIDTH_BLOCKS) as i64;
+      let block_y = (block_index / TILE_WIDTH_BLOCKS) as i64;
+      let neighborhood = tiles.neighborhood_for_block(block_x, block_y);
+      if neighborhood.iter().all(|&block| block == 0) {
+        continue;
+      }
+      *block = self.new_value_for_neighborhood(&neighborhood);
+    }
+    tile
+  }
+}
+
+pub fn compute_next_board_state(source: &SparseBoard, destination: &mut SparseBoard) {
+  compute_next_board_state_with_rule(source, destination, &Conway)
+}
+
+// Steps every candidate tile of the source board in parallel. Tiles that die out are not carried over into the
+// destination.
+pub fn compute_next_board_state_with_rule<R: TileRule>(source: &SparseBoard, destination: &mut SparseBoard, rule: &R) {
+  let candidates = source.candidate_tiles(rule.range());
+  destination.clear();
+  let new_tiles = compute_tiles_in_parallel(&candidates, |coordinate| {
+    rule.new_value_for_tile(&source.tile_neighborhood(coordinate))
+  });
+  for (coordinate, tile) in new_tiles {
+    destination.insert_tile(coordinate, tile);
+  }
+}
+
+// Computes a value for each of the given tile coordinates, spreading the work across threads.
+pub fn compute_tiles_in_parallel<T, F>(coordinates: &[TileCoordinate], compute_tile: F) -> Vec<(TileCoordinate, T)>
+where
+  T: Send,
+  F: Fn(TileCoordinate) -> T + Sync,
+{
+  if coordinates.is_empty() {
+    return Vec::new();
+  }
+
+  let num_threads = crate::num_threads();
+  let chunk_size = coordinates.len().div_ceil(num_threads);
+  let compute_tile = &compute_tile;
+
+  std::thread::scope(|scope| {
+    let mut threads = Vec::<std::thread::ScopedJoinHandle<Vec<(TileCoordinate, T)>>>::new();
+    for (chunk_index, chunk) in coordinates.chunks(chunk_size).enumerate() {
+      threads.push(scope.spawn(move || {
+        let start = Instant::now();
+        let tiles = chunk.iter().map(|&coordinate| (coordinate, compute_tile(coordinate))).collect();
+        crate::record_busy_time(chunk_index, start.elapsed());
+        tiles
+      }));
+    }
+    threads.into_iter().flat_map(|thread| thread.join().unwrap()).collect()
+  })
+}
@@ -0,0 +1,292 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use crate::history::History;
+use crate::render;
+use crate::render::{Colors, Viewport};
+use crate::universe::Universe;
+
+// Generations are stepped and the screen redrawn at most this often.
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+// The size of the terminal is looked up again this often, to follow it being resized.
+const RESIZE_INTERVAL: Duration = Duration::from_millis(500);
+
+// How far the view moves for each press of an arrow key, measured in characters.
+const PAN_CHARACTERS: f64 = 8.0;
+
+// Braille characters draw a grid of 2 x 4 dots, each of which is a bit of the character's offset from this one.
+const BRAILLE_BLANK: u32 = 0x2800;
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Glyphs {
+  // Each character shows 2 x 4 cells.
+  Braille,
+  // Each character shows 1 x 2 cells, which fewer fonts get wrong.
+  HalfBlocks,
+}
+
+impl Glyphs {
+  fn dots(self) -> (usize, usize) {
+    match self {
+      Glyphs::Braille => (2, 4),
+      Glyphs::HalfBlocks => (1, 2),
+    }
+  }
+}
+
+enum Key {
+  Character(u8),
+  Up,
+  Down,
+  Left,
+  Right,
+}
+
+// Runs the universe in the terminal rather than a window, drawing each cell as a dot of a braille or half block
+// character. The board is drawn through a viewport just like the window's, with one pixel per dot, so it pans, zooms
+// and shows whole blocks when zoomed out the same way.
+pub fn run(universe: &mut Universe, colors: &Colors, hexagonal: bool) -> Result<(), String> {
+  let saved_settings = stty(&["-g"])?;
+  stty(&["raw", "-echo"])?;
+  // Switch to the alternate screen and hide the cursor.
+  print!("\x1b[?1049h\x1b[?25l");
+  let result = main_loop(universe, colors, hexagonal);
+  print!("\x1b[?25h\x1b[?1049l");
+  std::io::stdout().flush().unwrap();
+  stty(&[saved_settings.trim()])?;
+  result
+}
+
+fn main_loop(universe: &mut Universe, colors: &Colors, hexagonal: bool) -> Result<(), String> {
+  let keys = read_keys();
+  let mut history = History::new(universe);
+  let mut glyphs = Glyphs::Braille;
+  let mut paused = false;
+  let (mut columns, mut rows) = terminal_size()?;
+  let mut resized = Some(Instant::now());
+  let (dots_x, dots_y) = glyphs.dots();
+  let mut viewport = Viewport::new(columns * dots_x as u32, rows.saturating_sub(1) * dots_y as u32, hexagonal);
+  viewport.cell_size = 1.0;
+  viewport.center_on(0.0, 0.0);
+
+  loop {
+    let frame_start = Instant::now();
+    while let Ok(key) = keys.try_recv() {
+      let (pan_x, pan_y) = (PAN_CHARACTERS * glyphs.dots().0 as f64, PAN_CHARACTERS * glyphs.dots().1 as f64);
+      match key {
+        Key::Character(b'q' | 0x03) => return Ok(()),
+        Key::Character(b' ') => paused = !paused,
+        Key::Character(b'n' | b'.') => {
+          history.step(universe);
+          paused = true;
+        }
+        Key::Character(b'b' | b',') => {
+          history.back(universe);
+          paused = true;
+        }
+        Key::Character(b'+' | b'=') => zoom(&mut viewport, 2.0),
+        Key::Character(b'-' | b'_') => zoom(&mut viewport, 0.5),
+        Key::Character(b'g') => {
+          glyphs = if glyphs == Glyphs::Braille { Glyphs::HalfBlocks } else { Glyphs::Braille };
+          resized = None;
+        }
+        Key::Up | Key::Character(b'k') => viewport.pan(0.0, pan_y),
+        Key::Down | Key::Character(b'j') => viewport.pan(0.0, -pan_y),
+        Key::Left | Key::Character(b'h') => viewport.pan(pan_x, 0.0),
+        Key::Right | Key::Character(b'l') => viewport.pan(-pan_x, 0.0),
+        Key::Character(_) => {}
+      }
+    }
+
+    if resized.is_none_or(|resized| resized.elapsed() >= RESIZE_INTERVAL) {
+      (columns, rows) = terminal_size()?;
+      let (dots_x, dots_y) = glyphs.dots();
+      // Keeps the middle of the view in place.
+      let (center_x, center_y) = (viewport.x + viewport.width as f64 / viewport.cell_size / 2.0, viewport.y + viewport.height as f64 / viewport.cell_size / 2.0);
+      viewport.resize(columns * dots_x as u32, rows.saturating_sub(1) * dots_y as u32);
+      viewport.x = center_x - viewport.width as f64 / viewport.cell_size / 2.0;
+      viewport.y = center_y - viewport.height as f64 / viewport.cell_size / 2.0;
+      resized = Some(Instant::now());
+    }
+
+    if !paused {
+      history.step(universe);
+    }
+    draw(universe, &viewport, colors, glyphs, paused, columns)?;
+    std::thread::sleep(FRAME_INTERVAL.saturating_sub(frame_start.elapsed()));
+  }
+}
+
+// Zooms by the given factor about the middle of the view.
+fn zoom(viewport: &mut Viewport, factor: f64) {
+  viewport.zoom(factor, viewport.width as f64 / 2.0, viewport.height as f64 / 2.0);
+}
+
+fn draw(universe: &Universe, viewport: &Viewport, colors: &Colors, glyphs: Glyphs, paused: bool, columns: u32) -> Result<(), String> {
+  let screen = screen(universe, viewport, colors, glyphs, paused, columns);
+  let mut stdout = std::io::stdout().lock();
+  stdout.write_all(screen.as_bytes()).and_then(|_| stdout.flush()).map_err(|error| format!("Could not draw to the terminal: {}", error))
+}
+
+// The escape sequences and characters that draw the view and the status line below it over the whole screen.
+fn screen(universe: &Universe, viewport: &Viewport, colors: &Colors, glyphs: Glyphs, paused: bool, columns: u32) -> String {
+  let (dots_x, dots_y) = glyphs.dots();
+  let (width, height) = (viewport.width as usize, viewport.height as usize);
+  let mut pixels = vec![0u32; width * height];
+  render::render(universe, viewport, colors, &mut pixels);
+
+  let mut screen = String::from("\x1b[H");
+  let mut current_color = None;
+  for row in 0..height / dots_y {
+    for column in 0..width / dots_x {
+      // Each character is drawn in the color of the first of its dots that is not in state 0.
+      let mut dots = 0;
+      let mut color = None;
+      for dot_y in 0..dots_y {
+        for dot_x in 0..dots_x {
+          let pixel = pixels[(row * dots_y + dot_y) * width + column * dots_x + dot_x];
+          if pixel != colors[0] {
+            dots |= 1 << (dot_y * dots_x + dot_x);
+            color.get_or_insert(pixel);
+          }
+        }
+      }
+      if let Some(color) = color.filter(|&color| Some(color) != current_color) {
+        screen += &format!("\x1b[38;2;{};{};{}m", (color >> 16) & 0xFF, (color >> 8) & 0xFF, color & 0xFF);
+        current_color = Some(color);
+      }
+      screen.push(glyph(glyphs, dots));
+    }
+    screen += "\x1b[K\r\n";
+  }
+
+  // The status line is cut to the width of the terminal, as wrapping past the last row would scroll the screen.
+  let population = universe.population().map_or(String::new(), |population| format!("  population {}", population));
+  let status = format!(
+    " generation {}{}  {} cells per dot{}  space pause  n step  b back  arrows pan  +/- zoom  g glyphs  q quit",
+    universe.generation, population, 1.0 / viewport.cell_size, if paused { "  paused" } else { "" },
+  );
+  screen += "\x1b[0m\x1b[7m";
+  screen.extend(status.chars().take(columns as usize));
+  screen += "\x1b[0m\x1b[K";
+  screen
+}
+
+// The character showing the given dots, numbered left to right and then top to bottom.
+fn glyph(glyphs: Glyphs, dots: u32) -> char {
+  match glyphs {
+    // Blank braille characters are drawn as spaces, which fewer bytes say.
+    Glyphs::Braille if dots == 0 => ' ',
+    Glyphs::Braille => {
+      let mut offset = 0;
+      for (dot_y, row) in BRAILLE_DOTS.iter().enumerate() {
+        for (dot_x, bit) in row.iter().enumerate() {
+          if (dots >> (dot_y * 2 + dot_x)) & 1 == 1 {
+            offset |= bit;
+          }
+        }
+      }
+      char::from_u32(BRAILLE_BLANK + offset).unwrap()
+    }
+    Glyphs::HalfBlocks => [' ', '▀', '▄', '█'][dots as usize],
+  }
+}
+
+// Reads keys on a thread of their own, so that the universe keeps running while waiting for them.
+fn read_keys() -> mpsc::Receiver<Key> {
+  let (sender, receiver) = mpsc::channel();
+  std::thread::spawn(move || {
+    let mut bytes = std::io::stdin().lock().bytes().map_while(Result::ok);
+    while let Some(byte) = bytes.next() {
+      // Arrow keys arrive as an escape followed by "[" and a letter.
+      let key = match byte {
+        0x1B if bytes.next() == Some(b'[') => match bytes.next() {
+          Some(b'A') => Key::Up,
+          Some(b'B') => Key::Down,
+          Some(b'C') => Key::Right,
+          Some(b'D') => Key::Left,
+          _ => continue,
+        },
+        _ => Key::Character(byte),
+      };
+      if sender.send(key).is_err() {
+        return;
+      }
+    }
+  });
+  receiver
+}
+
+// The number of columns and rows of the terminal.
+fn terminal_size() -> Result<(u32, u32), String> {
+  let size = stty(&["size"])?;
+  match size.split_whitespace().map(|number| number.parse::<u32>()).collect::<Vec<_>>()[..] {
+    [Ok(rows), Ok(columns)] => Ok((columns.max(1), rows.max(2))),
+    _ => Err(format!("Could not read the size of the terminal from \"{}\"", size.trim())),
+  }
+}
+
+// Runs stty on the terminal the program was started from, returning what it prints.
+fn stty(arguments: &[&str]) -> Result<String, String> {
+  let output = Command::new("stty").args(arguments).stdin(Stdio::inherit()).stderr(Stdio::inherit()).output()
+      .map_err(|error| format!("Could not run stty: {}", error))?;
+  if !output.status.success() {
+    return Err("The terminal front end needs to be run in a terminal".to_string());
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::universe::Buffers;
+
+  #[test]
+  fn braille_dots_follow_the_unicode_numbering() {
+    // Dots are numbered down the left column, then down the right one, with the bottom row added last.
+    let expected = [0x2801, 0x2808, 0x2802, 0x2810, 0x2804, 0x2820, 0x2840, 0x2880];
+    for (dot, expected) in expected.into_iter().enumerate() {
+      assert_eq!(glyph(Glyphs::Braille, 1 << dot) as u32, expected, "dot {}", dot);
+    }
+    assert_eq!(glyph(Glyphs::Braille, 0xFF), '\u{28FF}');
+    assert_eq!(glyph(Glyphs::Braille, 0), ' ');
+    assert_eq!((0..4).map(|dots| glyph(Glyphs::HalfBlocks, dots)).collect::<String>(), " ▀▄█");
+  }
+
+  // The characters of the screen, without the escape sequences that color them, a line for each row.
+  fn characters(screen: &str) -> Vec<String> {
+    let mut text = String::new();
+    let mut characters = screen.chars();
+    while let Some(character) = characters.next() {
+      if character == '\x1b' {
+        characters.by_ref().find(|character| character.is_ascii_alphabetic());
+      } else if character != '\r' {
+        text.push(character);
+      }
+    }
+    text.lines().map(|line| line.to_string()).collect()
+  }
+
+  #[test]
+  fn draws_a_dot_for_every_cell() {
+    let mut universe = Universe::new(Buffers::for_rule(None), None);
+    for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+      universe.set_cell(x, y, 1);
+    }
+    let colors = render::colors_for_rule(None);
+    let mut viewport = Viewport::new(8, 8, false);
+    (viewport.x, viewport.y, viewport.cell_size) = (0.0, 0.0, 1.0);
+
+    let braille = characters(&screen(&universe, &viewport, &colors, Glyphs::Braille, false, 80));
+    assert_eq!(braille[..2], ["\u{282C}\u{2806}  ", "    "]);
+    assert!(braille[2].starts_with(" generation 0  population 5  1 cells per dot  space pause"));
+
+    viewport.height = 6;
+    let half_blocks = characters(&screen(&universe, &viewport, &colors, Glyphs::HalfBlocks, true, 20));
+    assert_eq!(half_blocks[..3], [" ▀▄     ", "▀▀▀     ", "        "]);
+    // The status line is cut to the width of the terminal.
+    assert_eq!(half_blocks[3], " generation 0  popul");
+  }
+}
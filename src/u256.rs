@@ -0,0 +1,200 @@
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, ShlAssign, Shr, ShrAssign};
+
+// An unsigned 256-bit integer, held as four 64-bit words with the lowest first, for blocks of 16 x 16 cells. It has as
+// much of the interface of the built-in integers as blocks of cells use, so the rest of the program can use it in
+// their place, apart from integer literals. Like theirs, shifts by the number of bits or more overflow.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+  pub const BITS: u32 = 256;
+  // Only the tests and benchmarks fill whole blocks.
+  #[allow(dead_code)]
+  pub const MAX: U256 = U256([u64::MAX; 4]);
+
+  pub const fn from_le_bytes(bytes: [u8; 32]) -> Self {
+    let mut words = [0; 4];
+    let mut index = 0;
+    while index < 4 {
+      let word = [bytes[index * 8], bytes[index * 8 + 1], bytes[index * 8 + 2], bytes[index * 8 + 3], bytes[index * 8 + 4], bytes[index * 8 + 5], bytes[index * 8 + 6], bytes[index * 8 + 7]];
+      words[index] = u64::from_le_bytes(word);
+      index += 1;
+    }
+    U256(words)
+  }
+
+  pub fn to_le_bytes(self) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    for (chunk, word) in bytes.chunks_exact_mut(8).zip(self.0) {
+      chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+  }
+
+  pub fn count_ones(self) -> u32 {
+    self.0.iter().map(|word| word.count_ones()).sum()
+  }
+
+  pub fn trailing_zeros(self) -> u32 {
+    match self.0.iter().position(|&word| word != 0) {
+      Some(index) => index as u32 * u64::BITS + self.0[index].trailing_zeros(),
+      None => U256::BITS,
+    }
+  }
+
+  pub fn leading_zeros(self) -> u32 {
+    match self.0.iter().rposition(|&word| word != 0) {
+      Some(index) => (3 - index as u32) * u64::BITS + self.0[index].leading_zeros(),
+      None => U256::BITS,
+    }
+  }
+
+  pub fn reverse_bits(self) -> Self {
+    let [a, b, c, d] = self.0;
+    U256([d.reverse_bits(), c.reverse_bits(), b.reverse_bits(), a.reverse_bits()])
+  }
+
+  // Words move up by whole words, and then by the rest of the bits with the top bits of the word below carried in.
+  #[inline(always)]
+  fn shifted_left(self, bits: u32) -> Self {
+    assert!(bits < U256::BITS, "attempt to shift left with overflow");
+    let (words, bits) = ((bits / u64::BITS) as usize, bits % u64::BITS);
+    let word = |index: usize, words: usize| index.checked_sub(words).map_or(0, |index| self.0[index]);
+    U256(std::array::from_fn(|index| word(index, words) << bits | word(index, words + 1).checked_shr(u64::BITS - bits).unwrap_or(0)))
+  }
+
+  #[inline(always)]
+  fn shifted_right(self, bits: u32) -> Self {
+    assert!(bits < U256::BITS, "attempt to shift right with overflow");
+    let (words, bits) = ((bits / u64::BITS) as usize, bits % u64::BITS);
+    let word = |index: usize| self.0.get(index).copied().unwrap_or(0);
+    U256(std::array::from_fn(|index| word(index + words) >> bits | word(index + words + 1).checked_shl(u64::BITS - bits).unwrap_or(0)))
+  }
+}
+
+impl From<u64> for U256 {
+  fn from(value: u64) -> Self {
+    U256([value, 0, 0, 0])
+  }
+}
+
+impl From<bool> for U256 {
+  fn from(value: bool) -> Self {
+    U256::from(value as u64)
+  }
+}
+
+impl From<u8> for U256 {
+  fn from(value: u8) -> Self {
+    U256::from(value as u64)
+  }
+}
+
+// Lets blocks be masked with and compared to integer literals, as is done to pick out single cells.
+impl PartialEq<u64> for U256 {
+  fn eq(&self, other: &u64) -> bool {
+    *self == U256::from(*other)
+  }
+}
+
+impl BitAnd<u64> for U256 {
+  type Output = U256;
+
+  #[inline(always)]
+  fn bitand(self, other: u64) -> U256 {
+    self & U256::from(other)
+  }
+}
+
+impl Not for U256 {
+  type Output = U256;
+
+  fn not(self) -> U256 {
+    U256(self.0.map(|word| !word))
+  }
+}
+
+macro_rules! bitwise {
+  ($($trait:ident $method:ident $assign_trait:ident $assign_method:ident $operator:tt),*) => {$(
+    impl $trait for U256 {
+      type Output = U256;
+
+      #[inline(always)]
+      fn $method(self, other: U256) -> U256 {
+        U256([0, 1, 2, 3].map(|index| self.0[index] $operator other.0[index]))
+      }
+    }
+
+    impl $assign_trait for U256 {
+      #[inline(always)]
+      fn $assign_method(&mut self, other: U256) {
+        *self = *self $operator other;
+      }
+    }
+  )*};
+}
+
+bitwise!(BitAnd bitand BitAndAssign bitand_assign &, BitOr bitor BitOrAssign bitor_assign |, BitXor bitxor BitXorAssign bitxor_assign ^);
+
+// Shifts take any integer type, as the built-in integers' do.
+macro_rules! shifts {
+  ($($bits:ty),*) => {$(
+    impl Shl<$bits> for U256 {
+      type Output = U256;
+
+      #[inline(always)]
+      fn shl(self, bits: $bits) -> U256 {
+        self.shifted_left(u32::try_from(bits).unwrap_or(u32::MAX))
+      }
+    }
+
+    impl Shr<$bits> for U256 {
+      type Output = U256;
+
+      #[inline(always)]
+      fn shr(self, bits: $bits) -> U256 {
+        self.shifted_right(u32::try_from(bits).unwrap_or(u32::MAX))
+      }
+    }
+
+    impl ShlAssign<$bits> for U256 {
+      #[inline(always)]
+      fn shl_assign(&mut self, bits: $bits) {
+        *self = *self << bits;
+      }
+    }
+
+    impl ShrAssign<$bits> for U256 {
+      #[inline(always)]
+      fn shr_assign(&mut self, bits: $bits) {
+        *self = *self >> bits;
+      }
+    }
+  )*};
+}
+
+shifts!(u32, u64, usize, i32, i64);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shifts_carry_across_words() {
+    let one = U256::from(1u8);
+    for bits in 0..U256::BITS {
+      let shifted = one << bits;
+      assert_eq!(shifted.count_ones(), 1);
+      assert_eq!(shifted.trailing_zeros(), bits);
+      assert_eq!(shifted.leading_zeros(), U256::BITS - 1 - bits);
+      assert_eq!(shifted >> bits, one);
+      assert_eq!(shifted.reverse_bits(), one << (U256::BITS - 1 - bits));
+    }
+    let pattern = U256([0x0123456789abcdef, 0xfedcba9876543210, u64::MAX, 1]);
+    assert_eq!(pattern << 68 >> 68, pattern & (U256::MAX >> 68));
+    assert_eq!(pattern >> 4 << 4, pattern & !U256::from(0xfu8));
+    assert_eq!(U256([0, 1, 0, 0]) >> 1, U256::from(1u64 << 63));
+    assert_eq!(U256::from_le_bytes(pattern.to_le_bytes()), pattern);
+    assert_eq!(U256::default().trailing_zeros(), U256::BITS);
+  }
+}
@@ -0,0 +1,338 @@
+use std::ops::Range;
+use crate::conway;
+use crate::conway::{CellBlock, Conway, Neighborhood, CELLS_PER_BLOCK, CELL_BLOCK_HEIGHT, CELL_BLOCK_WIDTH, EMPTY_BLOCK, FIRST_CELL};
+use crate::dense;
+use crate::dense::DenseBoard;
+use crate::generations;
+use crate::generations::GenerationsBoard;
+use crate::multi_state;
+use crate::multi_state::MultiStateBoard;
+use crate::rule::Rule;
+use crate::sparse;
+use crate::sparse::{SparseBoard, TileCoordinate};
+
+// Double buffers for whichever kind of board the universe is simulated on. Each generation is computed from the first
+// buffer into the second, after which the two are swapped.
+pub enum Buffers {
+  // The dense board keeps both of its buffers to itself, whichever way it lays them out.
+  Dense(Box<dyn DenseBoard>),
+  Sparse(SparseBoard, SparseBoard),
+  Generations(GenerationsBoard, GenerationsBoard),
+  MultiState(MultiStateBoard, MultiStateBoard),
+}
+
+impl Buffers {
+  // Buffers that grow with the pattern, of whichever kind the rule runs on.
+  pub fn for_rule(rule: Option<&Rule>) -> Self {
+    match rule {
+      Some(Rule::MultiState(rule)) => Buffers::MultiState(MultiStateBoard::new(rule.states), MultiStateBoard::new(rule.states)),
+      Some(rule) if rule.states() > 2 => Buffers::Generations(GenerationsBoard::new(rule.states()), GenerationsBoard::new(rule.states())),
+      _ => Buffers::Sparse(SparseBoard::new(), SparseBoard::new()),
+    }
+  }
+}
+
+// A pixel zoomed far out covers thousands of blocks of the dense board, too many to look through for every pixel of
+// every frame, so only this many columns and rows of them, spread evenly across the pixel, are looked at.
+const DENSE_SAMPLES: i64 = 4;
+
+// Block coordinates are cell coordinates divided by the block dimensions.
+pub type BlockCoordinate = (i64, i64);
+
+// A block of one of the bit-planes a board stores the states of its cells in, as (plane, coordinate, cells).
+pub type PlaneBlock = (usize, BlockCoordinate, CellBlock);
+
+pub struct Universe {
+  pub buffers: Buffers,
+  // None runs Conway's Game of Life using the built-in tables.
+  pub rule: Option<Rule>,
+  pub generation: u64,
+}
+
+impl Universe {
+  pub fn new(buffers: Buffers, rule: Option<Rule>) -> Self {
+    Universe { buffers, rule, generation: 0 }
+  }
+
+  pub fn step(&mut self) {
+    match (&mut self.buffers, &self.rule) {
+      (Buffers::Dense(board), None) => board.step(&Conway),
+      (Buffers::Dense(board), Some(Rule::Table(rule))) => board.step(rule),
+      (Buffers::Dense(board), Some(Rule::Margolus(rule))) => board.step(&rule.step(self.generation)),
+      (Buffers::Dense(..), Some(_)) => panic!("Dense boards only support rules with a transition table"),
+      (Buffers::MultiState(board, next_board), Some(Rule::MultiState(rule))) => {
+        multi_state::compute_next_board_state(board, next_board, rule);
+        std::mem::swap(board, next_board);
+      }
+      (_, Some(Rule::MultiState(_))) | (Buffers::MultiState(..), _) => panic!("Rule tables only run on multi-state boards"),
+      (Buffers::Sparse(board, next_board), rule) => {
+        match rule {
+          None => sparse::compute_next_board_state(board, next_board),
+          Some(Rule::Table(rule)) => sparse::compute_next_board_state_with_rule(board, next_board, rule),
+          Some(Rule::LargerThanLife(rule)) => sparse::compute_next_board_state_with_rule(board, next_board, rule),
+          Some(Rule::Margolus(rule)) => sparse::compute_next_board_state_with_rule(board, next_board, &rule.step(self.generation)),
+          Some(Rule::MultiState(_)) => unreachable!(),
+        }
+        std::mem::swap(board, next_board);
+      }
+      (Buffers::Generations(board, next_board), rule) => {
+        match rule {
+          None => generations::compute_next_board_state(board, next_board, &Conway),
+          Some(Rule::Table(rule)) => generations::compute_next_board_state(board, next_board, rule),
+          Some(Rule::LargerThanLife(rule)) => generations::compute_next_board_state(board, next_board, rule),
+          Some(Rule::MultiState(_) | Rule::Margolus(_)) => unreachable!(),
+        }
+        std::mem::swap(board, next_board);
+      }
+    }
+    self.generation += 1;
+  }
+
+  pub fn get_cell(&self, x: i64, y: i64) -> u8 {
+    match &self.buffers {
+      Buffers::Dense(..) | Buffers::Sparse(..) => {
+        let (block_x, block_y, cell) = locate_cell(x, y);
+        u8::from((self.block(0, block_x, block_y) >> cell) & 1 == 1)
+      }
+      Buffers::Generations(board, _) => board.get_cell(x, y),
+      Buffers::MultiState(board, _) => board.get_cell(x, y),
+    }
+  }
+
+  // Cells outside of the dense board stay dead.
+  pub fn set_cell(&mut self, x: i64, y: i64, state: u8) {
+    match &mut self.buffers {
+      Buffers::Dense(board) => {
+        let (block_x, block_y, cell) = locate_cell(x, y);
+        if let Some((block_x, block_y)) = dense_block_coordinate(block_x, block_y) {
+          let flipped = (board.block(block_x, block_y) >> cell & FIRST_CELL) ^ CellBlock::from(state.min(1));
+          board.xor_block(block_x, block_y, flipped << cell);
+        }
+      }
+      Buffers::Sparse(board, _) => board.set_cell(x, y, state == 1),
+      Buffers::Generations(board, _) => board.set_cell(x, y, state),
+      Buffers::MultiState(board, _) => board.set_cell(x, y, state),
+    }
+  }
+
+  pub fn states(&self) -> u8 {
+    self.rule.as_ref().map_or(2, |rule| rule.states())
+  }
+
+  // Converts a block of each of the board's planes into the states of the block's cells.
+  pub fn states_from_planes(&self, blocks: &[CellBlock]) -> [u8; CELLS_PER_BLOCK as usize] {
+    match &self.buffers {
+      Buffers::Dense(..) | Buffers::Sparse(..) => std::array::from_fn(|cell| u8::from((blocks[0] >> cell) & 1 == 1)),
+      Buffers::Generations(..) => generations::states_from_planes(blocks),
+      Buffers::MultiState(..) => multi_state::states_from_planes(blocks),
+    }
+  }
+
+  // Converts the states of a block's cells into a block of each of the board's planes. States the board does not have
+  // become its last state.
+  pub fn planes_from_states(&self, states: &[u8; CELLS_PER_BLOCK as usize]) -> Vec<CellBlock> {
+    let last_state = self.states() - 1;
+    let states = states.map(|state| state.min(last_state));
+    match &self.buffers {
+      Buffers::Dense(..) | Buffers::Sparse(..) => {
+        vec![states.iter().enumerate().fold(EMPTY_BLOCK, |block, (cell, &state)| block | (CellBlock::from(state) << cell))]
+      }
+      Buffers::Generations(..) => generations::planes_from_states(&states, self.plane_count()),
+      Buffers::MultiState(..) => multi_state::planes_from_states(&states, self.plane_count()),
+    }
+  }
+
+  // How many bit-planes the board stores the states of its cells in. The dense and sparse boards only have one.
+  pub fn plane_count(&self) -> usize {
+    self.sparse_planes().map_or(1, |planes| planes.len())
+  }
+
+  pub fn block(&self, plane: usize, block_x: i64, block_y: i64) -> CellBlock {
+    match (&self.buffers, self.sparse_planes()) {
+      (Buffers::Dense(board), _) => dense_block_coordinate(block_x, block_y).map_or(EMPTY_BLOCK, |(block_x, block_y)| board.block(block_x, block_y)),
+      (_, Some(planes)) => planes[plane].block(block_x, block_y),
+      (_, None) => unreachable!(),
+    }
+  }
+
+  // Flips the cells of a block of one of the board's planes that are set in the given cells.
+  pub fn xor_block(&mut self, plane: usize, block_x: i64, block_y: i64, cells: CellBlock) {
+    match &mut self.buffers {
+      Buffers::Dense(board) => {
+        if let Some((block_x, block_y)) = dense_block_coordinate(block_x, block_y) {
+          board.xor_block(block_x, block_y, cells);
+        }
+      }
+      Buffers::Sparse(board, _) => board.xor_block(block_x, block_y, cells),
+      Buffers::Generations(board, _) => board.planes_mut()[plane].xor_block(block_x, block_y, cells),
+      Buffers::MultiState(board, _) => board.planes_mut()[plane].xor_block(block_x, block_y, cells),
+    }
+  }
+
+  // Every non-empty block of every plane of the board.
+  pub fn occupied_blocks(&self) -> Vec<PlaneBlock> {
+    match (&self.buffers, self.sparse_planes()) {
+      (Buffers::Dense(board), _) => board.occupied_blocks(),
+      (_, Some(planes)) => planes_blocks(planes, |plane, _| plane.occupied_blocks()),
+      (_, None) => unreachable!(),
+    }
+  }
+
+  // Every block that changed in the last step, as the cells that flipped. Only valid right after stepping, while the
+  // second buffer still holds the previous generation.
+  pub fn changed_blocks(&self) -> Vec<PlaneBlock> {
+    let previous_planes = match &self.buffers {
+      Buffers::Dense(board) => return board.changed_blocks(),
+      Buffers::Sparse(_, previous_board) => std::slice::from_ref(previous_board),
+      Buffers::Generations(_, previous_board) => previous_board.planes(),
+      Buffers::MultiState(_, previous_board) => previous_board.planes(),
+    };
+    planes_blocks(self.sparse_planes().unwrap(), |plane, index| plane.changed_blocks(&previous_planes[index]))
+  }
+
+  pub fn clear(&mut self) {
+    match &mut self.buffers {
+      Buffers::Dense(board) => board.clear(),
+      Buffers::Sparse(board, _) => board.clear(),
+      Buffers::Generations(board, _) => board.clear(),
+      Buffers::MultiState(board, _) => board.clear(),
+    }
+  }
+
+  // The coordinates of every tile holding any cell that is not in state 0, in no particular order. The dense board is
+  // split into tiles of the same size as the sparse boards use.
+  pub fn occupied_tiles(&self) -> Vec<TileCoordinate> {
+    match (&self.buffers, self.sparse_planes()) {
+      (Buffers::Dense(board), _) => board.occupied_tiles(),
+      (_, Some(planes)) => {
+        let mut tiles: Vec<TileCoordinate> = planes.iter()
+            .flat_map(|plane| plane.tiles().filter(|(_, tile)| !sparse::is_tile_empty(tile)).map(|(&coordinate, _)| coordinate))
+            .collect();
+        tiles.sort_unstable();
+        tiles.dedup();
+        tiles
+      }
+      (_, None) => unreachable!(),
+    }
+  }
+
+  // Whether the tile at the given tile coordinates holds any cell that is not in state 0.
+  pub fn is_tile_occupied(&self, tile_coordinate: TileCoordinate) -> bool {
+    match (&self.buffers, self.sparse_planes()) {
+      (Buffers::Dense(board), _) => {
+        let (tile_x, tile_y) = tile_coordinate;
+        (0..dense::WIDTH_TILES as i64).contains(&tile_x) && (0..dense::HEIGHT_TILES as i64).contains(&tile_y)
+            && board.is_tile_occupied(tile_x as usize, tile_y as usize)
+      }
+      (_, Some(planes)) => planes.iter().any(|plane| plane.tile(tile_coordinate).is_some_and(|tile| !sparse::is_tile_empty(tile))),
+      (_, None) => unreachable!(),
+    }
+  }
+
+  // Any block holding a cell that is not in state 0 within the given ranges of block columns and rows. Only the blocks
+  // the board has are looked through, so that ranges reaching far past the dense board or the sparse boards' tiles
+  // are quick. The dense board only has a sample of its blocks looked at, which may miss a lone occupied one.
+  pub fn occupied_block_in(&self, columns: Range<i64>, rows: Range<i64>) -> Option<BlockCoordinate> {
+    let Some(planes) = self.sparse_planes() else {
+      let columns = dense_samples(columns.start.max(0)..columns.end.min(conway::BOARD_WIDTH_BLOCKS as i64));
+      let rows = dense_samples(rows.start.max(0)..rows.end.min(conway::BOARD_HEIGHT_BLOCKS as i64));
+      return rows.flat_map(|block_y| columns.clone().map(move |block_x| (block_x, block_y)))
+          .find(|&(block_x, block_y)| self.block(0, block_x, block_y) != EMPTY_BLOCK);
+    };
+    planes.iter().find_map(|plane| plane.occupied_block_in(columns.clone(), rows.clone()))
+  }
+
+  // The planes of every board other than the dense one, which are all sparse boards.
+  fn sparse_planes(&self) -> Option<&[SparseBoard]> {
+    match &self.buffers {
+      Buffers::Dense(..) => None,
+      Buffers::Sparse(board, _) => Some(std::slice::from_ref(board)),
+      Buffers::Generations(board, _) => Some(board.planes()),
+      Buffers::MultiState(board, _) => Some(board.planes()),
+    }
+  }
+
+  // Returns the state of every cell of the block at the given block coordinates, in the same order as a block's bits.
+  // The dense board is finite, and everything outside of it is dead.
+  pub fn block_states(&self, block_x: i64, block_y: i64) -> [u8; CELLS_PER_BLOCK as usize] {
+    let block_states = |block: CellBlock| std::array::from_fn(|cell| u8::from((block >> cell) & 1 == 1));
+    match &self.buffers {
+      Buffers::Dense(board) => {
+        block_states(dense_block_coordinate(block_x, block_y).map_or(EMPTY_BLOCK, |(block_x, block_y)| board.block(block_x, block_y)))
+      }
+      Buffers::Sparse(board, _) => block_states(board.block(block_x, block_y)),
+      Buffers::Generations(board, _) => board.block_states(block_x, block_y),
+      Buffers::MultiState(board, _) => board.block_states(block_x, block_y),
+    }
+  }
+
+  pub fn neighborhood(&self) -> Neighborhood {
+    self.rule.as_ref().map_or(Neighborhood::Moore, |rule| rule.neighborhood())
+  }
+
+  // Counting the dense board means scanning several gigabytes, so it is not reported.
+  pub fn population(&self) -> Option<u64> {
+    match &self.buffers {
+      Buffers::Dense(..) => None,
+      Buffers::Sparse(board, _) => Some(board.population()),
+      Buffers::Generations(board, _) => Some(board.population()),
+      Buffers::MultiState(board, _) => Some(board.population()),
+    }
+  }
+
+  // The population even of the dense board, for the odd caller that is willing to wait for it to be counted.
+  pub fn counted_population(&self) -> u64 {
+    self.population().unwrap_or_else(|| self.occupied_blocks().iter().map(|&(_, _, cells)| cells.count_ones() as u64).sum())
+  }
+
+  pub fn tile_count(&self) -> Option<usize> {
+    match &self.buffers {
+      Buffers::Dense(..) => None,
+      Buffers::Sparse(board, _) => Some(board.tile_count()),
+      Buffers::Generations(board, _) => Some(board.tile_count()),
+      Buffers::MultiState(board, _) => Some(board.tile_count()),
+    }
+  }
+}
+
+// Splits cell coordinates into the coordinates of their block and the index of the cell within that block.
+fn locate_cell(x: i64, y: i64) -> (i64, i64, u64) {
+  let cell = y.rem_euclid(CELL_BLOCK_HEIGHT as i64) as u64 * CELL_BLOCK_WIDTH + x.rem_euclid(CELL_BLOCK_WIDTH as i64) as u64;
+  (x.div_euclid(CELL_BLOCK_WIDTH as i64), y.div_euclid(CELL_BLOCK_HEIGHT as i64), cell)
+}
+
+fn dense_block_coordinate(block_x: i64, block_y: i64) -> Option<(usize, usize)> {
+  let inside = (0..conway::BOARD_WIDTH_BLOCKS as i64).contains(&block_x)
+      && (0..conway::BOARD_HEIGHT_BLOCKS as i64).contains(&block_y);
+  inside.then_some((block_x as usize, block_y as usize))
+}
+
+// Up to DENSE_SAMPLES values spread evenly across the range, starting with its first.
+fn dense_samples(range: Range<i64>) -> impl Iterator<Item = i64> + Clone {
+  let (start, length) = (range.start, (range.end - range.start).max(0));
+  let samples = length.min(DENSE_SAMPLES);
+  (0..samples).map(move |sample| start + sample * length / samples)
+}
+
+fn planes_blocks<F: Fn(&SparseBoard, usize) -> Vec<(BlockCoordinate, CellBlock)>>(planes: &[SparseBoard], blocks_for_plane: F) -> Vec<PlaneBlock> {
+  planes.iter()
+      .enumerate()
+      .flat_map(|(index, plane)| blocks_for_plane(plane, index).into_iter().map(move |(coordinate, cells)| (index, coordinate, cells)))
+      .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dense_samples_spread_across_the_range() {
+    assert_eq!(dense_samples(5..8).collect::<Vec<_>>(), [5, 6, 7]);
+    assert_eq!(dense_samples(0..1024).collect::<Vec<_>>(), [0, 256, 512, 768]);
+    assert_eq!(dense_samples(-3..3).collect::<Vec<_>>(), [-3, -2, 0, 1]);
+    assert_eq!(dense_samples(4..4).count(), 0);
+    // Pixels past the edge of the board end up with ranges that end before they start once clamped to it.
+    let (past_the_edge, edge) = (9, 2);
+    assert_eq!(dense_samples(past_the_edge..edge).count(), 0);
+  }
+}
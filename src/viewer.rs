@@ -0,0 +1,203 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use crate::json::Json;
+use crate::png;
+use crate::remote;
+use crate::render;
+use crate::render::{Colors, Viewport};
+use crate::selection;
+use crate::universe::{Buffers, Universe};
+
+// Tiles are square, and this many pixels across as slippy maps expect.
+const TILE_PIXELS: u32 = 256;
+// At zoom level z cells are drawn 2^(z - ONE_PIXEL_ZOOM) pixels across, which runs from 256 cells to a pixel at zoom 0
+// to 128 pixels to a cell at MAX_ZOOM, as far as the window zooms either way.
+const ONE_PIXEL_ZOOM: i32 = 8;
+const MAX_ZOOM: i32 = 15;
+// How often the speed reported in the stats is measured.
+const SPEED_INTERVAL: Duration = Duration::from_secs(1);
+
+const PAGE: &str = include_str!("viewer.html");
+
+// Something a browser asked for that needs the board, which only the loop stepping it can look at.
+enum Resource {
+  // A tile by the cell at its top-left corner.
+  Tile { zoom: i32, x: i64, y: i64 },
+  Stats,
+}
+
+struct Request {
+  resource: Resource,
+  reply: mpsc::Sender<Response>,
+}
+
+struct Response {
+  status: &'static str,
+  content_type: &'static str,
+  body: Vec<u8>,
+}
+
+impl Response {
+  fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+    Response { status: "200 OK", content_type, body }
+  }
+
+  fn error(status: &'static str) -> Self {
+    Response { status, content_type: "text/plain; charset=utf-8", body: status.as_bytes().to_vec() }
+  }
+}
+
+// Runs the universe without a window, serving a page that shows it to browsers. The page is a slippy map of PNG tiles
+// rendered from the board at whatever zoom level and position the browser asks for, and polls /stats to redraw them
+// as the universe moves on. Requests are answered between generations, so that tiles all show the same one. The
+// universe stops after the given number of generations, if any, and is served as it ended up.
+pub fn run(universe: &mut Universe, address: &str, generations: Option<u64>, colors: &Colors, hexagonal: bool, rule_name: &str) -> Result<(), String> {
+  let listener = TcpListener::bind(address).map_err(|error| format!("Failed to listen on {}: {}", address, error))?;
+  let local_address = listener.local_addr().map_err(|error| error.to_string())?;
+  println!("Serving the board at http://{}/", local_address);
+  let (sender, requests) = mpsc::channel();
+  std::thread::spawn(move || accept(listener, sender));
+
+  let mut measured = (Instant::now(), universe.generation);
+  let mut generations_per_second = 0.0;
+  loop {
+    let stepping = generations.is_none_or(|generations| universe.generation < generations);
+    if stepping {
+      universe.step();
+    }
+    if measured.0.elapsed() >= SPEED_INTERVAL {
+      generations_per_second = (universe.generation - measured.1) as f64 / measured.0.elapsed().as_secs_f64();
+      measured = (Instant::now(), universe.generation);
+    }
+
+    // Once the universe has stopped, there is nothing to do until a browser asks for something.
+    let waiting = if stepping { None } else { requests.recv().ok() };
+    for request in waiting.into_iter().chain(requests.try_iter()) {
+      let response = match request.resource {
+        Resource::Tile { zoom, x, y } => Response::ok("image/png", render_tile(universe, colors, hexagonal, zoom, x, y)),
+        Resource::Stats => {
+          let stats = stats(universe, rule_name, hexagonal, stepping, generations_per_second);
+          Response::ok("application/json", stats.to_string().into_bytes())
+        }
+      };
+      // The browser may have given up on the request in the meantime.
+      request.reply.send(response).ok();
+    }
+  }
+}
+
+fn accept(listener: TcpListener, requests: mpsc::Sender<Request>) {
+  for stream in listener.incoming().flatten() {
+    let requests = requests.clone();
+    std::thread::spawn(move || serve(stream, requests));
+  }
+}
+
+// Answers a single request and closes the connection. Only the request line matters, so the headers are skipped.
+fn serve(mut stream: TcpStream, requests: mpsc::Sender<Request>) {
+  let mut reader = BufReader::new(&stream);
+  let mut request_line = String::new();
+  if reader.read_line(&mut request_line).is_err() {
+    return;
+  }
+  let mut header = String::new();
+  while reader.read_line(&mut header).is_ok_and(|length| length > 0) && !header.trim().is_empty() {
+    header.clear();
+  }
+
+  let mut words = request_line.split_whitespace();
+  let response = match (words.next(), words.next().and_then(|target| target.split('?').next())) {
+    (Some("GET"), Some("/")) => Response::ok("text/html; charset=utf-8", PAGE.as_bytes().to_vec()),
+    (Some("GET"), Some(path)) => match resource(path) {
+      Some(resource) => {
+        let (reply, response) = mpsc::channel();
+        requests.send(Request { resource, reply }).ok();
+        response.recv().unwrap_or_else(|_| Response::error("503 Service Unavailable"))
+      }
+      None => Response::error("404 Not Found"),
+    },
+    _ => Response::error("405 Method Not Allowed"),
+  };
+  let head = format!(
+    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+    response.status, response.content_type, response.body.len(),
+  );
+  stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(&response.body)).ok();
+}
+
+// Tiles are at /tiles/zoom/x/y.png, where x and y count tiles from the one whose top-left corner is the origin. Tiles
+// reaching past the coordinates cells can have are not found.
+fn resource(path: &str) -> Option<Resource> {
+  if path == "/stats" {
+    return Some(Resource::Stats);
+  }
+  let tile = path.strip_prefix("/tiles/")?.strip_suffix(".png")?;
+  match tile.split('/').collect::<Vec<_>>()[..] {
+    [zoom, x, y] => {
+      let zoom = zoom.parse().ok().filter(|zoom| (0..=MAX_ZOOM).contains(zoom))?;
+      Some(Resource::Tile { zoom, x: tile_cell(zoom, x.parse().ok()?)?, y: tile_cell(zoom, y.parse().ok()?)? })
+    }
+    _ => None,
+  }
+}
+
+// The cell at the top-left corner of a tile, as long as the tile ends before the coordinates do. Tiles are a whole
+// number of cells across even at MAX_ZOOM.
+fn tile_cell(zoom: i32, tile: i64) -> Option<i64> {
+  let cells_across = (TILE_PIXELS as i64) << ONE_PIXEL_ZOOM >> zoom;
+  tile.checked_add(1)?.checked_mul(cells_across)?;
+  tile.checked_mul(cells_across)
+}
+
+fn render_tile(universe: &Universe, colors: &Colors, hexagonal: bool, zoom: i32, x: i64, y: i64) -> Vec<u8> {
+  let viewport = Viewport {
+    x: x as f64,
+    y: y as f64,
+    cell_size: 2f64.powi(zoom - ONE_PIXEL_ZOOM),
+    width: TILE_PIXELS,
+    height: TILE_PIXELS,
+    hexagonal,
+  };
+  let mut pixels = vec![0u32; (TILE_PIXELS * TILE_PIXELS) as usize];
+  render::render(universe, &viewport, colors, &mut pixels);
+  png::encode(TILE_PIXELS, TILE_PIXELS, &pixels)
+}
+
+// The page starts out looking at the bounds of the pattern. Like the population, they are left out on the dense board,
+// where finding them means scanning all of it.
+fn stats(universe: &Universe, rule_name: &str, hexagonal: bool, stepping: bool, generations_per_second: f64) -> Json {
+  let dense = matches!(universe.buffers, Buffers::Dense(..));
+  let bounds = (!dense).then(|| selection::bounding_rectangle(universe)).flatten();
+  Json::object([
+    ("generation", universe.generation.into()),
+    ("rule", rule_name.into()),
+    ("population", universe.population().into()),
+    ("tiles", universe.tile_count().map(|tiles| tiles as u64).into()),
+    ("bounds", bounds.as_ref().map_or(Json::Null, remote::rectangle_json)),
+    ("stepping", stepping.into()),
+    ("generations_per_second", Json::Number(generations_per_second)),
+    ("hexagonal", hexagonal.into()),
+    ("tile_pixels", u64::from(TILE_PIXELS).into()),
+    ("one_pixel_zoom", (ONE_PIXEL_ZOOM as i64).into()),
+    ("max_zoom", (MAX_ZOOM as i64).into()),
+  ])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tiles_stay_within_the_coordinates() {
+    assert!(matches!(resource("/tiles/8/-1/2.png"), Some(Resource::Tile { zoom: 8, x: -256, y: 512 })));
+    assert!(matches!(resource("/tiles/15/3/0.png"), Some(Resource::Tile { zoom: 15, x: 6, y: 0 })));
+    let last_tile = i64::MAX / ((TILE_PIXELS as i64) << ONE_PIXEL_ZOOM) - 1;
+    assert!(resource(&format!("/tiles/0/{}/{}.png", last_tile, -last_tile)).is_some());
+    assert!(resource(&format!("/tiles/0/{}/0.png", last_tile + 1)).is_none());
+    assert!(resource(&format!("/tiles/15/0/{}.png", i64::MAX)).is_none());
+    assert!(resource(&format!("/tiles/8/{}/0.png", i64::MIN)).is_none());
+    assert!(resource("/tiles/16/0/0.png").is_none());
+  }
+}